@@ -0,0 +1,81 @@
+//! Compares tessellating a large infinite document in full with tessellating
+//! only the strokes a 1920×1080 window shows at 100%.
+//!
+//! Run with `cargo bench --bench culling`.
+
+use std::time::{Duration, Instant};
+
+use glam::Vec2;
+use renderer_2d::camera::Camera;
+use renderer_2d::document::{Document, Layer, Stroke};
+use renderer_2d::engine::BlendMode;
+use renderer_2d::tools::ellipse::EllipseStroke;
+
+const STROKES: usize = 200_000;
+
+/// Side of the square, in canvas units, the strokes are scattered over.
+const SPREAD: f32 = 200.0;
+
+const RUNS: u32 = 5;
+
+/// Deterministic pseudo-random numbers in [0, 1), so runs are comparable.
+struct Random(u64);
+
+impl Random {
+    fn next(&mut self) -> f32 {
+        self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (self.0 >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
+
+fn document() -> Document {
+    let mut random = Random(1);
+    let strokes = (0..STROKES)
+        .map(|_| {
+            let center = (Vec2::new(random.next(), random.next()) - 0.5) * SPREAD;
+            Stroke::Ellipse(EllipseStroke {
+                center,
+                radii: Vec2::splat(0.02 + random.next() * 0.05),
+                filled: true,
+                thickness: 0.0,
+                color: [random.next(), random.next(), random.next(), 1.0],
+                blend_mode: BlendMode::Normal,
+            })
+        })
+        .collect();
+    let mut document = Document::from_layers(vec![Layer::new("Layer 1").with_strokes(strokes)]);
+    document.infinite = true;
+    document
+}
+
+/// Fastest of `RUNS` runs of `f`, with the result of the last one.
+fn time<T>(mut f: impl FnMut() -> T) -> (Duration, T) {
+    let mut best = Duration::MAX;
+    let mut result = None;
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        result = Some(f());
+        best = best.min(start.elapsed());
+    }
+    (best, result.unwrap())
+}
+
+fn main() {
+    let start = Instant::now();
+    let document = document();
+    println!("built and indexed {} strokes in {:?}", STROKES, start.elapsed());
+
+    let view = Camera::default().visible_area(Vec2::new(1920.0, 1080.0));
+
+    let (full_time, full) = time(|| document.tessellate());
+    let (query_time, found) = time(|| document.layer(0).strokes_in(view));
+    let (culled_time, culled) = time(|| document.tessellate_in(Some(view)));
+
+    println!("full:   {:>9} vertices in {:?}", full.vertices.len(), full_time);
+    println!("query:  {:>9} strokes  in {:?}", found.len(), query_time);
+    println!("culled: {:>9} vertices in {:?}", culled.vertices.len(), culled_time);
+    println!(
+        "culling is {:.0}× faster",
+        full_time.as_secs_f64() / culled_time.as_secs_f64()
+    );
+}
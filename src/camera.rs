@@ -0,0 +1,160 @@
+use glam::{Mat4, Vec2};
+
+use crate::document::PIXELS_PER_UNIT;
+
+/// Smallest and largest zoom factors the view allows.
+pub const MIN_ZOOM: f32 = 0.05;
+pub const MAX_ZOOM: f32 = 32.0;
+
+/// Which part of the canvas is on screen. Screen positions are in clip
+/// space (-1..1 across the window) and `viewport` is the window size in
+/// pixels, which keeps canvas units square whatever the window's shape.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Camera {
+    /// Canvas point shown in the middle of the window.
+    pub center: Vec2,
+    /// Window pixels per document pixel; 1 is 100%.
+    pub zoom: f32,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self {
+            center: Vec2::ZERO,
+            zoom: 1.0,
+        }
+    }
+}
+
+impl Camera {
+    /// Maps canvas coordinates to clip space.
+    pub fn view_matrix(&self, viewport: Vec2) -> Mat4 {
+        let scale = self.zoom * PIXELS_PER_UNIT * 2.0 / viewport.max(Vec2::ONE);
+        Mat4::from_scale(scale.extend(1.0)) * Mat4::from_translation(-self.center.extend(0.0))
+    }
+
+    /// The canvas point under `screen`.
+    pub fn screen_to_canvas(&self, screen: Vec2, viewport: Vec2) -> Vec2 {
+        self.view_matrix(viewport).inverse().transform_point3(screen.extend(0.0)).truncate()
+    }
+
+    /// Where `point` on the canvas appears on screen.
+    pub fn canvas_to_screen(&self, point: Vec2, viewport: Vec2) -> Vec2 {
+        self.view_matrix(viewport).transform_point3(point.extend(0.0)).truncate()
+    }
+
+    /// Minimum and maximum corner of the canvas area the window shows.
+    pub fn visible_area(&self, viewport: Vec2) -> (Vec2, Vec2) {
+        let a = self.screen_to_canvas(Vec2::NEG_ONE, viewport);
+        let b = self.screen_to_canvas(Vec2::ONE, viewport);
+        (a.min(b), a.max(b))
+    }
+
+    /// Scales the zoom by `factor`, keeping the canvas point under `screen`
+    /// in place.
+    pub fn zoom_at(&mut self, screen: Vec2, factor: f32, viewport: Vec2) {
+        let anchor = self.screen_to_canvas(screen, viewport);
+        self.zoom = (self.zoom * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+        self.center += anchor - self.screen_to_canvas(screen, viewport);
+    }
+
+    /// Sets the zoom, keeping the middle of the window in place.
+    pub fn set_zoom(&mut self, zoom: f32) {
+        self.zoom = zoom.clamp(MIN_ZOOM, MAX_ZOOM);
+    }
+
+    /// Moves the view so the canvas follows a drag of `delta` on screen.
+    pub fn pan(&mut self, delta: Vec2, viewport: Vec2) {
+        self.center += self.screen_to_canvas(Vec2::ZERO, viewport) - self.screen_to_canvas(delta, viewport);
+    }
+
+    /// Fits `bounds`, the minimum and maximum corner of a canvas area, into
+    /// `area`, a rectangle of window pixels given by its top-left and
+    /// bottom-right corner, and centers it there.
+    pub fn fit(&mut self, bounds: (Vec2, Vec2), viewport: Vec2, area: (Vec2, Vec2)) {
+        let size_pixels = (bounds.1 - bounds.0) * PIXELS_PER_UNIT;
+        let area_size = (area.1 - area.0).max(Vec2::ONE);
+        self.set_zoom((area_size / size_pixels.max(Vec2::ONE)).min_element());
+
+        // Shift the view so the middle of `bounds` lands in the middle of `area`.
+        let area_center = (area.0 + area.1) / 2.0 / viewport.max(Vec2::ONE);
+        let screen = Vec2::new(area_center.x * 2.0 - 1.0, 1.0 - area_center.y * 2.0);
+        let middle = (bounds.0 + bounds.1) / 2.0;
+        self.center = middle;
+        self.center += middle - self.screen_to_canvas(screen, viewport);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VIEWPORT: Vec2 = Vec2::new(800.0, 600.0);
+
+    fn assert_near(a: Vec2, b: Vec2) {
+        assert!(a.distance(b) < 1e-5, "{} != {}", a, b);
+    }
+
+    fn camera() -> Camera {
+        Camera {
+            center: Vec2::new(0.3, -0.2),
+            zoom: 2.0,
+        }
+    }
+
+    #[test]
+    fn screen_to_canvas_inverts_the_view_matrix() {
+        let camera = camera();
+        let view = camera.view_matrix(VIEWPORT);
+        for screen in [Vec2::ZERO, Vec2::new(0.5, -0.25), Vec2::new(-1.0, 1.0)] {
+            let canvas = camera.screen_to_canvas(screen, VIEWPORT);
+            assert_near(view.transform_point3(canvas.extend(0.0)).truncate(), screen);
+            assert_near(camera.canvas_to_screen(canvas, VIEWPORT), screen);
+        }
+        assert_near(camera.screen_to_canvas(Vec2::ZERO, VIEWPORT), camera.center);
+
+        // At 100%, one document pixel covers one window pixel.
+        let pixel = 1.0 / PIXELS_PER_UNIT;
+        let screen = Camera::default().canvas_to_screen(Vec2::new(pixel, pixel), VIEWPORT);
+        assert_near(screen, 2.0 / VIEWPORT);
+    }
+
+    #[test]
+    fn zoom_at_keeps_the_point_under_the_cursor() {
+        let mut camera = camera();
+        let cursor = Vec2::new(0.6, -0.4);
+        let under = camera.screen_to_canvas(cursor, VIEWPORT);
+        camera.zoom_at(cursor, 1.5, VIEWPORT);
+        assert_eq!(camera.zoom, 3.0);
+        assert_near(camera.screen_to_canvas(cursor, VIEWPORT), under);
+
+        camera.zoom_at(cursor, 1000.0, VIEWPORT);
+        assert_eq!(camera.zoom, MAX_ZOOM);
+        assert_near(camera.screen_to_canvas(cursor, VIEWPORT), under);
+        camera.zoom_at(cursor, 0.0, VIEWPORT);
+        assert_eq!(camera.zoom, MIN_ZOOM);
+    }
+
+    #[test]
+    fn pan_follows_the_drag() {
+        let mut camera = camera();
+        let from = Vec2::new(-0.2, 0.1);
+        let delta = Vec2::new(0.3, 0.4);
+        let grabbed = camera.screen_to_canvas(from, VIEWPORT);
+        camera.pan(delta, VIEWPORT);
+        assert_near(camera.canvas_to_screen(grabbed, VIEWPORT), from + delta);
+    }
+
+    #[test]
+    fn fit_centers_bounds_in_the_area() {
+        let mut camera = camera();
+        let bounds = (Vec2::new(-1.0, -0.5), Vec2::new(1.0, 0.5));
+        // The right three quarters of the window.
+        let area = (Vec2::new(200.0, 0.0), VIEWPORT);
+        camera.fit(bounds, VIEWPORT, area);
+        assert_eq!(camera.zoom, 1.0);
+        assert_near(camera.canvas_to_screen(Vec2::ZERO, VIEWPORT), Vec2::new(0.25, 0.0));
+        assert_near(camera.canvas_to_screen(bounds.0, VIEWPORT), Vec2::new(-0.5, -0.5));
+        assert_near(camera.canvas_to_screen(bounds.1, VIEWPORT), Vec2::new(1.0, 0.5));
+    }
+}
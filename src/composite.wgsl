@@ -0,0 +1,115 @@
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) opacity: f32,
+    @location(1) @interpolate(flat) blend_mode: u32,
+};
+
+// The source texture, e.g. a layer.
+@group(0) @binding(0)
+var layer_texture: texture_2d<f32>;
+
+// What the source is blended onto, only bound for `fs_blend`.
+@group(1) @binding(0)
+var backdrop_texture: texture_2d<f32>;
+
+fn full_target_position(index: u32) -> vec4<f32> {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+// Full-target triangle, one instance per layer or batch.
+@vertex
+fn vs_main(
+    @builtin(vertex_index) index: u32,
+    @location(0) opacity: f32,
+    @location(1) blend_mode: u32,
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = full_target_position(index);
+    out.opacity = opacity;
+    out.blend_mode = blend_mode;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    // Layer textures hold premultiplied color, so opacity scales all channels.
+    let color = textureLoad(layer_texture, vec2<i32>(in.clip_position.xy), 0);
+    return color * in.opacity;
+}
+
+fn unpremultiply(color: vec4<f32>) -> vec3<f32> {
+    if color.a <= 0.0 {
+        return vec3<f32>(0.0);
+    }
+    return min(color.rgb / color.a, vec3<f32>(1.0));
+}
+
+// Separable blend functions, see `BlendMode` for the numbering.
+fn blend_channels(mode: u32, backdrop: vec3<f32>, source: vec3<f32>) -> vec3<f32> {
+    switch mode {
+        case 1u: {
+            return backdrop * source;
+        }
+        case 2u: {
+            return backdrop + source - backdrop * source;
+        }
+        case 3u: {
+            let multiply = 2.0 * backdrop * source;
+            let screen = 1.0 - 2.0 * (1.0 - backdrop) * (1.0 - source);
+            return select(screen, multiply, backdrop <= vec3<f32>(0.5));
+        }
+        case 4u: {
+            return min(backdrop, source);
+        }
+        case 5u: {
+            return max(backdrop, source);
+        }
+        case 6u: {
+            return min(backdrop + source, vec3<f32>(1.0));
+        }
+        case 7u: {
+            return abs(backdrop - source);
+        }
+        default: {
+            return source;
+        }
+    }
+}
+
+// Source-over compositing with the mixed color where both are present,
+// following the W3C compositing model on premultiplied colors.
+@fragment
+fn fs_blend(in: VertexOutput) -> @location(0) vec4<f32> {
+    let position = vec2<i32>(in.clip_position.xy);
+    let source = textureLoad(layer_texture, position, 0) * in.opacity;
+    let backdrop = textureLoad(backdrop_texture, position, 0);
+
+    let mixed = blend_channels(in.blend_mode, unpremultiply(backdrop), unpremultiply(source));
+    let rgb = source.rgb * (1.0 - backdrop.a)
+        + backdrop.rgb * (1.0 - source.a)
+        + source.a * backdrop.a * mixed;
+    let alpha = source.a + backdrop.a - source.a * backdrop.a;
+    return vec4<f32>(rgb, alpha);
+}
+
+@vertex
+fn vs_copy(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    return full_target_position(index);
+}
+
+@fragment
+fn fs_copy(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    return textureLoad(layer_texture, vec2<i32>(position.xy), 0);
+}
+
+// Side of the checkerboard squares behind transparent canvas areas, in pixels.
+const CHECKER_SIZE: u32 = 8u;
+
+@fragment
+fn fs_present(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let color = textureLoad(layer_texture, vec2<i32>(position.xy), 0);
+    let cell = vec2<u32>(position.xy) / CHECKER_SIZE;
+    let checker = select(1.0, 0.8, (cell.x + cell.y) % 2u == 1u);
+    return vec4<f32>(color.rgb + checker * (1.0 - color.a), 1.0);
+}
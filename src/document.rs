@@ -0,0 +1,762 @@
+use glam::{Mat4, Vec2, Vec3};
+use crate::engine::{BlendMode, Scene, StrokeOp, Vertex};
+use crate::spatial::{self, Bounds, GridIndex};
+use crate::tools::{
+    brush::BrushStroke, bucket::FillStroke, ellipse::EllipseStroke, eraser::EraseStroke, line::LineStroke,
+    pen::{Anchor, BezierStroke}, polygon::PolygonStroke, rectangle::RectangleStroke,
+};
+
+/// Document pixels per canvas unit. Canvas units are the same size on both
+/// axes; a document is as many units wide and tall as its pixel size
+/// divided by this, centered on the origin.
+pub const PIXELS_PER_UNIT: f32 = 300.0;
+
+/// Pixel size of new documents.
+pub const DEFAULT_SIZE: [u32; 2] = [800, 600];
+
+/// Background of new documents, the same white as `BACKGROUND_COLOR`.
+pub const DEFAULT_BACKGROUND: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+
+/// Width and height in canvas units of a document `size` pixels large.
+pub fn canvas_extent(size: [u32; 2]) -> Vec2 {
+    Vec2::new(size[0] as f32, size[1] as f32) / PIXELS_PER_UNIT
+}
+
+/// Maps `area`, in canvas units, onto clip space.
+pub fn area_view_matrix((min, max): Bounds) -> Mat4 {
+    let center = (min + max) / 2.0;
+    let half_size = (max - min) / 2.0;
+    Mat4::from_scale(Vec3::new(1.0 / half_size.x, 1.0 / half_size.y, 1.0))
+        * Mat4::from_translation(-center.extend(0.0))
+}
+
+/// The box around `points` grown by `padding` on every side, or `None`
+/// without points.
+fn padded_bounds(points: impl IntoIterator<Item = Vec2>, padding: f32) -> Option<Bounds> {
+    let (min, max) = points.into_iter().map(|point| (point, point)).reduce(spatial::union)?;
+    Some((min - padding, max + padding))
+}
+
+/// A committed drawing operation. Strokes keep their source parameters so
+/// they can be edited after the fact; vertices are produced on demand.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Stroke {
+    Brush(BrushStroke),
+    Line(LineStroke),
+    Rectangle(RectangleStroke),
+    Ellipse(EllipseStroke),
+    Polygon(PolygonStroke),
+    Bezier(BezierStroke),
+    Fill(FillStroke),
+    Erase(EraseStroke),
+}
+
+impl Stroke {
+    pub fn tessellate(&self) -> Vec<Vertex> {
+        match self {
+            Stroke::Brush(s) => s.tessellate(),
+            Stroke::Line(s) => s.tessellate(),
+            Stroke::Rectangle(s) => s.tessellate(),
+            Stroke::Ellipse(s) => s.tessellate(),
+            Stroke::Polygon(s) => s.tessellate(),
+            Stroke::Bezier(s) => s.tessellate(),
+            Stroke::Fill(s) => s.tessellate(),
+            Stroke::Erase(s) => s.tessellate(),
+        }
+    }
+
+    pub fn color(&self) -> Option<[f32; 4]> {
+        match self {
+            Stroke::Brush(s) => Some(s.color),
+            Stroke::Line(s) => Some(s.color),
+            Stroke::Rectangle(s) => Some(s.color),
+            Stroke::Ellipse(s) => Some(s.color),
+            Stroke::Polygon(s) => Some(s.color),
+            Stroke::Bezier(s) => Some(s.color),
+            Stroke::Fill(s) => Some(s.color),
+            Stroke::Erase(_) => None,
+        }
+    }
+
+    pub fn set_color(&mut self, color: [f32; 4]) {
+        match self {
+            Stroke::Brush(s) => s.color = color,
+            Stroke::Line(s) => s.color = color,
+            Stroke::Rectangle(s) => s.color = color,
+            Stroke::Ellipse(s) => s.color = color,
+            Stroke::Polygon(s) => s.color = color,
+            Stroke::Bezier(s) => s.color = color,
+            Stroke::Fill(s) => s.color = color,
+            Stroke::Erase(_) => {},
+        }
+    }
+
+    /// Multiplies the alpha of every color in the stroke by `factor`,
+    /// clamped to 1.
+    pub fn scale_opacity(&mut self, factor: f32) {
+        let scale = |color: &mut [f32; 4]| color[3] = (color[3] * factor).min(1.0);
+        match self {
+            Stroke::Rectangle(s) => {
+                scale(&mut s.color);
+                scale(&mut s.fill_color);
+            }
+            _ => {
+                if let Some(mut color) = self.color() {
+                    scale(&mut color);
+                    self.set_color(color);
+                }
+            }
+        }
+    }
+
+    /// Erase strokes always use `BlendMode::Normal`.
+    pub fn blend_mode(&self) -> BlendMode {
+        match self {
+            Stroke::Brush(s) => s.blend_mode,
+            Stroke::Line(s) => s.blend_mode,
+            Stroke::Rectangle(s) => s.blend_mode,
+            Stroke::Ellipse(s) => s.blend_mode,
+            Stroke::Polygon(s) => s.blend_mode,
+            Stroke::Bezier(s) => s.blend_mode,
+            Stroke::Fill(s) => s.blend_mode,
+            Stroke::Erase(_) => BlendMode::Normal,
+        }
+    }
+
+    /// How the stroke is applied to its layer when rendered.
+    pub fn op(&self) -> StrokeOp {
+        match self {
+            Stroke::Erase(_) => StrokeOp::Erase,
+            _ => StrokeOp::Paint(self.blend_mode()),
+        }
+    }
+
+    pub fn set_blend_mode(&mut self, blend_mode: BlendMode) {
+        match self {
+            Stroke::Brush(s) => s.blend_mode = blend_mode,
+            Stroke::Line(s) => s.blend_mode = blend_mode,
+            Stroke::Rectangle(s) => s.blend_mode = blend_mode,
+            Stroke::Ellipse(s) => s.blend_mode = blend_mode,
+            Stroke::Polygon(s) => s.blend_mode = blend_mode,
+            Stroke::Bezier(s) => s.blend_mode = blend_mode,
+            Stroke::Fill(s) => s.blend_mode = blend_mode,
+            Stroke::Erase(_) => {},
+        }
+    }
+
+    pub fn set_size(&mut self, size: f32) {
+        match self {
+            Stroke::Brush(s) => s.size = size,
+            Stroke::Line(s) => s.thickness = size,
+            Stroke::Ellipse(s) => s.thickness = size,
+            Stroke::Polygon(s) => s.thickness = size,
+            Stroke::Bezier(s) => s.thickness = size,
+            Stroke::Erase(s) => s.size = size,
+            Stroke::Rectangle(s) => s.thickness = size,
+            Stroke::Fill(_) => {},
+        }
+    }
+
+    /// Moves the stroke by `offset` in canvas coordinates.
+    pub fn translate(&mut self, offset: Vec2) {
+        match self {
+            Stroke::Brush(s) => s.points.iter_mut().for_each(|p| *p += offset),
+            Stroke::Erase(s) => s.points.iter_mut().for_each(|p| *p += offset),
+            Stroke::Line(s) => {
+                s.start += offset;
+                s.end += offset;
+            }
+            Stroke::Rectangle(s) => {
+                s.start += offset;
+                s.end += offset;
+            }
+            Stroke::Ellipse(s) => s.center += offset,
+            Stroke::Polygon(s) => s.points.iter_mut().for_each(|p| *p += offset),
+            Stroke::Bezier(s) => s.translate(offset),
+            Stroke::Fill(s) => s.translate(offset),
+        }
+    }
+
+    /// Scales the stroke's geometry by `factor` around the origin. Widths,
+    /// radii and dash lengths can't stretch unevenly, so they scale by the
+    /// geometric mean of the two factors.
+    pub fn scale(&mut self, factor: Vec2) {
+        let mean = (factor.x * factor.y).abs().sqrt();
+        match self {
+            Stroke::Brush(s) => {
+                s.points.iter_mut().for_each(|p| *p *= factor);
+                s.size *= mean;
+            }
+            Stroke::Erase(s) => {
+                s.points.iter_mut().for_each(|p| *p *= factor);
+                s.size *= mean;
+            }
+            Stroke::Line(s) => {
+                s.start *= factor;
+                s.end *= factor;
+                s.thickness *= mean;
+                s.style.dash *= mean;
+                s.style.gap *= mean;
+            }
+            Stroke::Rectangle(s) => {
+                s.start *= factor;
+                s.end *= factor;
+                s.thickness *= mean;
+                s.corner_radius *= mean;
+            }
+            Stroke::Ellipse(s) => {
+                s.center *= factor;
+                s.radii *= factor.abs();
+                s.thickness *= mean;
+            }
+            Stroke::Polygon(s) => {
+                s.points.iter_mut().for_each(|p| *p *= factor);
+                s.thickness *= mean;
+            }
+            Stroke::Bezier(s) => {
+                for anchor in &mut s.anchors {
+                    anchor.point *= factor;
+                    anchor.handle_in *= factor;
+                    anchor.handle_out *= factor;
+                }
+                s.thickness *= mean;
+                s.style.dash *= mean;
+                s.style.gap *= mean;
+            }
+            Stroke::Fill(s) => {
+                for rect in &mut s.rects {
+                    let (a, b) = (rect[0] * factor, rect[1] * factor);
+                    *rect = [a.min(b), a.max(b)];
+                }
+            }
+        }
+    }
+
+    /// Bytes owned by the stroke on the heap, not counting `size_of::<Stroke>()`.
+    pub fn heap_size(&self) -> usize {
+        match self {
+            Stroke::Brush(s) => {
+                s.points.capacity() * std::mem::size_of::<Vec2>()
+                    + s.pressures.capacity() * std::mem::size_of::<f32>()
+            }
+            Stroke::Erase(s) => s.points.capacity() * std::mem::size_of::<Vec2>(),
+            Stroke::Polygon(s) => s.points.capacity() * std::mem::size_of::<Vec2>(),
+            Stroke::Bezier(s) => s.anchors.capacity() * std::mem::size_of::<Anchor>(),
+            Stroke::Fill(s) => s.rects.capacity() * std::mem::size_of::<[Vec2; 2]>(),
+            Stroke::Line(_) | Stroke::Rectangle(_) | Stroke::Ellipse(_) => 0,
+        }
+    }
+
+    /// The box around everything the stroke draws, or `None` if it draws
+    /// nothing.
+    /// Worked out from the stroke's shape rather than its triangles, and
+    /// may be a little larger than what is drawn.
+    pub fn bounds(&self) -> Option<Bounds> {
+        match self {
+            Stroke::Brush(s) => padded_bounds(s.stabilizer.apply(&s.points), s.size),
+            Stroke::Line(s) => padded_bounds([s.start, s.end], s.style.reach(s.thickness, false)),
+            Stroke::Rectangle(s) => padded_bounds([s.min(), s.max()], s.thickness),
+            Stroke::Ellipse(s) => {
+                let reach = s.radii.abs() + if s.filled { 0.0 } else { s.thickness };
+                Some((s.center - reach, s.center + reach))
+            }
+            Stroke::Polygon(s) => padded_bounds(s.points.iter().copied(), s.thickness),
+            // The curves stay within their anchors and handles.
+            Stroke::Bezier(s) => padded_bounds(
+                s.anchors.iter().flat_map(|anchor| [anchor.point, anchor.handle_in, anchor.handle_out]),
+                s.style.reach(s.thickness, true),
+            ),
+            Stroke::Fill(s) => s.rects.iter().map(|&[min, max]| (min, max)).reduce(spatial::union),
+            Stroke::Erase(s) => padded_bounds(s.points.iter().copied(), s.size),
+        }
+    }
+
+    /// Returns true if `point` lies on the stroke or within `tolerance` of
+    /// it. Erase strokes are never hit.
+    pub fn hit_test(&self, point: Vec2, tolerance: f32) -> bool {
+        match self {
+            Stroke::Brush(s) => s.hit_test(point, tolerance),
+            Stroke::Line(s) => s.hit_test(point, tolerance),
+            Stroke::Rectangle(s) => s.hit_test(point, tolerance),
+            Stroke::Ellipse(s) => s.hit_test(point, tolerance),
+            Stroke::Polygon(s) => s.hit_test(point, tolerance),
+            Stroke::Bezier(s) => s.hit_test(point, tolerance),
+            Stroke::Fill(s) => s.hit_test(point, tolerance),
+            Stroke::Erase(_) => false,
+        }
+    }
+}
+
+/// The settings of a layer that the layers panel changes, apart from its
+/// strokes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayerProperties {
+    pub name: String,
+    pub visible: bool,
+    pub opacity: f32,
+    pub blend_mode: BlendMode,
+    pub locked: bool,
+}
+
+/// A named stack of strokes composited as one unit.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Layer {
+    pub name: String,
+    pub visible: bool,
+    pub opacity: f32,
+    pub blend_mode: BlendMode,
+    /// Locked layers can't be drawn on.
+    pub locked: bool,
+    strokes: Vec<Stroke>,
+    /// Where each stroke draws, kept in step with `strokes`.
+    index: GridIndex,
+}
+
+impl Layer {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            visible: true,
+            opacity: 1.0,
+            blend_mode: BlendMode::Normal,
+            locked: false,
+            strokes: Vec::new(),
+            index: GridIndex::default(),
+        }
+    }
+
+    pub fn with_strokes(mut self, strokes: Vec<Stroke>) -> Self {
+        self.strokes = strokes;
+        self.reindex();
+        self
+    }
+
+    pub fn properties(&self) -> LayerProperties {
+        LayerProperties {
+            name: self.name.clone(),
+            visible: self.visible,
+            opacity: self.opacity,
+            blend_mode: self.blend_mode,
+            locked: self.locked,
+        }
+    }
+
+    pub fn set_properties(&mut self, properties: LayerProperties) {
+        self.name = properties.name;
+        self.visible = properties.visible;
+        self.opacity = properties.opacity;
+        self.blend_mode = properties.blend_mode;
+        self.locked = properties.locked;
+    }
+
+    pub fn strokes(&self) -> &[Stroke] {
+        &self.strokes
+    }
+
+    pub fn push(&mut self, stroke: Stroke) {
+        self.index.insert(self.strokes.len(), stroke.bounds());
+        self.strokes.push(stroke);
+    }
+
+    pub fn insert(&mut self, index: usize, stroke: Stroke) {
+        self.index.insert(index, stroke.bounds());
+        self.strokes.insert(index, stroke);
+    }
+
+    pub fn remove(&mut self, index: usize) -> Stroke {
+        self.index.remove(index);
+        self.strokes.remove(index)
+    }
+
+    /// Swaps the stroke at `index` for `stroke`, returning the old one.
+    pub fn replace(&mut self, index: usize, stroke: Stroke) -> Stroke {
+        self.index.replace(index, stroke.bounds());
+        std::mem::replace(&mut self.strokes[index], stroke)
+    }
+
+    pub fn clear(&mut self) {
+        self.strokes.clear();
+        self.index.clear();
+    }
+
+    fn reindex(&mut self) {
+        self.index = GridIndex::new(self.strokes.iter().map(Stroke::bounds).collect());
+    }
+
+    /// The box around everything the layer's strokes draw.
+    pub fn bounds(&self) -> Option<Bounds> {
+        self.index.total_bounds()
+    }
+
+    /// The box around what the stroke at `index` draws.
+    pub fn stroke_bounds(&self, index: usize) -> Option<Bounds> {
+        self.index.bounds(index)
+    }
+
+    /// Indices, in ascending order, of the strokes that draw anything
+    /// within `area`.
+    pub fn strokes_in(&self, area: Bounds) -> Vec<usize> {
+        self.index.query(area)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.strokes.is_empty()
+    }
+
+    /// Index of the topmost stroke under `point`, if any.
+    pub fn stroke_at(&self, point: Vec2) -> Option<usize> {
+        self.index
+            .query((point, point))
+            .into_iter()
+            .rev()
+            .find(|&index| self.strokes[index].hit_test(point, 0.0))
+    }
+
+    /// Indices, in ascending order, of the strokes within `radius` of the
+    /// segment from `from` to `to`.
+    pub fn strokes_touched(&self, from: Vec2, to: Vec2, radius: f32) -> Vec<usize> {
+        // Sample the segment densely enough that no stroke slips between samples.
+        let spacing = radius.max(f32::EPSILON);
+        let steps = ((to - from).length() / spacing).ceil().min(10_000.0) as usize;
+        let samples: Vec<Vec2> = (0..=steps)
+            .map(|i| from.lerp(to, if steps == 0 { 1.0 } else { i as f32 / steps as f32 }))
+            .collect();
+
+        let area = (from.min(to) - radius, from.max(to) + radius);
+        self.index
+            .query(area)
+            .into_iter()
+            .filter(|&index| samples.iter().any(|&p| self.strokes[index].hit_test(p, radius)))
+            .collect()
+    }
+
+    /// Bytes owned by the layer on the heap, not counting `size_of::<Layer>()`.
+    pub fn heap_size(&self) -> usize {
+        self.name.capacity()
+            + self.strokes.capacity() * std::mem::size_of::<Stroke>()
+            + self.strokes.iter().map(Stroke::heap_size).sum::<usize>()
+            + self.index.heap_size()
+    }
+
+    pub fn tessellate(&self) -> Vec<Vertex> {
+        self.strokes.iter().flat_map(Stroke::tessellate).collect()
+    }
+}
+
+/// The retained contents of the canvas: layers ordered bottom to top.
+/// Always holds at least one layer.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Document {
+    layers: Vec<Layer>,
+    /// Width and height in pixels, which also fix the canvas area.
+    size: [u32; 2],
+    /// Straight-alpha color under all layers, or `None` for transparent.
+    pub background: Option<[f32; 4]>,
+    /// Infinite documents have no edges. Their canvas area is wherever
+    /// strokes have been drawn, and `size` only applies while it's empty.
+    pub infinite: bool,
+}
+
+impl Default for Document {
+    fn default() -> Self {
+        Self {
+            layers: vec![Layer::new("Layer 1")],
+            size: DEFAULT_SIZE,
+            background: Some(DEFAULT_BACKGROUND),
+            infinite: false,
+        }
+    }
+}
+
+impl Document {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Builds a document from `layers`, or a single empty layer if there are none.
+    pub fn from_layers(layers: Vec<Layer>) -> Self {
+        if layers.is_empty() {
+            return Self::default();
+        }
+        Self {
+            layers,
+            ..Self::default()
+        }
+    }
+
+    /// Width and height in pixels of the canvas area.
+    pub fn size(&self) -> [u32; 2] {
+        if !self.infinite {
+            return self.size;
+        }
+        let (min, max) = self.bounds();
+        let size = ((max - min) * PIXELS_PER_UNIT).ceil().max(Vec2::ONE);
+        [size.x as u32, size.y as u32]
+    }
+
+    /// The size set on the document, which infinite documents only use
+    /// while they're empty.
+    pub fn empty_size(&self) -> [u32; 2] {
+        self.size
+    }
+
+    /// Changes the pixel size, and with it the canvas area. Strokes stay
+    /// where they are.
+    pub fn set_size(&mut self, size: [u32; 2]) {
+        self.size = size;
+    }
+
+    /// Minimum and maximum corner of the canvas area, in canvas units.
+    pub fn bounds(&self) -> (Vec2, Vec2) {
+        if self.infinite {
+            if let Some(bounds) = self.layers.iter().filter_map(Layer::bounds).reduce(spatial::union) {
+                return bounds;
+            }
+        }
+        let half_size = canvas_extent(self.size) / 2.0;
+        (-half_size, half_size)
+    }
+
+    /// The background premultiplied, for clearing a render target.
+    pub fn clear_color(&self) -> wgpu::Color {
+        match self.background {
+            Some([r, g, b, a]) => wgpu::Color {
+                r: (r * a) as f64,
+                g: (g * a) as f64,
+                b: (b * a) as f64,
+                a: a as f64,
+            },
+            None => wgpu::Color::TRANSPARENT,
+        }
+    }
+
+    /// Maps the canvas area onto clip space, for rendering the document on
+    /// its own, e.g. for export.
+    pub fn view_matrix(&self) -> Mat4 {
+        area_view_matrix(self.bounds())
+    }
+
+    pub fn layers(&self) -> &[Layer] {
+        &self.layers
+    }
+
+    pub fn layer(&self, index: usize) -> &Layer {
+        &self.layers[index]
+    }
+
+    pub fn layer_mut(&mut self, index: usize) -> &mut Layer {
+        &mut self.layers[index]
+    }
+
+    pub fn insert_layer(&mut self, index: usize, layer: Layer) {
+        self.layers.insert(index, layer);
+    }
+
+    /// Removes the layer at `index`.
+    ///
+    /// Panics if it is the only layer, as a document always keeps one.
+    pub fn remove_layer(&mut self, index: usize) -> Layer {
+        assert!(self.layers.len() > 1, "cannot remove the only layer of a document");
+        self.layers.remove(index)
+    }
+
+    pub fn move_layer(&mut self, from: usize, to: usize) {
+        let layer = self.layers.remove(from);
+        self.layers.insert(to, layer);
+    }
+
+    /// A layer name not used by any existing layer.
+    pub fn next_layer_name(&self) -> String {
+        (self.layers.len() + 1..)
+            .map(|n| format!("Layer {}", n))
+            .find(|name| self.layers.iter().all(|layer| &layer.name != name))
+            .unwrap()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layers.iter().all(Layer::is_empty)
+    }
+
+    pub fn tessellate(&self) -> Scene {
+        self.tessellate_in(None)
+    }
+
+    /// Tessellates all visible layers. With an `area`, strokes that draw
+    /// nothing inside it are left out.
+    pub fn tessellate_in(&self, area: Option<Bounds>) -> Scene {
+        self.tessellate_with_preview(0, &[], StrokeOp::default(), area)
+    }
+
+    /// Index of the scene layer `layer` becomes when tessellated, or
+    /// `None` if it's hidden and left out.
+    pub fn scene_layer(&self, layer: usize) -> Option<usize> {
+        self.layers[layer]
+            .visible
+            .then(|| self.layers[..layer].iter().filter(|layer| layer.visible).count())
+    }
+
+    /// Tessellates all visible layers, appending `preview` (an in-progress
+    /// stroke applied with `preview_op`) on top of the layer at
+    /// `preview_layer`. With an `area`, strokes that draw nothing inside
+    /// it are left out.
+    pub fn tessellate_with_preview(
+        &self,
+        preview_layer: usize,
+        preview: &[Vertex],
+        preview_op: StrokeOp,
+        area: Option<Bounds>,
+    ) -> Scene {
+        let mut scene = Scene::default();
+        for (index, layer) in self.layers.iter().enumerate() {
+            if !layer.visible {
+                continue;
+            }
+
+            scene.begin_layer(layer.opacity, layer.blend_mode);
+            let strokes: Box<dyn Iterator<Item = &Stroke>> = match area {
+                Some(area) => Box::new(layer.strokes_in(area).into_iter().map(|i| &layer.strokes[i])),
+                None => Box::new(layer.strokes.iter()),
+            };
+            for stroke in strokes {
+                scene.push_stroke(&stroke.tessellate(), stroke.op());
+            }
+            if index == preview_layer {
+                scene.push_stroke(preview, preview_op);
+            }
+        }
+        scene
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tessellation::{LineCap, LineJoin};
+    use crate::tools::line::LineStyle;
+    use crate::tools::rectangle::RectangleStyle;
+    use crate::tools::stabilizer::{Stabilizer, StabilizerKind};
+
+    const COLOR: [f32; 4] = [0.0, 0.0, 0.0, 1.0];
+
+    fn zigzag() -> Vec<Vec2> {
+        vec![Vec2::new(-0.5, 0.0), Vec2::new(-0.45, 0.3), Vec2::new(-0.4, 0.0), Vec2::new(0.2, 0.1)]
+    }
+
+    #[test]
+    fn bounds_contain_what_strokes_draw() {
+        let style = LineStyle {
+            cap: LineCap::Square,
+            join: LineJoin::Miter,
+            end_arrow: true,
+            ..LineStyle::default()
+        };
+        let mut brush = BrushStroke::new(0.05, COLOR);
+        brush.points = zigzag();
+        brush.pressures = vec![1.0; 4];
+        brush.stabilizer = Stabilizer::new(StabilizerKind::CatmullRom, 0.5);
+        let strokes = [
+            Stroke::Brush(brush),
+            Stroke::Line(LineStroke {
+                start: Vec2::ZERO,
+                end: Vec2::new(0.3, 0.2),
+                thickness: 0.02,
+                style,
+                color: COLOR,
+                blend_mode: BlendMode::Normal,
+            }),
+            Stroke::Rectangle(RectangleStroke {
+                start: Vec2::new(0.1, 0.2),
+                end: Vec2::new(-0.2, -0.1),
+                style: RectangleStyle::Outline,
+                thickness: 0.03,
+                corner_radius: 0.05,
+                color: COLOR,
+                fill_color: COLOR,
+                blend_mode: BlendMode::Normal,
+            }),
+            Stroke::Ellipse(EllipseStroke {
+                center: Vec2::new(0.1, -0.1),
+                radii: Vec2::new(0.2, 0.05),
+                filled: false,
+                thickness: 0.02,
+                color: COLOR,
+                blend_mode: BlendMode::Normal,
+            }),
+            Stroke::Polygon(PolygonStroke {
+                points: zigzag(),
+                closed: false,
+                filled: false,
+                thickness: 0.04,
+                color: COLOR,
+                blend_mode: BlendMode::Normal,
+            }),
+            Stroke::Bezier(BezierStroke {
+                anchors: vec![
+                    Anchor {
+                        point: Vec2::ZERO,
+                        handle_in: Vec2::ZERO,
+                        handle_out: Vec2::new(0.2, 0.4),
+                    },
+                    Anchor {
+                        point: Vec2::new(0.3, 0.0),
+                        handle_in: Vec2::new(0.1, -0.3),
+                        handle_out: Vec2::new(0.3, 0.0),
+                    },
+                ],
+                closed: false,
+                thickness: 0.03,
+                style,
+                color: COLOR,
+                blend_mode: BlendMode::Normal,
+            }),
+            Stroke::Erase(EraseStroke {
+                points: zigzag(),
+                size: 0.05,
+            }),
+        ];
+
+        for stroke in strokes {
+            let (min, max) = stroke.bounds().unwrap();
+            for vertex in stroke.tessellate() {
+                let point = Vec2::from(vertex.position);
+                assert!(
+                    point.cmpge(min - 1e-5).all() && point.cmple(max + 1e-5).all(),
+                    "{:?} draws {} outside {:?}",
+                    stroke,
+                    point,
+                    (min, max)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn finds_strokes_through_the_index() {
+        let dot = |x: f32| {
+            Stroke::Ellipse(EllipseStroke {
+                center: Vec2::new(x, 0.0),
+                radii: Vec2::splat(0.1),
+                filled: true,
+                thickness: 0.0,
+                color: COLOR,
+                blend_mode: BlendMode::Normal,
+            })
+        };
+        let layer = Layer::new("Layer 1").with_strokes(vec![dot(0.0), dot(0.05), dot(1.0)]);
+        assert_eq!(layer.stroke_at(Vec2::new(0.02, 0.0)), Some(1));
+        assert_eq!(layer.stroke_at(Vec2::new(-0.08, 0.0)), Some(0));
+        assert_eq!(layer.stroke_at(Vec2::new(0.5, 0.0)), None);
+        assert_eq!(layer.strokes_touched(Vec2::new(0.5, 0.5), Vec2::new(0.5, -0.5), 0.01), Vec::<usize>::new());
+        assert_eq!(layer.strokes_touched(Vec2::new(-1.0, 0.0), Vec2::new(2.0, 0.0), 0.01), vec![0, 1, 2]);
+        assert_eq!(layer.strokes_touched(Vec2::new(0.9, 0.2), Vec2::new(0.9, -0.2), 0.05), vec![2]);
+    }
+
+    #[test]
+    #[should_panic(expected = "only layer")]
+    fn keeps_the_last_layer() {
+        let mut document = Document::new();
+        document.insert_layer(1, Layer::new("Layer 2"));
+        document.remove_layer(0);
+        document.remove_layer(0);
+    }
+}
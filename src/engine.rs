@@ -1,9 +1,37 @@
 use winit::window::Window;
 use bytemuck::{Pod, Zeroable};
+use std::fmt;
 use std::mem;
+use std::ops::Range;
 use wgpu::util::DeviceExt;
 use egui_wgpu::renderer::ScreenDescriptor;
-use egui_winit::State as EguiWinitState;
+use glam::{Mat4, Vec2};
+
+use crate::camera::Camera;
+
+/// Texture format used for headless rendering, so readback is plain RGBA8.
+pub const OFFSCREEN_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+/// Format of the intermediate texture each layer is drawn into before compositing.
+pub const LAYER_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+/// Depth buffer used while drawing a layer. Each batch gets its own depth so
+/// translucent strokes don't blend over their own overlapping triangles.
+const LAYER_DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+/// Batch depths are `(index + 1) / BATCH_DEPTH_STEPS`, exact in an f32.
+const BATCH_DEPTH_STEPS: f32 = (1 << 24) as f32;
+
+/// Clear color behind the paint canvas.
+pub const BACKGROUND_COLOR: wgpu::Color = wgpu::Color::WHITE;
+
+/// Color of the window around the document.
+pub const LETTERBOX_COLOR: wgpu::Color = wgpu::Color {
+    r: 0.1,
+    g: 0.1,
+    b: 0.1,
+    a: 1.0,
+};
 
 #[repr(C)]
 #[derive(Copy, Clone, Debug, Pod, Zeroable)]
@@ -33,18 +61,638 @@ impl Vertex {
     }
 }
 
+/// How a stroke or layer combines with what is already underneath it. The
+/// discriminants are the mode numbers `composite.wgsl` switches on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BlendMode {
+    #[default]
+    Normal = 0,
+    Multiply = 1,
+    Screen = 2,
+    Overlay = 3,
+    Darken = 4,
+    Lighten = 5,
+    Add = 6,
+    Difference = 7,
+}
+
+impl BlendMode {
+    pub const ALL: [BlendMode; 8] = [
+        BlendMode::Normal,
+        BlendMode::Multiply,
+        BlendMode::Screen,
+        BlendMode::Overlay,
+        BlendMode::Darken,
+        BlendMode::Lighten,
+        BlendMode::Add,
+        BlendMode::Difference,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            BlendMode::Normal => "Normal",
+            BlendMode::Multiply => "Multiply",
+            BlendMode::Screen => "Screen",
+            BlendMode::Overlay => "Overlay",
+            BlendMode::Darken => "Darken",
+            BlendMode::Lighten => "Lighten",
+            BlendMode::Add => "Add",
+            BlendMode::Difference => "Difference",
+        }
+    }
+
+    pub fn from_index(index: u32) -> Option<Self> {
+        Self::ALL.get(index as usize).copied()
+    }
+}
+
+/// What a stroke does to the layer it's drawn into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StrokeOp {
+    /// Paints the stroke's colors using the given blend mode.
+    Paint(BlendMode),
+    /// Makes the layer transparent under the stroke, scaled by the stroke's
+    /// alpha (destination-out). Vertex colors are otherwise ignored.
+    Erase,
+}
+
+impl Default for StrokeOp {
+    fn default() -> Self {
+        StrokeOp::Paint(BlendMode::Normal)
+    }
+}
+
+impl StrokeOp {
+    /// The mode used when compositing the stroke. Erasing happens while
+    /// drawing, so it composites as `Normal`.
+    fn blend_mode(self) -> BlendMode {
+        match self {
+            StrokeOp::Paint(blend_mode) => blend_mode,
+            StrokeOp::Erase => BlendMode::Normal,
+        }
+    }
+}
+
+/// Parameters for one composite pass: one instance per layer, then one per
+/// batch.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+struct CompositeInstance {
+    opacity: f32,
+    blend_mode: u32,
+}
+
+impl CompositeInstance {
+    fn desc() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: mem::size_of::<CompositeInstance>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 0,
+                    format: wgpu::VertexFormat::Float32,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<f32>() as wgpu::BufferAddress,
+                    shader_location: 1,
+                    format: wgpu::VertexFormat::Uint32,
+                },
+            ],
+        }
+    }
+}
+
+/// A contiguous range of `Scene::vertices` drawn with one draw call.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SceneBatch {
+    pub vertices: Range<u32>,
+    /// Translucent batches hold a single stroke, and each pixel of it is
+    /// blended only once even where its triangles overlap.
+    pub translucent: bool,
+    /// Batches painting with a mode other than `Normal` hold a single
+    /// stroke, drawn on its own and then blended onto the layer.
+    pub op: StrokeOp,
+}
+
+impl SceneBatch {
+    /// Whether the batch needs its own texture and a blend pass.
+    fn is_isolated(&self) -> bool {
+        self.op.blend_mode() != BlendMode::Normal
+    }
+}
+
+/// Batches drawn into one intermediate texture, then composited with
+/// `opacity` and `blend_mode`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SceneLayer {
+    pub batches: Vec<SceneBatch>,
+    pub opacity: f32,
+    pub blend_mode: BlendMode,
+}
+
+/// Everything the renderer draws: all vertices, split into layers that are
+/// composited bottom to top.
+#[derive(Debug, Clone, Default)]
+pub struct Scene {
+    pub vertices: Vec<Vertex>,
+    pub layers: Vec<SceneLayer>,
+}
+
+impl Scene {
+    /// A scene with all `vertices` as one stroke in a single layer.
+    pub fn from_vertices(vertices: Vec<Vertex>) -> Self {
+        let mut scene = Self::default();
+        scene.begin_layer(1.0, BlendMode::Normal);
+        scene.push_stroke(&vertices, StrokeOp::default());
+        scene
+    }
+
+    /// Starts a new layer on top of the previous ones. Strokes pushed
+    /// afterwards go into this layer.
+    pub fn begin_layer(&mut self, opacity: f32, blend_mode: BlendMode) {
+        self.layers.push(SceneLayer {
+            batches: Vec::new(),
+            opacity,
+            blend_mode,
+        });
+    }
+
+    /// Appends one stroke to the current layer. Consecutive opaque strokes
+    /// that paint with `Normal` or erase share a batch; any other stroke
+    /// gets its own.
+    pub fn push_stroke(&mut self, vertices: &[Vertex], op: StrokeOp) {
+        if vertices.is_empty() {
+            return;
+        }
+
+        let start = self.vertices.len() as u32;
+        self.vertices.extend_from_slice(vertices);
+        let end = self.vertices.len() as u32;
+
+        let layer = self
+            .layers
+            .last_mut()
+            .expect("begin_layer() must be called before push_stroke()");
+        push_batch(&mut layer.batches, start..end, is_translucent(vertices), op);
+    }
+}
+
+fn is_translucent(vertices: &[Vertex]) -> bool {
+    vertices.iter().any(|v| v.color[3] < 1.0)
+}
+
+/// Adds a stroke drawn from `vertices` to `batches`, extending the last
+/// batch when both can share one and their vertices are contiguous.
+fn push_batch(batches: &mut Vec<SceneBatch>, vertices: Range<u32>, translucent: bool, op: StrokeOp) {
+    let shareable = !translucent && op.blend_mode() == BlendMode::Normal;
+    match batches.last_mut() {
+        Some(batch)
+            if shareable
+                && !batch.translucent
+                && batch.op == op
+                && batch.vertices.end == vertices.start =>
+        {
+            batch.vertices.end = vertices.end;
+        }
+        _ => batches.push(SceneBatch {
+            vertices,
+            translucent,
+            op,
+        }),
+    }
+}
+
+/// Smallest allocation of a `VertexBuffer`, in vertices.
+const MIN_VERTEX_CAPACITY: u64 = 4096;
+
+const VERTEX_SIZE: u64 = mem::size_of::<Vertex>() as u64;
+
+/// A vertex buffer kept from frame to frame. Writes go into it in place;
+/// it's only reallocated, to a power of two, when they don't fit or when
+/// it's mostly empty after a rewrite.
+struct VertexBuffer {
+    buffer: wgpu::Buffer,
+    label: &'static str,
+    /// Room for this many vertices.
+    capacity: u64,
+    /// Vertices written so far.
+    len: u32,
+}
+
+impl VertexBuffer {
+    fn new(device: &wgpu::Device, label: &'static str) -> Self {
+        Self {
+            buffer: Self::allocate(device, label, MIN_VERTEX_CAPACITY),
+            label,
+            capacity: MIN_VERTEX_CAPACITY,
+            len: 0,
+        }
+    }
+
+    fn allocate(device: &wgpu::Device, label: &'static str, capacity: u64) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            size: capacity * VERTEX_SIZE,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// Replaces the contents with `vertices`.
+    fn write(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, vertices: &[Vertex]) {
+        let len = vertices.len() as u64;
+        let capacity = len.next_power_of_two().max(MIN_VERTEX_CAPACITY);
+        if len > self.capacity || capacity * 4 <= self.capacity {
+            self.buffer = Self::allocate(device, self.label, capacity);
+            self.capacity = capacity;
+        }
+        if !vertices.is_empty() {
+            queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(vertices));
+        }
+        self.len = vertices.len() as u32;
+    }
+
+    /// Adds `vertices` after the current contents and returns where they
+    /// went. Growing copies the old contents over on the GPU.
+    fn append(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, vertices: &[Vertex]) -> Range<u32> {
+        let start = self.len;
+        let end = start + vertices.len() as u32;
+        if end as u64 > self.capacity {
+            let capacity = (end as u64).next_power_of_two();
+            let buffer = Self::allocate(device, self.label, capacity);
+            let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Vertex Buffer Growth Encoder"),
+            });
+            encoder.copy_buffer_to_buffer(&self.buffer, 0, &buffer, 0, start as u64 * VERTEX_SIZE);
+            queue.submit(std::iter::once(encoder.finish()));
+            self.buffer = buffer;
+            self.capacity = capacity;
+        }
+        if !vertices.is_empty() {
+            queue.write_buffer(&self.buffer, start as u64 * VERTEX_SIZE, bytemuck::cast_slice(vertices));
+        }
+        self.len = end;
+        start..end
+    }
+}
+
+/// The in-progress stroke. It lives in its own buffer so that updating it
+/// uploads only its vertices, however big the scene is.
+struct Preview {
+    vertices: VertexBuffer,
+    /// Scene layer it's drawn on top of, or `None` to hide it.
+    layer: Option<usize>,
+    batch: SceneBatch,
+}
+
+/// A batch ready to draw: the buffer its vertex range refers to, and the
+/// instance it's blended with when drawn on its own.
+struct DrawBatch<'a> {
+    batch: &'a SceneBatch,
+    buffer: &'a wgpu::Buffer,
+    instance: u32,
+}
+
+/// GPU copy of a `Scene` plus the in-progress stroke, kept up to date in
+/// place. Committed strokes are appended to the vertex buffer, so neither
+/// drawing nor committing uploads the rest of the scene again.
+struct SceneBuffers {
+    vertices: VertexBuffer,
+    instance_buffer: wgpu::Buffer,
+    layers: Vec<SceneLayer>,
+    // Index of each layer's first batch instance in `instance_buffer`.
+    first_batch_instance: Vec<u32>,
+    preview: Preview,
+    // Index of the preview's instance, after all the others.
+    preview_instance: u32,
+}
+
+impl SceneBuffers {
+    fn new(device: &wgpu::Device, queue: &wgpu::Queue, scene: &Scene) -> Self {
+        let mut vertices = VertexBuffer::new(device, "Vertex Buffer");
+        vertices.write(device, queue, &scene.vertices);
+        let preview = Preview {
+            vertices: VertexBuffer::new(device, "Preview Vertex Buffer"),
+            layer: None,
+            batch: SceneBatch {
+                vertices: 0..0,
+                translucent: false,
+                op: StrokeOp::default(),
+            },
+        };
+        let (instance_buffer, first_batch_instance, preview_instance) =
+            create_instances(device, &scene.layers, &preview.batch);
+
+        Self {
+            vertices,
+            instance_buffer,
+            layers: scene.layers.clone(),
+            first_batch_instance,
+            preview,
+            preview_instance,
+        }
+    }
+
+    /// Replaces the scene, keeping the preview.
+    fn set(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, scene: &Scene) {
+        self.vertices.write(device, queue, &scene.vertices);
+        self.layers = scene.layers.clone();
+        self.write_instances(device);
+    }
+
+    /// Adds a stroke on top of scene layer `layer`.
+    fn append(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, layer: usize, vertices: &[Vertex], op: StrokeOp) {
+        if vertices.is_empty() {
+            return;
+        }
+        let range = self.vertices.append(device, queue, vertices);
+        let batches = &mut self.layers[layer].batches;
+        let count = batches.len();
+        push_batch(batches, range, is_translucent(vertices), op);
+        if batches.len() != count {
+            self.write_instances(device);
+        }
+    }
+
+    fn set_preview(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layer: Option<usize>,
+        vertices: &[Vertex],
+        op: StrokeOp,
+    ) {
+        self.preview.vertices.write(device, queue, vertices);
+        self.preview.layer = layer.filter(|_| !vertices.is_empty());
+        self.preview.batch = SceneBatch {
+            vertices: 0..vertices.len() as u32,
+            translucent: is_translucent(vertices),
+            op,
+        };
+        let instance = CompositeInstance {
+            opacity: 1.0,
+            blend_mode: op.blend_mode() as u32,
+        };
+        let offset = self.preview_instance as u64 * mem::size_of::<CompositeInstance>() as u64;
+        queue.write_buffer(&self.instance_buffer, offset, bytemuck::bytes_of(&instance));
+    }
+
+    fn write_instances(&mut self, device: &wgpu::Device) {
+        (self.instance_buffer, self.first_batch_instance, self.preview_instance) =
+            create_instances(device, &self.layers, &self.preview.batch);
+    }
+
+    /// The batches of layer `index` in drawing order, with the preview
+    /// last if it's on this layer.
+    fn draw_batches(&self, index: usize) -> Vec<DrawBatch<'_>> {
+        let first_instance = self.first_batch_instance[index];
+        let mut batches: Vec<DrawBatch> = self.layers[index]
+            .batches
+            .iter()
+            .zip(first_instance..)
+            .map(|(batch, instance)| DrawBatch {
+                batch,
+                buffer: &self.vertices.buffer,
+                instance,
+            })
+            .collect();
+        if self.preview.layer == Some(index) {
+            batches.push(DrawBatch {
+                batch: &self.preview.batch,
+                buffer: &self.preview.vertices.buffer,
+                instance: self.preview_instance,
+            });
+        }
+        batches
+    }
+}
+
+/// Builds the composite instances: one per layer, then one per batch of
+/// each layer in turn, then the preview's. Also returns the index of each
+/// layer's first batch instance and of the preview's.
+fn create_instances(
+    device: &wgpu::Device,
+    layers: &[SceneLayer],
+    preview: &SceneBatch,
+) -> (wgpu::Buffer, Vec<u32>, u32) {
+    let mut instances: Vec<CompositeInstance> = layers
+        .iter()
+        .map(|layer| CompositeInstance {
+            opacity: layer.opacity,
+            blend_mode: layer.blend_mode as u32,
+        })
+        .collect();
+    let mut first_batch_instance = Vec::with_capacity(layers.len());
+    for layer in layers {
+        first_batch_instance.push(instances.len() as u32);
+        instances.extend(layer.batches.iter().map(|batch| CompositeInstance {
+            opacity: 1.0,
+            blend_mode: batch.op.blend_mode() as u32,
+        }));
+    }
+    let preview_instance = instances.len() as u32;
+    instances.push(CompositeInstance {
+        opacity: 1.0,
+        blend_mode: preview.op.blend_mode() as u32,
+    });
+
+    let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Layer Instance Buffer"),
+        contents: bytemuck::cast_slice(&instances),
+        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+    });
+    (buffer, first_batch_instance, preview_instance)
+}
+
+/// A view matrix in a uniform buffer, bound for `shader.wgsl`.
+struct ViewUniform {
+    buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+impl ViewUniform {
+    fn new(device: &wgpu::Device, layout: &wgpu::BindGroupLayout, matrix: Mat4) -> Self {
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("View Buffer"),
+            contents: bytemuck::cast_slice(&matrix.to_cols_array()),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("View Bind Group"),
+            layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+        });
+        Self { buffer, bind_group }
+    }
+}
+
+/// A color texture that can be rendered into and read by the composite shader.
+struct TargetTexture {
+    view: wgpu::TextureView,
+    bind_group: wgpu::BindGroup,
+}
+
+impl TargetTexture {
+    fn new(device: &wgpu::Device, layout: &wgpu::BindGroupLayout, width: u32, height: u32) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Layer Texture"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: LAYER_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Layer Bind Group"),
+            layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&view),
+            }],
+        });
+
+        Self { view, bind_group }
+    }
+}
+
+/// Intermediate textures the scene is built up in, all the size of the
+/// final target. Blend modes read the backdrop, so the canvas and layer
+/// textures come in pairs that are swapped after each blend.
+struct RenderTargets {
+    canvas: [TargetTexture; 2],
+    layer: [TargetTexture; 2],
+    // A single stroke with a non-`Normal` blend mode, before it's blended.
+    stroke: TargetTexture,
+    depth_view: wgpu::TextureView,
+    width: u32,
+    height: u32,
+}
+
+impl RenderTargets {
+    fn new(device: &wgpu::Device, layout: &wgpu::BindGroupLayout, width: u32, height: u32) -> Self {
+        let texture = || TargetTexture::new(device, layout, width, height);
+        let depth_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Layer Depth Texture"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: LAYER_DEPTH_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+
+        Self {
+            canvas: [texture(), texture()],
+            layer: [texture(), texture()],
+            stroke: texture(),
+            depth_view: depth_texture.create_view(&wgpu::TextureViewDescriptor::default()),
+            width,
+            height,
+        }
+    }
+}
+
+/// Why `Renderer2D::new_headless` failed.
+#[derive(Debug)]
+pub enum HeadlessError {
+    /// The machine has neither a GPU nor a fallback (software) adapter.
+    NoAdapter,
+    Device(wgpu::RequestDeviceError),
+}
+
+impl fmt::Display for HeadlessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HeadlessError::NoAdapter => write!(f, "no adapter available for headless rendering"),
+            HeadlessError::Device(err) => write!(f, "could not create a headless device: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for HeadlessError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            HeadlessError::NoAdapter => None,
+            HeadlessError::Device(err) => Some(err),
+        }
+    }
+}
+
+/// A device on the preferred adapter, or on the fallback (software) one
+/// if `force_fallback_adapter` is set.
+async fn request_headless_device(
+    instance: &wgpu::Instance,
+    force_fallback_adapter: bool,
+) -> Result<(wgpu::Device, wgpu::Queue), HeadlessError> {
+    let adapter = instance
+        .request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::default(),
+            compatible_surface: None,
+            force_fallback_adapter,
+        })
+        .await
+        .ok_or(HeadlessError::NoAdapter)?;
+
+    adapter
+        .request_device(
+            &wgpu::DeviceDescriptor {
+                label: None,
+                features: wgpu::Features::empty(),
+                limits: wgpu::Limits::downlevel_defaults().using_resolution(adapter.limits()),
+            },
+            None,
+        )
+        .await
+        .map_err(HeadlessError::Device)
+}
+
 pub struct Renderer2D {
-    surface: wgpu::Surface,
+    // `None` for headless renderers, which draw into `offscreen_texture` instead.
+    surface: Option<wgpu::Surface>,
     device: wgpu::Device,
     queue: wgpu::Queue,
     config: wgpu::SurfaceConfiguration,
     pub size: winit::dpi::PhysicalSize<u32>,
-    render_pipeline: wgpu::RenderPipeline,
-    vertex_buffer: wgpu::Buffer,
-    num_vertices: u32,
+    // Draw strokes into a layer texture, for opaque and translucent batches.
+    stroke_pipeline: wgpu::RenderPipeline,
+    translucent_stroke_pipeline: wgpu::RenderPipeline,
+    // Cuts erase strokes out of a layer texture.
+    erase_pipeline: wgpu::RenderPipeline,
+    // Draws a texture over another with plain alpha blending.
+    composite_pipeline: wgpu::RenderPipeline,
+    // Writes the blend of a texture and a backdrop texture, for other modes.
+    blend_pipeline: wgpu::RenderPipeline,
+    // Copy the finished canvas onto the surface, or onto an `OFFSCREEN_FORMAT` texture.
+    present_pipeline: wgpu::RenderPipeline,
+    offscreen_present_pipeline: wgpu::RenderPipeline,
+    composite_bind_group_layout: wgpu::BindGroupLayout,
+    view_bind_group_layout: wgpu::BindGroupLayout,
+    // What the window shows, and the document area within it, if it has edges.
+    camera: Camera,
+    document_bounds: Option<(Vec2, Vec2)>,
+    view: ViewUniform,
+    /// Clear color of the canvas under the scene, premultiplied.
+    background: wgpu::Color,
+    scene: SceneBuffers,
+    targets: RenderTargets,
+    offscreen_texture: Option<wgpu::Texture>,
     egui_renderer: egui_wgpu::renderer::Renderer,
-    pub egui_state: EguiWinitState,
-    pub egui_ctx: egui::Context,
 }
 
 impl Renderer2D {
@@ -96,75 +744,145 @@ impl Renderer2D {
         };
         surface.configure(&device, &config);
 
-        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("Shader"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("shader.wgsl").into()),
-        });
-
-        let render_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: Some("Render Pipeline Layout"),
-            bind_group_layouts: &[],
-            push_constant_ranges: &[],
-        });
-
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Render Pipeline"),
-            layout: Some(&render_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: "vs_main",
-                buffers: &[Vertex::desc()],
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: "fs_main",
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: config.format,
-                    blend: Some(wgpu::BlendState {
-                        color: wgpu::BlendComponent::REPLACE,
-                        alpha: wgpu::BlendComponent::REPLACE,
-                    }),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
-                unclipped_depth: false,
-                polygon_mode: wgpu::PolygonMode::Fill,
-                conservative: false,
-            },
-            depth_stencil: None,
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-            multiview: None,
-        });
-
         // Create a triangle
-        let vertices = [
+        let vertices = vec![
             Vertex { position: [0.0, 0.5], color: [1.0, 0.0, 0.0, 1.0] },    // Top (red)
             Vertex { position: [-0.5, -0.5], color: [0.0, 1.0, 0.0, 1.0] },  // Bottom left (green)
             Vertex { position: [0.5, -0.5], color: [0.0, 0.0, 1.0, 1.0] },   // Bottom right (blue)
         ];
-        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Vertex Buffer"),
-            contents: bytemuck::cast_slice(&vertices),
-            usage: wgpu::BufferUsages::VERTEX,
+
+        Self::from_device(device, queue, Some(surface), config, &Scene::from_vertices(vertices))
+    }
+
+    /// Creates a renderer without a window that draws into an offscreen
+    /// texture of the given size. Uses the GPU if there is one and the
+    /// fallback (software) adapter otherwise, so it also works in CI.
+    pub async fn new_headless(width: u32, height: u32) -> Result<Self, HeadlessError> {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+            backends: wgpu::Backends::all(),
+            dx12_shader_compiler: Default::default(),
         });
 
+        let (device, queue) = match request_headless_device(&instance, false).await {
+            Ok(device) => device,
+            Err(_) => request_headless_device(&instance, true).await?,
+        };
+
+        // Never used to configure a surface, only to track format and size.
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            format: OFFSCREEN_FORMAT,
+            width: width.max(1),
+            height: height.max(1),
+            present_mode: wgpu::PresentMode::Fifo,
+            alpha_mode: wgpu::CompositeAlphaMode::Auto,
+            view_formats: vec![],
+        };
+
+        let mut renderer = Self::from_device(device, queue, None, config, &Scene::default());
+        renderer.offscreen_texture = Some(create_offscreen_texture(
+            &renderer.device,
+            renderer.size.width,
+            renderer.size.height,
+        ));
+        Ok(renderer)
+    }
+
+    fn from_device(
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        surface: Option<wgpu::Surface>,
+        config: wgpu::SurfaceConfiguration,
+        scene: &Scene,
+    ) -> Self {
+        let size = winit::dpi::PhysicalSize::new(config.width, config.height);
+
+        let composite_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Composite Bind Group Layout"),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                }],
+            });
+
+        let view_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("View Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let view = ViewUniform::new(&device, &view_bind_group_layout, Mat4::IDENTITY);
+
+        // Opaque batches may overdraw themselves, translucent ones may not.
+        let stroke_pipeline = create_stroke_pipeline(
+            &device,
+            &view_bind_group_layout,
+            LAYER_FORMAT,
+            wgpu::CompareFunction::GreaterEqual,
+            wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING,
+        );
+        let translucent_stroke_pipeline = create_stroke_pipeline(
+            &device,
+            &view_bind_group_layout,
+            LAYER_FORMAT,
+            wgpu::CompareFunction::Greater,
+            wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING,
+        );
+        // Destination-out: scales what's in the layer by one minus the
+        // stroke's alpha.
+        let destination_out = wgpu::BlendComponent {
+            src_factor: wgpu::BlendFactor::Zero,
+            dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+            operation: wgpu::BlendOperation::Add,
+        };
+        let erase_pipeline = create_stroke_pipeline(
+            &device,
+            &view_bind_group_layout,
+            LAYER_FORMAT,
+            wgpu::CompareFunction::Greater,
+            wgpu::BlendState {
+                color: destination_out,
+                alpha: destination_out,
+            },
+        );
+        let composite_pipeline =
+            create_composite_pipeline(&device, &composite_bind_group_layout, CompositeKind::Over);
+        let blend_pipeline =
+            create_composite_pipeline(&device, &composite_bind_group_layout, CompositeKind::Blend);
+        let present_pipeline = create_composite_pipeline(
+            &device,
+            &composite_bind_group_layout,
+            CompositeKind::Present(config.format),
+        );
+        let offscreen_present_pipeline = create_composite_pipeline(
+            &device,
+            &composite_bind_group_layout,
+            CompositeKind::Copy(OFFSCREEN_FORMAT),
+        );
+        let targets =
+            RenderTargets::new(&device, &composite_bind_group_layout, size.width, size.height);
+        let scene = SceneBuffers::new(&device, &queue, scene);
+
         let egui_renderer = egui_wgpu::renderer::Renderer::new(
             &device,
             config.format,
             None,
             1,
         );
-        let egui_state = EguiWinitState::new(&window);
-        let egui_ctx = egui::Context::default();
 
         Self {
             surface,
@@ -172,12 +890,27 @@ impl Renderer2D {
             queue,
             config,
             size,
-            render_pipeline,
-            vertex_buffer,
-            num_vertices: vertices.len() as u32,
+            stroke_pipeline,
+            translucent_stroke_pipeline,
+            erase_pipeline,
+            composite_pipeline,
+            blend_pipeline,
+            present_pipeline,
+            offscreen_present_pipeline,
+            composite_bind_group_layout,
+            view_bind_group_layout,
+            // Until `set_view`, the canvas from -1 to 1 fills the target.
+            camera: Camera {
+                center: Vec2::ZERO,
+                zoom: 1.0,
+            },
+            document_bounds: Some((Vec2::NEG_ONE, Vec2::ONE)),
+            view,
+            background: BACKGROUND_COLOR,
+            scene,
+            targets,
+            offscreen_texture: None,
             egui_renderer,
-            egui_state,
-            egui_ctx,
         }
     }
 
@@ -186,46 +919,271 @@ impl Renderer2D {
             self.size = new_size;
             self.config.width = new_size.width;
             self.config.height = new_size.height;
-            self.surface.configure(&self.device, &self.config);
+            self.targets = RenderTargets::new(
+                &self.device,
+                &self.composite_bind_group_layout,
+                new_size.width,
+                new_size.height,
+            );
+            // Canvas units stay square, so the drawing keeps its proportions.
+            self.write_view();
+            match &self.surface {
+                Some(surface) => surface.configure(&self.device, &self.config),
+                None => {
+                    self.offscreen_texture = Some(create_offscreen_texture(
+                        &self.device,
+                        new_size.width,
+                        new_size.height,
+                    ));
+                }
+            }
+        }
+    }
+
+    /// Builds the scene up on a canvas texture cleared to `background`,
+    /// one layer at a time and seen through `camera`, then copies the
+    /// result onto `view` with `present_pipeline`. With a `clip` rectangle
+    /// (x, y, width, height in pixels) only that part is copied and the
+    /// rest of `view` is cleared to `LETTERBOX_COLOR`.
+    #[allow(clippy::too_many_arguments)]
+    fn draw_scene(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        present_pipeline: &wgpu::RenderPipeline,
+        targets: &RenderTargets,
+        scene: &SceneBuffers,
+        camera: &ViewUniform,
+        background: wgpu::Color,
+        clip: Option<[u32; 4]>,
+    ) {
+        clear_texture(encoder, &targets.canvas[0].view, background);
+        let mut canvas = 0;
+
+        for (index, layer) in scene.layers.iter().enumerate() {
+            let batches = scene.draw_batches(index);
+            if batches.is_empty() || layer.opacity <= 0.0 {
+                continue;
+            }
+
+            let mut current = 0;
+            clear_texture(encoder, &targets.layer[current].view, wgpu::Color::TRANSPARENT);
+
+            // Runs of `Normal` and erase batches go straight into the layer
+            // texture; every other batch is drawn alone, then blended onto it.
+            let mut batch_index = 0;
+            while batch_index < batches.len() {
+                if !batches[batch_index].batch.is_isolated() {
+                    let run_end = batches[batch_index..]
+                        .iter()
+                        .position(|draw| draw.batch.is_isolated())
+                        .map_or(batches.len(), |offset| batch_index + offset);
+                    self.draw_batches(
+                        encoder,
+                        &targets.layer[current].view,
+                        targets,
+                        camera,
+                        &batches,
+                        batch_index..run_end,
+                    );
+                    batch_index = run_end;
+                } else {
+                    clear_texture(encoder, &targets.stroke.view, wgpu::Color::TRANSPARENT);
+                    self.draw_batches(
+                        encoder,
+                        &targets.stroke.view,
+                        targets,
+                        camera,
+                        &batches,
+                        batch_index..batch_index + 1,
+                    );
+                    self.composite(
+                        encoder,
+                        &targets.layer[1 - current].view,
+                        &targets.stroke,
+                        Some(&targets.layer[current]),
+                        scene,
+                        batches[batch_index].instance,
+                    );
+                    current = 1 - current;
+                    batch_index += 1;
+                }
+            }
+
+            let instance = index as u32;
+            if layer.blend_mode == BlendMode::Normal {
+                self.composite(
+                    encoder,
+                    &targets.canvas[canvas].view,
+                    &targets.layer[current],
+                    None,
+                    scene,
+                    instance,
+                );
+            } else {
+                self.composite(
+                    encoder,
+                    &targets.canvas[1 - canvas].view,
+                    &targets.layer[current],
+                    Some(&targets.canvas[canvas]),
+                    scene,
+                    instance,
+                );
+                canvas = 1 - canvas;
+            }
+        }
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Present Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: match clip {
+                        Some(_) => wgpu::LoadOp::Clear(LETTERBOX_COLOR),
+                        None => wgpu::LoadOp::Load,
+                    },
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        if let Some([x, y, width, height]) = clip {
+            if width == 0 || height == 0 {
+                return;
+            }
+            render_pass.set_scissor_rect(x, y, width, height);
+        }
+        render_pass.set_pipeline(present_pipeline);
+        render_pass.set_bind_group(0, &targets.canvas[canvas].bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+
+    /// Draws `layer_batches[batches]` into `view`. The depth buffer is
+    /// cleared with the layer's first batch and kept for the rest.
+    fn draw_batches(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        targets: &RenderTargets,
+        camera: &ViewUniform,
+        layer_batches: &[DrawBatch],
+        batches: Range<usize>,
+    ) {
+        let clear_depth = batches.start == 0;
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Layer Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &targets.depth_view,
+                depth_ops: Some(wgpu::Operations {
+                    load: if clear_depth {
+                        wgpu::LoadOp::Clear(0.0)
+                    } else {
+                        wgpu::LoadOp::Load
+                    },
+                    store: true,
+                }),
+                stencil_ops: None,
+            }),
+        });
+
+        render_pass.set_bind_group(0, &camera.bind_group, &[]);
+        for batch_index in batches {
+            let DrawBatch { batch, buffer, .. } = &layer_batches[batch_index];
+            render_pass.set_vertex_buffer(0, buffer.slice(..));
+            // Pin every fragment of the batch to its own depth, above all
+            // earlier batches.
+            let depth = (batch_index + 1) as f32 / BATCH_DEPTH_STEPS;
+            render_pass.set_viewport(
+                0.0,
+                0.0,
+                targets.width as f32,
+                targets.height as f32,
+                depth,
+                depth,
+            );
+            render_pass.set_pipeline(match batch.op {
+                StrokeOp::Erase => &self.erase_pipeline,
+                _ if batch.translucent => &self.translucent_stroke_pipeline,
+                _ => &self.stroke_pipeline,
+            });
+            render_pass.draw(batch.vertices.clone(), 0..1);
         }
     }
 
+    /// Composites `source` using the parameters of `instance`. Without a
+    /// `backdrop` it is drawn over `view` with plain alpha blending;
+    /// otherwise `view` is overwritten with the blend of the two.
+    fn composite(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        source: &TargetTexture,
+        backdrop: Option<&TargetTexture>,
+        scene: &SceneBuffers,
+        instance: u32,
+    ) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Composite Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+
+        render_pass.set_bind_group(0, &source.bind_group, &[]);
+        match backdrop {
+            Some(backdrop) => {
+                render_pass.set_pipeline(&self.blend_pipeline);
+                render_pass.set_bind_group(1, &backdrop.bind_group, &[]);
+            }
+            None => render_pass.set_pipeline(&self.composite_pipeline),
+        }
+        render_pass.set_vertex_buffer(0, scene.instance_buffer.slice(..));
+        // A single triangle covering the whole target.
+        render_pass.draw(0..3, instance..instance + 1);
+    }
+
     pub fn render(
         &mut self,
         window: &Window,
         gui_primitives: Vec<egui::ClippedPrimitive>,
         gui_textures: egui::TexturesDelta,
     ) -> Result<(), wgpu::SurfaceError> {
-        let output = self.surface.get_current_texture()?;
+        let surface = self
+            .surface
+            .as_ref()
+            .expect("render() needs a window surface, use render_offscreen() when headless");
+        let output = surface.get_current_texture()?;
         let view = output.texture.create_view(&wgpu::TextureViewDescriptor::default());
         let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
             label: Some("Render Encoder"),
         });
 
         // Render the paint canvas
-        {
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("Render Pass"),
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color {
-                            r: 1.0, // White background
-                            g: 1.0,
-                            b: 1.0,
-                            a: 1.0,
-                        }),
-                        store: true,
-                    },
-                })],
-                depth_stencil_attachment: None,
-            });
-
-            render_pass.set_pipeline(&self.render_pipeline);
-            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-            render_pass.draw(0..self.num_vertices, 0..1);
-        }
+        self.draw_scene(
+            &mut encoder,
+            &view,
+            &self.present_pipeline,
+            &self.targets,
+            &self.scene,
+            &self.view,
+            self.background,
+            self.document_rect(),
+        );
 
         // Render the GUI
         let screen_descriptor = ScreenDescriptor {
@@ -241,7 +1199,7 @@ impl Renderer2D {
                 &image_delta,
             );
         }
-        
+
         self.egui_renderer.update_buffers(
             &self.device,
             &self.queue,
@@ -277,12 +1235,527 @@ impl Renderer2D {
         Ok(())
     }
 
-    pub fn update_vertices(&mut self, vertices: &[Vertex]) {
-        self.vertex_buffer = self.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Vertex Buffer"),
-            contents: bytemuck::cast_slice(vertices),
-            usage: wgpu::BufferUsages::VERTEX,
+    /// Sets what part of the canvas the window and `render_offscreen`
+    /// show. `bounds` is the minimum and maximum corner of the document;
+    /// the target around it is letterboxed. Without bounds the canvas
+    /// fills the whole target.
+    pub fn set_view(&mut self, camera: Camera, bounds: Option<(Vec2, Vec2)>) {
+        self.camera = camera;
+        self.document_bounds = bounds;
+        self.write_view();
+    }
+
+    /// Sets the premultiplied color under the scene in the window and
+    /// `render_offscreen`. In the window, transparent parts show a
+    /// checkerboard.
+    pub fn set_background(&mut self, color: wgpu::Color) {
+        self.background = color;
+    }
+
+    fn viewport(&self) -> Vec2 {
+        Vec2::new(self.size.width as f32, self.size.height as f32)
+    }
+
+    fn write_view(&self) {
+        let matrix = self.camera.view_matrix(self.viewport());
+        self.queue.write_buffer(&self.view.buffer, 0, bytemuck::cast_slice(&matrix.to_cols_array()));
+    }
+
+    /// Pixels of the target the document covers, as x, y, width and
+    /// height, limited to the target.
+    fn document_rect(&self) -> Option<[u32; 4]> {
+        let viewport = self.viewport();
+        let to_pixels = |point: Vec2| {
+            let screen = self.camera.canvas_to_screen(point, viewport);
+            (Vec2::new(screen.x + 1.0, 1.0 - screen.y) / 2.0 * viewport).round().clamp(Vec2::ZERO, viewport)
+        };
+        let (min, max) = self.document_bounds?;
+        let top_left = to_pixels(Vec2::new(min.x, max.y));
+        let bottom_right = to_pixels(Vec2::new(max.x, min.y));
+        let size = (bottom_right - top_left).max(Vec2::ZERO);
+        Some([top_left.x as u32, top_left.y as u32, size.x as u32, size.y as u32])
+    }
+
+    /// Uploads `scene` in place of the current one. The preview stays.
+    pub fn update_scene(&mut self, scene: &Scene) {
+        self.scene.set(&self.device, &self.queue, scene);
+    }
+
+    /// Adds a committed stroke on top of `layer` of the current scene,
+    /// uploading only its vertices.
+    pub fn append_stroke(&mut self, layer: usize, vertices: &[Vertex], op: StrokeOp) {
+        self.scene.append(&self.device, &self.queue, layer, vertices, op);
+    }
+
+    /// Sets the in-progress stroke, drawn with `op` on top of `layer` of
+    /// the scene, or hidden when `layer` is `None`. Only its own vertices
+    /// are uploaded, so this is cheap to call on every cursor move.
+    pub fn set_preview(&mut self, layer: Option<usize>, vertices: &[Vertex], op: StrokeOp) {
+        self.scene.set_preview(&self.device, &self.queue, layer, vertices, op);
+    }
+
+    /// Renders the canvas into the offscreen texture of a headless renderer.
+    pub fn render_offscreen(&mut self) {
+        let texture = self
+            .offscreen_texture
+            .as_ref()
+            .expect("render_offscreen() is only available on headless renderers");
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Offscreen Render Encoder"),
+        });
+
+        self.draw_scene(
+            &mut encoder,
+            &view,
+            &self.offscreen_present_pipeline,
+            &self.targets,
+            &self.scene,
+            &self.view,
+            self.background,
+            self.document_rect(),
+        );
+
+        self.queue.submit(std::iter::once(encoder.finish()));
+    }
+
+    /// Reads the offscreen texture back as tightly packed RGBA8 rows, top to
+    /// bottom, with straight (non-premultiplied) alpha.
+    pub fn read_pixels(&self) -> Vec<u8> {
+        let texture = self
+            .offscreen_texture
+            .as_ref()
+            .expect("read_pixels() is only available on headless renderers");
+        self.read_texture(texture, self.size.width, self.size.height)
+    }
+
+    /// Largest width or height `render_to_pixels` accepts.
+    pub fn max_texture_size(&self) -> u32 {
+        self.device.limits().max_texture_dimension_2d
+    }
+
+    /// Renders `scene` into a fresh `width` x `height` texture and returns
+    /// its straight-alpha RGBA8 pixels. `view` maps canvas coordinates onto
+    /// the texture, e.g. `Document::view_matrix`. Works on both windowed
+    /// and headless renderers and doesn't touch the on-screen scene. A
+    /// `None` background leaves uncovered pixels fully transparent.
+    pub fn render_to_pixels(
+        &self,
+        scene: &Scene,
+        view: Mat4,
+        width: u32,
+        height: u32,
+        background: Option<wgpu::Color>,
+    ) -> Vec<u8> {
+        let texture = create_offscreen_texture(&self.device, width, height);
+        let texture_view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let targets =
+            RenderTargets::new(&self.device, &self.composite_bind_group_layout, width, height);
+        let scene = SceneBuffers::new(&self.device, &self.queue, scene);
+        let view = ViewUniform::new(&self.device, &self.view_bind_group_layout, view);
+
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Export Render Encoder"),
+        });
+        self.draw_scene(
+            &mut encoder,
+            &texture_view,
+            &self.offscreen_present_pipeline,
+            &targets,
+            &scene,
+            &view,
+            background.unwrap_or(wgpu::Color::TRANSPARENT),
+            None,
+        );
+        self.queue.submit(std::iter::once(encoder.finish()));
+
+        self.read_texture(&texture, width, height)
+    }
+
+    fn read_texture(&self, texture: &wgpu::Texture, width: u32, height: u32) -> Vec<u8> {
+        // Rows in a texture-to-buffer copy must be padded to a 256 byte multiple.
+        let unpadded_bytes_per_row = width * 4;
+        let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let padded_bytes_per_row = unpadded_bytes_per_row.div_ceil(align) * align;
+
+        let readback_buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Readback Buffer"),
+            size: (padded_bytes_per_row * height) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Readback Encoder"),
+        });
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &readback_buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_bytes_per_row),
+                    rows_per_image: Some(height),
+                },
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+        self.queue.submit(std::iter::once(encoder.finish()));
+
+        let slice = readback_buffer.slice(..);
+        slice.map_async(wgpu::MapMode::Read, |result| result.unwrap());
+        self.device.poll(wgpu::Maintain::Wait);
+
+        let padded = slice.get_mapped_range();
+        let mut pixels = Vec::with_capacity((unpadded_bytes_per_row * height) as usize);
+        for row in padded.chunks(padded_bytes_per_row as usize) {
+            pixels.extend_from_slice(&row[..unpadded_bytes_per_row as usize]);
+        }
+        drop(padded);
+        readback_buffer.unmap();
+
+        unpremultiply_srgb(&mut pixels);
+        pixels
+    }
+}
+
+fn create_stroke_pipeline(
+    device: &wgpu::Device,
+    view_bind_group_layout: &wgpu::BindGroupLayout,
+    format: wgpu::TextureFormat,
+    depth_compare: wgpu::CompareFunction,
+    blend: wgpu::BlendState,
+) -> wgpu::RenderPipeline {
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("Shader"),
+        source: wgpu::ShaderSource::Wgsl(include_str!("shader.wgsl").into()),
+    });
+
+    let render_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Render Pipeline Layout"),
+        bind_group_layouts: &[view_bind_group_layout],
+        push_constant_ranges: &[],
+    });
+
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Render Pipeline"),
+        layout: Some(&render_pipeline_layout),
+        vertex: wgpu::VertexState {
+            module: &shader,
+            entry_point: "vs_main",
+            buffers: &[Vertex::desc()],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: &shader,
+            entry_point: "fs_main",
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                // The shader outputs premultiplied alpha.
+                blend: Some(blend),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: Some(wgpu::Face::Back),
+            unclipped_depth: false,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            conservative: false,
+        },
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: LAYER_DEPTH_FORMAT,
+            depth_write_enabled: true,
+            depth_compare,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState {
+            count: 1,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+        multiview: None,
+    })
+}
+
+/// The four uses of `composite.wgsl`.
+enum CompositeKind {
+    /// A layer texture drawn over another with premultiplied alpha blending.
+    Over,
+    /// The blend of a source and backdrop texture, replacing the target.
+    Blend,
+    /// A straight copy onto a texture of the given format.
+    Copy(wgpu::TextureFormat),
+    /// A copy over a checkerboard onto a texture of the given format.
+    Present(wgpu::TextureFormat),
+}
+
+fn create_composite_pipeline(
+    device: &wgpu::Device,
+    bind_group_layout: &wgpu::BindGroupLayout,
+    kind: CompositeKind,
+) -> wgpu::RenderPipeline {
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("Composite Shader"),
+        source: wgpu::ShaderSource::Wgsl(include_str!("composite.wgsl").into()),
+    });
+
+    let (vertex_entry, fragment_entry, format, blend) = match kind {
+        CompositeKind::Over => (
+            "vs_main",
+            "fs_main",
+            LAYER_FORMAT,
+            Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+        ),
+        CompositeKind::Blend => ("vs_main", "fs_blend", LAYER_FORMAT, None),
+        CompositeKind::Copy(format) => ("vs_copy", "fs_copy", format, None),
+        CompositeKind::Present(format) => ("vs_copy", "fs_present", format, None),
+    };
+    let bind_group_layouts: &[&wgpu::BindGroupLayout] = match kind {
+        CompositeKind::Blend => &[bind_group_layout, bind_group_layout],
+        _ => &[bind_group_layout],
+    };
+    let buffers = match kind {
+        CompositeKind::Copy(_) | CompositeKind::Present(_) => vec![],
+        _ => vec![CompositeInstance::desc()],
+    };
+
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Composite Pipeline Layout"),
+        bind_group_layouts,
+        push_constant_ranges: &[],
+    });
+
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Composite Pipeline"),
+        layout: Some(&pipeline_layout),
+        vertex: wgpu::VertexState {
+            module: &shader,
+            entry_point: vertex_entry,
+            buffers: &buffers,
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: &shader,
+            entry_point: fragment_entry,
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend,
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: None,
+            unclipped_depth: false,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            conservative: false,
+        },
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState {
+            count: 1,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+        multiview: None,
+    })
+}
+
+fn clear_texture(encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView, color: wgpu::Color) {
+    encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("Clear Pass"),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(color),
+                store: true,
+            },
+        })],
+        depth_stencil_attachment: None,
+    });
+}
+
+fn create_offscreen_texture(device: &wgpu::Device, width: u32, height: u32) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Offscreen Texture"),
+        size: wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: OFFSCREEN_FORMAT,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        view_formats: &[],
+    })
+}
+
+/// Converts sRGB-encoded premultiplied RGBA8 pixels to straight alpha. The
+/// division happens in linear space, where the premultiplication was done.
+fn unpremultiply_srgb(pixels: &mut [u8]) {
+    let to_linear: Vec<f32> = (0..=255u8)
+        .map(|v| {
+            let c = v as f32 / 255.0;
+            if c <= 0.04045 {
+                c / 12.92
+            } else {
+                ((c + 0.055) / 1.055).powf(2.4)
+            }
+        })
+        .collect();
+
+    for pixel in pixels.chunks_exact_mut(4) {
+        let alpha = pixel[3];
+        if alpha == 0 || alpha == 255 {
+            continue;
+        }
+        let alpha = alpha as f32 / 255.0;
+        for channel in &mut pixel[..3] {
+            let linear = (to_linear[*channel as usize] / alpha).min(1.0);
+            let srgb = if linear <= 0.003_130_8 {
+                linear * 12.92
+            } else {
+                1.055 * linear.powf(1.0 / 2.4) - 0.055
+            };
+            *channel = (srgb * 255.0).round() as u8;
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    // Some GL drivers can't keep two devices alive at once, so renderers
+    // are created one at a time.
+    static GPU: Mutex<()> = Mutex::new(());
+
+    /// Runs `f` on a fresh headless renderer, or skips it on machines
+    /// without any adapter.
+    pub(crate) fn with_headless(width: u32, height: u32, f: impl FnOnce(&mut Renderer2D)) {
+        let _gpu = GPU.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        match pollster::block_on(Renderer2D::new_headless(width, height)) {
+            Ok(mut renderer) => f(&mut renderer),
+            Err(err) => eprintln!("skipping: {}", err),
+        }
+    }
+
+    /// The straight-alpha RGBA8 pixel at `x`, `y` of `width` wide `pixels`.
+    pub(crate) fn pixel(pixels: &[u8], width: u32, x: u32, y: u32) -> [u8; 4] {
+        let offset = ((y * width + x) * 4) as usize;
+        pixels[offset..offset + 4].try_into().unwrap()
+    }
+
+    fn square(min: Vec2, max: Vec2, color: [f32; 4]) -> Vec<Vertex> {
+        let corners = [min, Vec2::new(max.x, min.y), max, min, max, Vec2::new(min.x, max.y)];
+        corners
+            .iter()
+            .map(|corner| Vertex {
+                position: corner.to_array(),
+                color,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn headless_snapshot() {
+        with_headless(8, 8, |renderer| {
+            // The left half of the canvas, in red.
+            let scene = Scene::from_vertices(square(Vec2::new(-1.0, -1.0), Vec2::new(0.0, 1.0), [1.0, 0.0, 0.0, 1.0]));
+            renderer.update_scene(&scene);
+            renderer.render_offscreen();
+            let pixels = renderer.read_pixels();
+
+            assert_eq!(pixels.len(), 8 * 8 * 4);
+            for y in 0..8 {
+                assert_eq!(pixel(&pixels, 8, 1, y), [255, 0, 0, 255]);
+                assert_eq!(pixel(&pixels, 8, 6, y), [255, 255, 255, 255]);
+            }
+        });
+    }
+
+    fn srgb_u8(linear: f32) -> u8 {
+        let linear = linear.clamp(0.0, 1.0);
+        let srgb = if linear <= 0.003_130_8 {
+            linear * 12.92
+        } else {
+            1.055 * linear.powf(1.0 / 2.4) - 0.055
+        };
+        (srgb * 255.0).round() as u8
+    }
+
+    /// What `mode` mixes opaque `source` over opaque `backdrop` into, per
+    /// channel in linear space.
+    fn expected_blend(mode: BlendMode, backdrop: f32, source: f32) -> f32 {
+        match mode {
+            BlendMode::Normal => source,
+            BlendMode::Multiply => backdrop * source,
+            BlendMode::Screen => backdrop + source - backdrop * source,
+            BlendMode::Overlay if backdrop <= 0.5 => 2.0 * backdrop * source,
+            BlendMode::Overlay => 1.0 - 2.0 * (1.0 - backdrop) * (1.0 - source),
+            BlendMode::Darken => backdrop.min(source),
+            BlendMode::Lighten => backdrop.max(source),
+            BlendMode::Add => (backdrop + source).min(1.0),
+            BlendMode::Difference => (backdrop - source).abs(),
+        }
+    }
+
+    #[test]
+    fn blend_modes_match_their_formulas() {
+        use crate::document::{Document, Layer, Stroke};
+        use crate::tools::rectangle::{RectangleStroke, RectangleStyle};
+
+        const SIZE: u32 = 4;
+        const BACKDROP: [f32; 4] = [0.2, 0.45, 0.8, 1.0];
+        const SOURCE: [f32; 4] = [0.6, 0.3, 0.9, 1.0];
+        let cover = |color: [f32; 4], blend_mode: BlendMode| {
+            Stroke::Rectangle(RectangleStroke {
+                start: Vec2::splat(-1.0),
+                end: Vec2::splat(1.0),
+                style: RectangleStyle::Fill,
+                thickness: 0.0,
+                corner_radius: 0.0,
+                color,
+                fill_color: color,
+                blend_mode,
+            })
+        };
+
+        with_headless(1, 1, |renderer| {
+            for mode in BlendMode::ALL {
+                let expected: Vec<u8> = (0..3)
+                    .map(|channel| srgb_u8(expected_blend(mode, BACKDROP[channel], SOURCE[channel])))
+                    .chain([255])
+                    .collect();
+
+                // Once as a stroke blending with the strokes below it, and
+                // once as a layer blending with the layers below it.
+                let strokes = Layer::new("Strokes")
+                    .with_strokes(vec![cover(BACKDROP, BlendMode::Normal), cover(SOURCE, mode)]);
+                let mut top = Layer::new("Top").with_strokes(vec![cover(SOURCE, BlendMode::Normal)]);
+                top.blend_mode = mode;
+                let backdrop = Layer::new("Backdrop").with_strokes(vec![cover(BACKDROP, BlendMode::Normal)]);
+
+                for (how, layers) in [("stroke", vec![strokes]), ("layer", vec![backdrop, top])] {
+                    let mut document = Document::from_layers(layers);
+                    document.set_size([SIZE, SIZE]);
+                    let pixels = renderer.render_to_pixels(&document.tessellate(), document.view_matrix(), SIZE, SIZE, None);
+                    let actual = pixel(&pixels, SIZE, 1, 1);
+                    let worst = actual.iter().zip(&expected).map(|(a, b)| a.abs_diff(*b)).max().unwrap();
+                    assert!(worst <= 2, "{} {:?}: got {:?}, expected {:?}", how, mode, actual, expected);
+                }
+            }
         });
-        self.num_vertices = vertices.len() as u32;
     }
 }
@@ -0,0 +1,744 @@
+use std::fmt::{self, Write as _};
+use std::fs::{self, File};
+use std::io::{self, BufWriter};
+use std::path::Path;
+
+use glam::Vec2;
+
+use crate::document::{area_view_matrix, Document, Stroke};
+use crate::engine::{BlendMode, HeadlessError, Renderer2D, Vertex};
+use crate::spatial::Bounds;
+use crate::tessellation::MITER_LIMIT;
+use crate::tools::bucket::FillStroke;
+use crate::tools::line::LineStyle;
+use crate::tools::pen::{Anchor, BezierStroke};
+use crate::tools::rectangle::{RectangleStroke, RectangleStyle};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PngOptions {
+    /// Output size in pixels, independent of the window size. A different
+    /// aspect ratio than the document's adds margins rather than stretching.
+    pub width: u32,
+    pub height: u32,
+    /// Leave the background transparent even if the document has one.
+    pub transparent: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SvgOptions {
+    /// `[min_x, min_y, width, height]` of the SVG viewBox. The document's
+    /// bounds are stretched to cover it.
+    pub view_box: [f32; 4],
+}
+
+#[derive(Debug)]
+pub enum ExportError {
+    Io(io::Error),
+    Png(png::EncodingError),
+    /// The requested size is zero or exceeds the GPU texture limit.
+    InvalidSize { width: u32, height: u32, max: u32 },
+    /// No renderer could be created to draw the document.
+    Renderer(HeadlessError),
+}
+
+impl fmt::Display for ExportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExportError::Io(err) => write!(f, "I/O error: {}", err),
+            ExportError::Png(err) => write!(f, "PNG encoding failed: {}", err),
+            ExportError::InvalidSize { width, height, max } => write!(
+                f,
+                "invalid export size {}x{} (each side must be between 1 and {})",
+                width, height, max
+            ),
+            ExportError::Renderer(err) => write!(f, "rendering failed: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for ExportError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ExportError::Io(err) => Some(err),
+            ExportError::Png(err) => Some(err),
+            ExportError::InvalidSize { .. } => None,
+            ExportError::Renderer(err) => Some(err),
+        }
+    }
+}
+
+impl From<io::Error> for ExportError {
+    fn from(err: io::Error) -> Self {
+        ExportError::Io(err)
+    }
+}
+
+impl From<png::EncodingError> for ExportError {
+    fn from(err: png::EncodingError) -> Self {
+        ExportError::Png(err)
+    }
+}
+
+impl From<HeadlessError> for ExportError {
+    fn from(err: HeadlessError) -> Self {
+        ExportError::Renderer(err)
+    }
+}
+
+/// Renders `document` with an existing renderer and writes the result as a
+/// PNG. The document's bounds are centered in the output and grown to its
+/// aspect ratio, so the drawing keeps its proportions.
+pub fn render_png(
+    renderer: &Renderer2D,
+    document: &Document,
+    options: &PngOptions,
+    path: impl AsRef<Path>,
+) -> Result<(), ExportError> {
+    let max = renderer.max_texture_size();
+    if options.width == 0 || options.height == 0 || options.width > max || options.height > max {
+        return Err(ExportError::InvalidSize {
+            width: options.width,
+            height: options.height,
+            max,
+        });
+    }
+
+    let background = if options.transparent || document.background.is_none() {
+        None
+    } else {
+        Some(document.clear_color())
+    };
+    let aspect = options.width as f32 / options.height as f32;
+    let pixels = renderer.render_to_pixels(
+        &document.tessellate(),
+        area_view_matrix(fit_aspect(document.bounds(), aspect)),
+        options.width,
+        options.height,
+        background,
+    );
+    write_png(path, options.width, options.height, &pixels)
+}
+
+/// `bounds` grown around its center in one direction until its width is
+/// `aspect` times its height.
+fn fit_aspect((min, max): Bounds, aspect: f32) -> Bounds {
+    let center = (min + max) / 2.0;
+    let mut half_size = (max - min) / 2.0;
+    if half_size.x < half_size.y * aspect {
+        half_size.x = half_size.y * aspect;
+    } else {
+        half_size.y = half_size.x / aspect;
+    }
+    (center - half_size, center + half_size)
+}
+
+/// Renders `document` on a headless renderer and writes it as a PNG. Needs
+/// no window, so it can be used from batch jobs.
+pub fn export_png(
+    document: &Document,
+    options: &PngOptions,
+    path: impl AsRef<Path>,
+) -> Result<(), ExportError> {
+    let renderer = pollster::block_on(Renderer2D::new_headless(1, 1))?;
+    render_png(&renderer, document, options, path)
+}
+
+/// Writes tightly packed RGBA8 pixels as a PNG file.
+pub fn write_png(
+    path: impl AsRef<Path>,
+    width: u32,
+    height: u32,
+    rgba: &[u8],
+) -> Result<(), ExportError> {
+    let file = BufWriter::new(File::create(path)?);
+    let mut encoder = png::Encoder::new(file, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_source_srgb(png::SrgbRenderingIntent::Perceptual);
+
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(rgba)?;
+    writer.finish()?;
+    Ok(())
+}
+
+/// Serializes `document` as SVG, keeping strokes as vector primitives.
+pub fn document_to_svg(document: &Document, options: &SvgOptions) -> String {
+    let [min_x, min_y, width, height] = options.view_box;
+    let (min, max) = document.bounds();
+    let map = SvgMapping {
+        origin: Vec2::new(min_x, min_y),
+        top_left: Vec2::new(min.x, max.y),
+        scale: Vec2::new(width, height) / (max - min),
+    };
+
+    let mut svg = String::new();
+    writeln!(svg, r#"<?xml version="1.0" encoding="UTF-8"?>"#).unwrap();
+    writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="{} {} {} {}">"#,
+        min_x, min_y, width, height
+    )
+    .unwrap();
+    if let Some(color) = document.background {
+        writeln!(
+            svg,
+            r#"  <rect x="{}" y="{}" width="{}" height="{}" {}/>"#,
+            min_x,
+            min_y,
+            width,
+            height,
+            svg_paint("fill", color)
+        )
+        .unwrap();
+    }
+
+    let mut mask_count = 0;
+    for layer in document.layers().iter().filter(|layer| layer.visible) {
+        // Isolated, so stroke blend modes only see strokes of the same layer.
+        writeln!(
+            svg,
+            r#"  <g opacity="{}" style="isolation:isolate{}">"#,
+            layer.opacity,
+            css_blend_mode(layer.blend_mode)
+                .map(|mode| format!(";mix-blend-mode:{}", mode))
+                .unwrap_or_default()
+        )
+        .unwrap();
+        writeln!(svg, "   <title>{}</title>", xml_escape(&layer.name)).unwrap();
+
+        // Each erase stroke becomes a mask over everything drawn before it
+        // in the layer, so it cuts holes the same way the renderer does.
+        let mut content = String::new();
+        for stroke in layer.strokes() {
+            if let Stroke::Erase(_) = stroke {
+                mask_count += 1;
+                writeln!(
+                    svg,
+                    r#"   <mask id="erase{}" maskUnits="userSpaceOnUse" x="{}" y="{}" width="{}" height="{}">"#,
+                    mask_count, min_x, min_y, width, height
+                )
+                .unwrap();
+                writeln!(
+                    svg,
+                    r#"    <rect x="{}" y="{}" width="{}" height="{}" fill="white"/>"#,
+                    min_x, min_y, width, height
+                )
+                .unwrap();
+                write_svg_stroke(&mut svg, &map, stroke);
+                svg.push_str("   </mask>\n");
+                content = format!("   <g mask=\"url(#erase{})\">\n{}   </g>\n", mask_count, content);
+                continue;
+            }
+
+            match css_blend_mode(stroke.blend_mode()) {
+                Some(mode) => {
+                    writeln!(content, r#"   <g style="mix-blend-mode:{}">"#, mode).unwrap();
+                    write_svg_stroke(&mut content, &map, stroke);
+                    content.push_str("   </g>\n");
+                }
+                None => write_svg_stroke(&mut content, &map, stroke),
+            }
+        }
+        svg.push_str(&content);
+        svg.push_str("  </g>\n");
+    }
+
+    svg.push_str("</svg>\n");
+    svg
+}
+
+fn write_svg_stroke(svg: &mut String, map: &SvgMapping, stroke: &Stroke) {
+    match stroke {
+        Stroke::Brush(s) => {
+            let (radius, color) = s.style_at(s.mean_pressure());
+            if s.is_uniform() {
+                write_svg_path(svg, map, &s.path(), radius, color);
+            } else {
+                // SVG strokes have one width, so varying ones are filled
+                // outlines. Opacity can't vary either; the mean is used.
+                write_svg_triangles(svg, map, &s.tessellate(), color);
+            }
+        }
+        // Erase strokes only appear inside masks, where black hides content.
+        Stroke::Erase(s) => write_svg_path(svg, map, &s.points, s.size, [0.0, 0.0, 0.0, 1.0]),
+        Stroke::Line(s) => write_svg_line(svg, map, &[s.start, s.end], s.thickness, &s.style, s.color),
+        Stroke::Rectangle(s) => {
+            let (min, max, radius) = (s.min(), s.max(), s.radius());
+            match s.style {
+                RectangleStyle::Fill => write_svg_rect(svg, map, min, max, radius, &svg_paint("fill", s.fill_color)),
+                RectangleStyle::Outline => write_svg_rect(svg, map, min, max, radius, &svg_outline(map, s)),
+                RectangleStyle::FillAndOutline => {
+                    // Like the renderer, fill only inside the outline.
+                    let (inner_min, inner_max) = (min + s.thickness, max - s.thickness);
+                    if inner_max.cmpgt(inner_min).all() {
+                        let inner_radius = (radius - s.thickness).max(0.0);
+                        let paint = svg_paint("fill", s.fill_color);
+                        write_svg_rect(svg, map, inner_min, inner_max, inner_radius, &paint);
+                    }
+                    write_svg_rect(svg, map, min, max, radius, &svg_outline(map, s));
+                }
+            }
+        }
+        Stroke::Ellipse(s) => {
+            let center = map.point(s.center);
+            let radii = (s.radii * map.scale).abs();
+            let paint = if s.filled {
+                svg_paint("fill", s.color)
+            } else {
+                format!(
+                    r#"fill="none" stroke-width="{}" {}"#,
+                    map.length(s.thickness * 2.0),
+                    svg_paint("stroke", s.color)
+                )
+            };
+            writeln!(
+                svg,
+                r#"    <ellipse cx="{}" cy="{}" rx="{}" ry="{}" {}/>"#,
+                center.x, center.y, radii.x, radii.y, paint
+            )
+            .unwrap();
+        }
+        Stroke::Polygon(s) if s.is_filled() => {
+            let points: Vec<String> = s
+                .points
+                .iter()
+                .map(|p| {
+                    let p = map.point(*p);
+                    format!("{},{}", p.x, p.y)
+                })
+                .collect();
+            writeln!(
+                svg,
+                r#"    <polygon points="{}" {}/>"#,
+                points.join(" "),
+                svg_paint("fill", s.color)
+            )
+            .unwrap();
+        }
+        Stroke::Polygon(s) => write_svg_path(svg, map, &s.outline(), s.thickness, s.color),
+        Stroke::Bezier(s) => write_svg_bezier(svg, map, s),
+        Stroke::Fill(s) => write_svg_fill(svg, map, s),
+    }
+}
+
+/// Writes `document` to `path` as an SVG file.
+pub fn export_svg(
+    document: &Document,
+    options: &SvgOptions,
+    path: impl AsRef<Path>,
+) -> Result<(), ExportError> {
+    fs::write(path, document_to_svg(document, options))?;
+    Ok(())
+}
+
+struct SvgMapping {
+    origin: Vec2,
+    /// Canvas point mapped to `origin`.
+    top_left: Vec2,
+    scale: Vec2,
+}
+
+impl SvgMapping {
+    fn point(&self, p: Vec2) -> Vec2 {
+        self.origin + Vec2::new(p.x - self.top_left.x, self.top_left.y - p.y) * self.scale
+    }
+
+    /// Maps a canvas length to viewBox units. Uses the geometric mean of the
+    /// two axis scales, since SVG stroke widths can't be anisotropic.
+    fn length(&self, length: f32) -> f32 {
+        length * (self.scale.x * self.scale.y).abs().sqrt()
+    }
+}
+
+fn write_svg_path(svg: &mut String, map: &SvgMapping, points: &[Vec2], size: f32, color: [f32; 4]) {
+    let Some((first, rest)) = points.split_first() else {
+        return;
+    };
+
+    let first = map.point(*first);
+    let mut data = format!("M {} {}", first.x, first.y);
+    if rest.is_empty() {
+        // A zero-length segment still renders its caps.
+        write!(data, " L {} {}", first.x, first.y).unwrap();
+    }
+    for point in rest {
+        let p = map.point(*point);
+        write!(data, " L {} {}", p.x, p.y).unwrap();
+    }
+
+    writeln!(
+        svg,
+        r#"    <path d="{}" fill="none" stroke-width="{}" stroke-linecap="round" stroke-linejoin="round" {}/>"#,
+        data,
+        map.length(size * 2.0),
+        svg_paint("stroke", color)
+    )
+    .unwrap();
+}
+
+/// Writes a path stroked `radius` wide on each side in `style`, with
+/// arrowheads as filled polygons. A plain straight segment is written as a
+/// `<line>`.
+fn write_svg_line(svg: &mut String, map: &SvgMapping, points: &[Vec2], radius: f32, style: &LineStyle, color: [f32; 4]) {
+    let (path, arrows) = style.layout(points, radius);
+    if let ([start, end], true, false) = (path.as_slice(), arrows.is_empty(), style.is_dashed()) {
+        let [start, end] = [*start, *end].map(|p| map.point(p));
+        writeln!(
+            svg,
+            r#"    <line x1="{}" y1="{}" x2="{}" y2="{}" stroke-width="{}" stroke-linecap="{}" {}/>"#,
+            start.x,
+            start.y,
+            end.x,
+            end.y,
+            map.length(radius * 2.0),
+            style.cap.name().to_lowercase(),
+            svg_paint("stroke", color)
+        )
+        .unwrap();
+        return;
+    }
+    if let Some((first, rest)) = path.split_first() {
+        let first = map.point(*first);
+        let mut data = format!("M {} {}", first.x, first.y);
+        if rest.is_empty() {
+            write!(data, " L {} {}", first.x, first.y).unwrap();
+        }
+        for point in rest {
+            let p = map.point(*point);
+            write!(data, " L {} {}", p.x, p.y).unwrap();
+        }
+        write_svg_styled_path(svg, map, &data, radius, style, color);
+    }
+    for arrow in arrows {
+        let [a, b, c] = arrow.map(|p| map.point(p));
+        writeln!(
+            svg,
+            r#"    <polygon points="{},{} {},{} {},{}" {}/>"#,
+            a.x, a.y, b.x, b.y, c.x, c.y,
+            svg_paint("fill", color)
+        )
+        .unwrap();
+    }
+}
+
+/// Writes a Bezier path with its curves kept as curves. Arrowheads need the
+/// path cut short, so paths with them are written flattened instead.
+fn write_svg_bezier(svg: &mut String, map: &SvgMapping, stroke: &BezierStroke) {
+    let has_arrows = stroke.style.start_arrow || stroke.style.end_arrow;
+    if (has_arrows && !stroke.closed) || stroke.anchors.len() < 2 {
+        write_svg_line(svg, map, &stroke.flatten(), stroke.thickness, &stroke.style, stroke.color);
+        return;
+    }
+
+    let first = map.point(stroke.anchors[0].point);
+    let mut data = format!("M {} {}", first.x, first.y);
+    let mut curve = |from: &Anchor, to: &Anchor| {
+        let [a, b, c] = [from.handle_out, to.handle_in, to.point].map(|p| map.point(p));
+        write!(data, " C {} {} {} {} {} {}", a.x, a.y, b.x, b.y, c.x, c.y).unwrap();
+    };
+    for pair in stroke.anchors.windows(2) {
+        curve(&pair[0], &pair[1]);
+    }
+    if stroke.closed {
+        curve(&stroke.anchors[stroke.anchors.len() - 1], &stroke.anchors[0]);
+        data.push_str(" Z");
+    }
+    write_svg_styled_path(svg, map, &data, stroke.thickness, &stroke.style, stroke.color);
+}
+
+/// Writes a fill as one path of its rectangles. Edges are kept crisp so
+/// neighboring rectangles don't show seams.
+fn write_svg_fill(svg: &mut String, map: &SvgMapping, stroke: &FillStroke) {
+    let mut data = String::new();
+    for [min, max] in &stroke.rects {
+        let (a, b) = (map.point(*min), map.point(*max));
+        write!(data, "M {} {} H {} V {} H {} Z ", a.x, a.y, b.x, b.y, a.x).unwrap();
+    }
+    writeln!(
+        svg,
+        r#"    <path d="{}" shape-rendering="crispEdges" {}/>"#,
+        data.trim_end(),
+        svg_paint("fill", stroke.color)
+    )
+    .unwrap();
+}
+
+fn write_svg_styled_path(svg: &mut String, map: &SvgMapping, data: &str, radius: f32, style: &LineStyle, color: [f32; 4]) {
+    let dashes = if style.is_dashed() {
+        format!(r#" stroke-dasharray="{} {}""#, map.length(style.dash), map.length(style.gap))
+    } else {
+        String::new()
+    };
+    writeln!(
+        svg,
+        r#"    <path d="{}" fill="none" stroke-width="{}" stroke-linecap="{}" stroke-linejoin="{}" stroke-miterlimit="{}"{} {}/>"#,
+        data,
+        map.length(radius * 2.0),
+        style.cap.name().to_lowercase(),
+        style.join.name().to_lowercase(),
+        MITER_LIMIT,
+        dashes,
+        svg_paint("stroke", color)
+    )
+    .unwrap();
+}
+
+fn write_svg_rect(svg: &mut String, map: &SvgMapping, min: Vec2, max: Vec2, radius: f32, paint: &str) {
+    // The y axis flips, so the NDC top-left is (min.x, max.y).
+    let top_left = map.point(Vec2::new(min.x, max.y));
+    let size = ((max - min) * map.scale).abs();
+    let corner = if radius > 0.0 {
+        let radii = (Vec2::splat(radius) * map.scale).abs();
+        format!(r#"rx="{}" ry="{}" "#, radii.x, radii.y)
+    } else {
+        String::new()
+    };
+    writeln!(
+        svg,
+        r#"    <rect x="{}" y="{}" width="{}" height="{}" {}{}/>"#,
+        top_left.x, top_left.y, size.x, size.y, corner, paint
+    )
+    .unwrap();
+}
+
+fn svg_outline(map: &SvgMapping, s: &RectangleStroke) -> String {
+    format!(
+        r#"fill="none" stroke-width="{}" {}"#,
+        map.length(s.thickness * 2.0),
+        svg_paint("stroke", s.color)
+    )
+}
+
+/// Writes `vertices` as one filled path. The triangles share a winding, so
+/// the nonzero fill rule paints their union once.
+fn write_svg_triangles(svg: &mut String, map: &SvgMapping, vertices: &[Vertex], color: [f32; 4]) {
+    let mut data = String::new();
+    for triangle in vertices.chunks_exact(3) {
+        let [a, b, c] = [0, 1, 2].map(|i| map.point(Vec2::from(triangle[i].position)));
+        write!(data, "M {} {} L {} {} L {} {} Z ", a.x, a.y, b.x, b.y, c.x, c.y).unwrap();
+    }
+    writeln!(
+        svg,
+        r#"    <path d="{}" fill-rule="nonzero" {}/>"#,
+        data.trim_end(),
+        svg_paint("fill", color)
+    )
+    .unwrap();
+}
+
+/// The CSS `mix-blend-mode` for `blend_mode`, or `None` for `Normal`. Browsers
+/// blend in sRGB rather than linear space, so results differ slightly from
+/// the renderer.
+fn css_blend_mode(blend_mode: BlendMode) -> Option<&'static str> {
+    match blend_mode {
+        BlendMode::Normal => None,
+        BlendMode::Multiply => Some("multiply"),
+        BlendMode::Screen => Some("screen"),
+        BlendMode::Overlay => Some("overlay"),
+        BlendMode::Darken => Some("darken"),
+        BlendMode::Lighten => Some("lighten"),
+        BlendMode::Add => Some("plus-lighter"),
+        BlendMode::Difference => Some("difference"),
+    }
+}
+
+/// `text` with the characters that are special in XML text and attribute
+/// values replaced by entities.
+fn xml_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Formats `color` as `<attribute>="rgb(..)" <attribute>-opacity=".."`.
+/// Stroke colors are linear, SVG colors are sRGB.
+fn svg_paint(attribute: &str, color: [f32; 4]) -> String {
+    format!(
+        r#"{attr}="rgb({},{},{})" {attr}-opacity="{}""#,
+        linear_to_srgb_u8(color[0]),
+        linear_to_srgb_u8(color[1]),
+        linear_to_srgb_u8(color[2]),
+        color[3].clamp(0.0, 1.0),
+        attr = attribute
+    )
+}
+
+fn linear_to_srgb_u8(value: f32) -> u8 {
+    let value = value.clamp(0.0, 1.0);
+    let srgb = if value <= 0.003_130_8 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    };
+    (srgb * 255.0).round() as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::Layer;
+    use crate::engine::tests::{pixel, with_headless};
+    use crate::tessellation::LineCap;
+    use crate::tools::ellipse::EllipseStroke;
+    use crate::tools::eraser::EraseStroke;
+    use crate::tools::line::LineStroke;
+
+    const OPTIONS: SvgOptions = SvgOptions {
+        view_box: [0.0, 0.0, 100.0, 100.0],
+    };
+
+    /// A horizontal line across the middle of a one unit square document.
+    fn line(color: [f32; 4], blend_mode: BlendMode) -> Stroke {
+        Stroke::Line(LineStroke {
+            start: Vec2::new(-0.5, 0.0),
+            end: Vec2::new(0.5, 0.0),
+            thickness: 0.01,
+            style: LineStyle::default(),
+            color,
+            blend_mode,
+        })
+    }
+
+    fn svg(layers: Vec<Layer>) -> String {
+        let mut document = Document::from_layers(layers);
+        document.set_size([300, 300]);
+        document.background = None;
+        document_to_svg(&document, &OPTIONS)
+    }
+
+    #[test]
+    fn writes_strokes_in_view_box_units() {
+        let svg = svg(vec![Layer::new("Layer 1").with_strokes(vec![line([1.0, 0.0, 0.0, 1.0], BlendMode::Normal)])]);
+        assert!(svg.starts_with(r#"<?xml version="1.0" encoding="UTF-8"?>"#));
+        assert!(svg.contains(r#"viewBox="0 0 100 100""#));
+        assert!(svg.contains(r#"<line x1="0" y1="50" x2="100" y2="50" stroke-width="2""#));
+        assert!(svg.contains(r#"stroke="rgb(255,0,0)" stroke-opacity="1""#));
+        assert!(svg.trim_end().ends_with("</svg>"));
+    }
+
+    #[test]
+    fn writes_only_plain_lines_as_lines() {
+        let styled = |style: LineStyle| {
+            let mut stroke = line([0.0, 0.0, 0.0, 1.0], BlendMode::Normal);
+            if let Stroke::Line(line) = &mut stroke {
+                line.style = style;
+            }
+            svg(vec![Layer::new("Layer 1").with_strokes(vec![stroke])])
+        };
+        let plain = styled(LineStyle { cap: LineCap::Square, ..LineStyle::default() });
+        assert!(plain.contains(r#"<line x1="0" y1="50" x2="100" y2="50" stroke-width="2" stroke-linecap="square""#));
+        assert!(!plain.contains("<path"));
+
+        let dashed = styled(LineStyle { dash: 0.05, gap: 0.05, ..LineStyle::default() });
+        assert!(dashed.contains(r#"<path d="M 0 50 L 100 50""#));
+        assert!(dashed.contains(r#"stroke-dasharray="5 5""#));
+        assert!(!dashed.contains("<line"));
+
+        let arrow = styled(LineStyle { end_arrow: true, ..LineStyle::default() });
+        assert!(arrow.contains("<path d=\"M 0 50 L "));
+        assert!(arrow.contains("<polygon points="));
+        assert!(!arrow.contains("<line"));
+    }
+
+    #[test]
+    fn masks_strokes_drawn_before_an_erase() {
+        let erase = Stroke::Erase(EraseStroke {
+            points: vec![Vec2::new(0.0, -0.5), Vec2::new(0.0, 0.5)],
+            size: 0.05,
+        });
+        let strokes = vec![line([1.0, 0.0, 0.0, 1.0], BlendMode::Normal), erase, line([0.0, 0.0, 1.0, 1.0], BlendMode::Normal)];
+        let svg = svg(vec![Layer::new("Layer 1").with_strokes(strokes)]);
+
+        let mask = svg.find(r#"<mask id="erase1""#).expect("erase mask");
+        assert!(svg[mask..].contains(r#"<path d="M 50 100 L 50 0""#));
+        let masked = svg.find(r#"<g mask="url(#erase1)">"#).expect("masked group");
+        let red = svg.find(r#"stroke="rgb(255,0,0)""#).unwrap();
+        let group_end = masked + svg[masked..].find("</g>").unwrap();
+        let blue = svg.find(r#"stroke="rgb(0,0,255)""#).unwrap();
+        assert!(masked < red && red < group_end && group_end < blue);
+    }
+
+    #[test]
+    fn leaves_out_hidden_layers() {
+        let mut hidden = Layer::new("Hidden").with_strokes(vec![line([0.0, 1.0, 0.0, 1.0], BlendMode::Normal)]);
+        hidden.visible = false;
+        let svg = svg(vec![Layer::new("Shown"), hidden]);
+        assert!(svg.contains("<title>Shown</title>"));
+        assert!(!svg.contains("Hidden"));
+        assert!(!svg.contains("rgb(0,255,0)"));
+    }
+
+    #[test]
+    fn writes_opacity_and_blend_modes() {
+        let mut layer = Layer::new("Layer 1").with_strokes(vec![line([1.0, 1.0, 1.0, 0.5], BlendMode::Multiply)]);
+        layer.opacity = 0.25;
+        layer.blend_mode = BlendMode::Screen;
+        let svg = svg(vec![layer]);
+        assert!(svg.contains(r#"<g opacity="0.25" style="isolation:isolate;mix-blend-mode:screen">"#));
+        assert!(svg.contains(r#"<g style="mix-blend-mode:multiply">"#));
+        assert!(svg.contains(r#"stroke="rgb(255,255,255)" stroke-opacity="0.5""#));
+    }
+
+    #[test]
+    fn escapes_layer_names() {
+        let svg = svg(vec![Layer::new(r#"<Sky> & "Sea" 's"#)]);
+        assert!(svg.contains("<title>&lt;Sky&gt; &amp; &quot;Sea&quot; &apos;s</title>"));
+    }
+
+    /// Renders a red dot of a quarter unit radius in the middle of a one
+    /// unit square white document to a 200x100 PNG and reads it back.
+    fn png(renderer: &Renderer2D, transparent: bool) -> (u32, u32, Vec<u8>) {
+        let dot = Stroke::Ellipse(EllipseStroke {
+            center: Vec2::ZERO,
+            radii: Vec2::splat(0.25),
+            filled: true,
+            thickness: 0.0,
+            color: [1.0, 0.0, 0.0, 1.0],
+            blend_mode: BlendMode::Normal,
+        });
+        let mut document = Document::from_layers(vec![Layer::new("Layer 1").with_strokes(vec![dot])]);
+        document.set_size([300, 300]);
+        document.background = Some([1.0, 1.0, 1.0, 1.0]);
+        let options = PngOptions { width: 200, height: 100, transparent };
+
+        let path = std::env::temp_dir().join(format!("renderer_2d_{}_{}.png", std::process::id(), transparent));
+        render_png(renderer, &document, &options, &path).unwrap();
+        let decoder = png::Decoder::new(File::open(&path).unwrap());
+        let mut reader = decoder.read_info().unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut pixels).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(info.color_type, png::ColorType::Rgba);
+        (info.width, info.height, pixels)
+    }
+
+    #[test]
+    fn writes_pngs_without_stretching() {
+        with_headless(1, 1, |renderer| {
+            let (width, height, pixels) = png(renderer, false);
+            assert_eq!((width, height), (200, 100));
+            // The dot is 50 pixels across both ways.
+            for (x, y) in [(100, 50), (120, 50), (100, 70)] {
+                assert_eq!(pixel(&pixels, width, x, y), [255, 0, 0, 255]);
+            }
+            for (x, y) in [(135, 50), (100, 85), (5, 5)] {
+                assert_eq!(pixel(&pixels, width, x, y), [255, 255, 255, 255]);
+            }
+
+            let (width, _, pixels) = png(renderer, true);
+            assert_eq!(pixel(&pixels, width, 100, 50), [255, 0, 0, 255]);
+            assert_eq!(pixel(&pixels, width, 135, 50)[3], 0);
+            assert_eq!(pixel(&pixels, width, 5, 5)[3], 0);
+        });
+    }
+}
@@ -0,0 +1,591 @@
+use std::collections::VecDeque;
+use std::mem;
+
+use glam::Vec2;
+
+use crate::document::{self, Document, Layer, LayerProperties, Stroke, PIXELS_PER_UNIT};
+use crate::engine::{BlendMode, StrokeOp};
+
+/// Default memory budget for undo/redo entries.
+pub const DEFAULT_BUDGET_BYTES: usize = 64 * 1024 * 1024;
+
+/// A reversible change to a `Document`. Edits only store the strokes and
+/// layers they touch, not a snapshot of the whole document. Stroke edits
+/// address strokes by layer index and position within that layer.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Edit {
+    Insert { layer: usize, index: usize, stroke: Stroke },
+    Remove { layer: usize, index: usize, stroke: Stroke },
+    Replace { layer: usize, index: usize, before: Stroke, after: Stroke },
+    InsertLayer { index: usize, layer: Layer },
+    RemoveLayer { index: usize, layer: Layer },
+    MoveLayer { from: usize, to: usize },
+    /// Changes a layer's name, visibility, opacity, blend mode or lock.
+    ChangeLayer { index: usize, before: LayerProperties, after: LayerProperties },
+    /// Changes the document's pixel size. Strokes are moved or scaled to
+    /// match by `Replace` edits in the same batch.
+    Resize { before: [u32; 2], after: [u32; 2] },
+    /// Several edits undone and redone as one step. Applied in order,
+    /// reverted in reverse order.
+    Batch(Vec<Edit>),
+}
+
+impl Edit {
+    /// Builds an edit that removes every stroke in `document`, keeping its layers.
+    pub fn clear(document: &Document) -> Self {
+        Edit::Batch(
+            document
+                .layers()
+                .iter()
+                .enumerate()
+                .flat_map(|(layer, contents)| {
+                    contents
+                        .strokes()
+                        .iter()
+                        .enumerate()
+                        .rev()
+                        .map(move |(index, stroke)| Edit::Remove {
+                            layer,
+                            index,
+                            stroke: stroke.clone(),
+                        })
+                })
+                .collect(),
+        )
+    }
+
+    /// Whether the layer at `index` can be merged into the one below it
+    /// without changing the picture. Hidden layers always can, as they're
+    /// merged by dropping their strokes. Otherwise both layers must be
+    /// visible, fully opaque and use `Normal`. Upper strokes that erase or
+    /// use another blend mode must also stay clear of the lower layer's
+    /// strokes, since in their own layer they only affected its strokes.
+    pub fn can_merge_down(document: &Document, index: usize) -> bool {
+        let upper = document.layer(index);
+        if !upper.visible {
+            return true;
+        }
+        let lower = document.layer(index - 1);
+        let plain = |layer: &Layer| layer.visible && layer.opacity >= 1.0 && layer.blend_mode == BlendMode::Normal;
+        plain(upper)
+            && plain(lower)
+            && upper.strokes().iter().enumerate().all(|(i, stroke)| {
+                stroke.op() == StrokeOp::Paint(BlendMode::Normal)
+                    || upper.stroke_bounds(i).is_none_or(|bounds| lower.strokes_in(bounds).is_empty())
+            })
+    }
+
+    /// Builds an edit that merges the layer at `index` into the one below
+    /// it, or `None` if that would change the picture; see
+    /// `can_merge_down`.
+    pub fn merge_down(document: &Document, index: usize) -> Option<Self> {
+        if !Self::can_merge_down(document, index) {
+            return None;
+        }
+        let upper = document.layer(index);
+        let below = index - 1;
+        let offset = document.layer(below).strokes().len();
+
+        let mut edits: Vec<Edit> = Vec::new();
+        if upper.visible {
+            edits.extend(upper.strokes().iter().enumerate().map(|(i, stroke)| Edit::Insert {
+                layer: below,
+                index: offset + i,
+                stroke: stroke.clone(),
+            }));
+        }
+        edits.push(Edit::RemoveLayer {
+            index,
+            layer: upper.clone(),
+        });
+        Some(Edit::Batch(edits))
+    }
+
+    /// Builds an edit that changes the canvas to `size` pixels without
+    /// scaling the drawing. `anchor` is the part of the canvas that stays
+    /// in place, from -1 to 1 on each axis: (-1, 1) keeps the top-left
+    /// corner and zero keeps the middle.
+    pub fn canvas_size(document: &Document, size: [u32; 2], anchor: Vec2) -> Self {
+        let growth = (document::canvas_extent(size) - document::canvas_extent(document.size())) / 2.0;
+        let offset = anchor * growth;
+        Self::resize(document, size, |stroke| stroke.translate(offset))
+    }
+
+    /// Builds an edit that changes the document to `size` pixels and
+    /// stretches the drawing to match.
+    pub fn image_size(document: &Document, size: [u32; 2]) -> Self {
+        let factor = document::canvas_extent(size) / document::canvas_extent(document.size());
+        Self::resize(document, size, |stroke| stroke.scale(factor))
+    }
+
+    /// Builds an edit that crops the document to the canvas area from `min`
+    /// to `max`, limited to the current canvas and rounded to whole pixels.
+    pub fn crop(document: &Document, min: Vec2, max: Vec2) -> Self {
+        let (bounds_min, bounds_max) = document.bounds();
+        let (min, max) = (min.max(bounds_min), max.min(bounds_max));
+        let size = ((max - min) * PIXELS_PER_UNIT).round().max(Vec2::ONE);
+        let offset = -(min + max) / 2.0;
+        Self::resize(document, [size.x as u32, size.y as u32], |stroke| stroke.translate(offset))
+    }
+
+    /// A resize to `size` that changes every stroke with `transform`.
+    fn resize(document: &Document, size: [u32; 2], transform: impl Fn(&mut Stroke)) -> Self {
+        let mut edits: Vec<Edit> = document
+            .layers()
+            .iter()
+            .enumerate()
+            .flat_map(|(layer, contents)| {
+                contents.strokes().iter().enumerate().map(move |(index, stroke)| (layer, index, stroke))
+            })
+            .filter_map(|(layer, index, before)| {
+                let mut after = before.clone();
+                transform(&mut after);
+                (after != *before).then(|| Edit::Replace {
+                    layer,
+                    index,
+                    before: before.clone(),
+                    after,
+                })
+            })
+            .collect();
+        edits.push(Edit::Resize {
+            before: document.size(),
+            after: size,
+        });
+        Edit::Batch(edits)
+    }
+
+    pub fn apply(&self, document: &mut Document) {
+        match self {
+            Edit::Insert { layer, index, stroke } => {
+                document.layer_mut(*layer).insert(*index, stroke.clone())
+            }
+            Edit::Remove { layer, index, .. } => {
+                document.layer_mut(*layer).remove(*index);
+            }
+            Edit::Replace { layer, index, after, .. } => {
+                document.layer_mut(*layer).replace(*index, after.clone());
+            }
+            Edit::InsertLayer { index, layer } => document.insert_layer(*index, layer.clone()),
+            Edit::RemoveLayer { index, .. } => {
+                document.remove_layer(*index);
+            }
+            Edit::MoveLayer { from, to } => document.move_layer(*from, *to),
+            Edit::ChangeLayer { index, after, .. } => document.layer_mut(*index).set_properties(after.clone()),
+            Edit::Resize { after, .. } => document.set_size(*after),
+            Edit::Batch(edits) => edits.iter().for_each(|edit| edit.apply(document)),
+        }
+    }
+
+    pub fn revert(&self, document: &mut Document) {
+        match self {
+            Edit::Insert { layer, index, .. } => {
+                document.layer_mut(*layer).remove(*index);
+            }
+            Edit::Remove { layer, index, stroke } => {
+                document.layer_mut(*layer).insert(*index, stroke.clone())
+            }
+            Edit::Replace { layer, index, before, .. } => {
+                document.layer_mut(*layer).replace(*index, before.clone());
+            }
+            Edit::InsertLayer { index, .. } => {
+                document.remove_layer(*index);
+            }
+            Edit::RemoveLayer { index, layer } => document.insert_layer(*index, layer.clone()),
+            Edit::MoveLayer { from, to } => document.move_layer(*to, *from),
+            Edit::ChangeLayer { index, before, .. } => document.layer_mut(*index).set_properties(before.clone()),
+            Edit::Resize { before, .. } => document.set_size(*before),
+            Edit::Batch(edits) => edits.iter().rev().for_each(|edit| edit.revert(document)),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        match self {
+            Edit::Batch(edits) => edits.iter().all(Edit::is_empty),
+            Edit::MoveLayer { from, to } => from == to,
+            Edit::ChangeLayer { before, after, .. } => before == after,
+            Edit::Resize { before, after } => before == after,
+            _ => false,
+        }
+    }
+
+    /// Approximate number of bytes this edit keeps alive.
+    pub fn memory_size(&self) -> usize {
+        mem::size_of::<Edit>()
+            + match self {
+                Edit::Insert { stroke, .. } | Edit::Remove { stroke, .. } => stroke.heap_size(),
+                Edit::Replace { before, after, .. } => before.heap_size() + after.heap_size(),
+                Edit::InsertLayer { layer, .. } | Edit::RemoveLayer { layer, .. } => layer.heap_size(),
+                Edit::ChangeLayer { before, after, .. } => before.name.capacity() + after.name.capacity(),
+                Edit::MoveLayer { .. } | Edit::Resize { .. } => 0,
+                Edit::Batch(edits) => edits.iter().map(Edit::memory_size).sum(),
+            }
+    }
+}
+
+/// Undo/redo stacks of `Edit`s, capped by memory use rather than step count.
+#[derive(Debug)]
+pub struct History {
+    undo_stack: VecDeque<Edit>,
+    redo_stack: Vec<Edit>,
+    budget_bytes: usize,
+    used_bytes: usize,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new(DEFAULT_BUDGET_BYTES)
+    }
+}
+
+impl History {
+    pub fn new(budget_bytes: usize) -> Self {
+        Self {
+            undo_stack: VecDeque::new(),
+            redo_stack: Vec::new(),
+            budget_bytes,
+            used_bytes: 0,
+        }
+    }
+
+    /// Applies `edit` to `document` and records it as the newest undo step.
+    pub fn execute(&mut self, document: &mut Document, edit: Edit) {
+        if edit.is_empty() {
+            return;
+        }
+        edit.apply(document);
+
+        // A new action invalidates everything that was undone before it.
+        for undone in self.redo_stack.drain(..) {
+            self.used_bytes -= undone.memory_size();
+        }
+        self.used_bytes += edit.memory_size();
+        self.undo_stack.push_back(edit);
+        self.enforce_budget();
+    }
+
+    pub fn undo(&mut self, document: &mut Document) -> bool {
+        match self.undo_stack.pop_back() {
+            Some(edit) => {
+                edit.revert(document);
+                self.redo_stack.push(edit);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn redo(&mut self, document: &mut Document) -> bool {
+        match self.redo_stack.pop() {
+            Some(edit) => {
+                edit.apply(document);
+                self.undo_stack.push_back(edit);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+        self.used_bytes = 0;
+    }
+
+    pub fn budget_bytes(&self) -> usize {
+        self.budget_bytes
+    }
+
+    pub fn set_budget_bytes(&mut self, budget_bytes: usize) {
+        self.budget_bytes = budget_bytes;
+        self.enforce_budget();
+    }
+
+    /// Bytes currently held by undo and redo entries.
+    pub fn memory_usage(&self) -> usize {
+        self.used_bytes
+    }
+
+    /// Drops the oldest undo steps, then the redo steps furthest from the
+    /// current state, until the budget is met. The newest undo step (or the
+    /// next redo step if there is none) is always kept so the last action
+    /// can be undone even if it is huge.
+    fn enforce_budget(&mut self) {
+        while self.used_bytes > self.budget_bytes && self.undo_stack.len() + self.redo_stack.len() > 1 {
+            let dropped = if self.undo_stack.len() > 1 || self.redo_stack.is_empty() {
+                self.undo_stack.pop_front()
+            } else {
+                Some(self.redo_stack.remove(0))
+            };
+            if let Some(dropped) = dropped {
+                self.used_bytes -= dropped.memory_size();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::Renderer2D;
+    use crate::engine::tests::with_headless;
+    use crate::tools::brush::BrushStroke;
+    use crate::tools::ellipse::EllipseStroke;
+    use crate::tools::eraser::EraseStroke;
+
+    fn stroke(red: f32) -> Stroke {
+        Stroke::Brush(BrushStroke::new(0.01, [red, 0.0, 0.0, 1.0]))
+    }
+
+    fn strokes(document: &Document) -> Vec<Stroke> {
+        document.layer(0).strokes().to_vec()
+    }
+
+    fn insert(index: usize, stroke: Stroke) -> Edit {
+        Edit::Insert { layer: 0, index, stroke }
+    }
+
+    #[test]
+    fn undoes_and_redoes_edits() {
+        let (first, second) = (stroke(0.25), stroke(0.75));
+        let mut document = Document::new();
+        let mut history = History::default();
+        assert!(!history.can_undo() && !history.can_redo());
+
+        history.execute(&mut document, insert(0, first.clone()));
+        history.execute(&mut document, insert(1, second.clone()));
+        assert_eq!(strokes(&document), vec![first.clone(), second.clone()]);
+
+        assert!(history.undo(&mut document));
+        assert_eq!(strokes(&document), vec![first.clone()]);
+        assert!(history.can_redo());
+        assert!(history.undo(&mut document));
+        assert!(strokes(&document).is_empty());
+        assert!(!history.undo(&mut document));
+
+        assert!(history.redo(&mut document));
+        assert!(history.redo(&mut document));
+        assert_eq!(strokes(&document), vec![first, second]);
+        assert!(!history.redo(&mut document));
+    }
+
+    #[test]
+    fn new_edits_clear_the_redo_stack() {
+        let (first, second) = (stroke(0.25), stroke(0.75));
+        let mut document = Document::new();
+        let mut history = History::default();
+        history.execute(&mut document, insert(0, first));
+        history.undo(&mut document);
+        let undone = history.memory_usage();
+
+        history.execute(&mut document, insert(0, second.clone()));
+        assert!(!history.can_redo());
+        assert!(!history.redo(&mut document));
+        assert_eq!(strokes(&document), vec![second]);
+        assert_eq!(history.memory_usage(), undone);
+    }
+
+    #[test]
+    fn batches_are_one_step() {
+        let mut document = Document::new();
+        let mut history = History::default();
+        history.execute(&mut document, insert(0, stroke(0.25)));
+        history.execute(&mut document, insert(1, stroke(0.75)));
+        let original = strokes(&document);
+
+        let clear = Edit::clear(&document);
+        history.execute(&mut document, clear);
+        assert!(strokes(&document).is_empty());
+        history.undo(&mut document);
+        assert_eq!(strokes(&document), original);
+        history.redo(&mut document);
+        assert!(strokes(&document).is_empty());
+        assert!(!history.can_redo());
+    }
+
+    #[test]
+    fn skips_empty_edits() {
+        let mut document = Document::new();
+        let mut history = History::default();
+        history.execute(&mut document, Edit::Batch(Vec::new()));
+        history.execute(&mut document, Edit::Batch(vec![Edit::Batch(Vec::new())]));
+        assert!(!history.can_undo());
+        assert_eq!(history.memory_usage(), 0);
+    }
+
+    #[test]
+    fn evicts_the_oldest_steps_over_budget() {
+        let step = insert(0, stroke(0.5)).memory_size();
+        let mut document = Document::new();
+        let mut history = History::new(step * 3);
+        for index in 0..5 {
+            history.execute(&mut document, insert(index, stroke(0.5)));
+        }
+        assert_eq!(history.memory_usage(), step * 3);
+        for _ in 0..3 {
+            assert!(history.undo(&mut document));
+        }
+        assert!(!history.undo(&mut document));
+        assert_eq!(strokes(&document).len(), 2);
+
+        // The newest step stays even if it alone is over budget.
+        history.clear();
+        history.set_budget_bytes(1);
+        history.execute(&mut document, insert(0, stroke(0.5)));
+        history.execute(&mut document, insert(0, stroke(0.5)));
+        assert_eq!(history.memory_usage(), step);
+        assert!(history.undo(&mut document));
+        assert!(!history.undo(&mut document));
+    }
+
+    #[test]
+    fn evicts_the_furthest_redo_steps_over_budget() {
+        let step = insert(0, stroke(0.5)).memory_size();
+        let mut document = Document::new();
+        let mut history = History::new(step * 5);
+        for index in 0..5 {
+            history.execute(&mut document, insert(index, stroke(0.5)));
+        }
+        for _ in 0..3 {
+            assert!(history.undo(&mut document));
+        }
+        history.set_budget_bytes(step * 2);
+        assert_eq!(history.memory_usage(), step * 2);
+
+        // The last undo step and the next redo step are left.
+        assert!(history.redo(&mut document));
+        assert!(!history.redo(&mut document));
+        assert_eq!(strokes(&document).len(), 3);
+        for _ in 0..2 {
+            assert!(history.undo(&mut document));
+        }
+        assert!(!history.undo(&mut document));
+        assert_eq!(strokes(&document).len(), 1);
+
+        // With everything undone, the next redo step stays.
+        history.set_budget_bytes(1);
+        assert_eq!(history.memory_usage(), step);
+        assert!(history.redo(&mut document));
+        assert!(!history.redo(&mut document));
+    }
+
+    const SIZE: u32 = 64;
+
+    fn ellipse(x: f32, y: f32, color: [f32; 4]) -> Stroke {
+        Stroke::Ellipse(EllipseStroke {
+            center: Vec2::new(x, y),
+            radii: Vec2::splat(0.03),
+            filled: true,
+            thickness: 0.0,
+            color,
+            blend_mode: BlendMode::Normal,
+        })
+    }
+
+    fn erase(x: f32, y: f32) -> Stroke {
+        Stroke::Erase(EraseStroke {
+            points: vec![Vec2::new(x - 0.01, y), Vec2::new(x + 0.01, y)],
+            size: 0.01,
+        })
+    }
+
+    /// Two layers, with a red circle below and an overlapping translucent
+    /// blue one and an erased green one above.
+    fn two_layers() -> Document {
+        let mut document = Document::from_layers(vec![
+            Layer::new("Below").with_strokes(vec![ellipse(-0.03, 0.0, [1.0, 0.0, 0.0, 1.0])]),
+            Layer::new("Above").with_strokes(vec![
+                ellipse(0.0, 0.0, [0.0, 0.0, 1.0, 0.5]),
+                ellipse(0.06, -0.06, [0.0, 1.0, 0.0, 1.0]),
+                erase(0.06, -0.06),
+            ]),
+        ]);
+        document.set_size([SIZE, SIZE]);
+        document
+    }
+
+    fn render(renderer: &Renderer2D, document: &Document) -> Vec<u8> {
+        renderer.render_to_pixels(&document.tessellate(), document.view_matrix(), SIZE, SIZE, Some(wgpu::Color::WHITE))
+    }
+
+    /// Merges the top layer of `document` down and checks the picture
+    /// doesn't change, allowing for rounding in the layer textures.
+    fn assert_merge_keeps_picture(document: Document) {
+        with_headless(1, 1, |renderer| {
+            let before = render(renderer, &document);
+            let mut merged = document.clone();
+            Edit::merge_down(&document, 1).expect("mergeable").apply(&mut merged);
+            assert_eq!(merged.layers().len(), 1);
+            let after = render(renderer, &merged);
+
+            let worst = before.iter().zip(&after).map(|(a, b)| a.abs_diff(*b)).max().unwrap();
+            assert!(worst <= 1, "merging changed a channel by {}", worst);
+        });
+    }
+
+    #[test]
+    fn merge_down_keeps_the_picture() {
+        assert_merge_keeps_picture(two_layers());
+    }
+
+    #[test]
+    fn merge_down_drops_hidden_layers() {
+        let mut document = two_layers();
+        document.layer_mut(1).visible = false;
+        let mut merged = document.clone();
+        Edit::merge_down(&document, 1).unwrap().apply(&mut merged);
+        assert_eq!(merged.layer(0).strokes(), document.layer(0).strokes());
+        assert_merge_keeps_picture(document);
+    }
+
+    #[test]
+    fn merge_down_refuses_to_change_the_picture() {
+        let mut erasing_below = two_layers();
+        erasing_below.layer_mut(1).push(erase(-0.03, 0.0));
+        assert_eq!(Edit::merge_down(&erasing_below, 1), None);
+
+        let mut translucent = two_layers();
+        translucent.layer_mut(1).opacity = 0.5;
+        assert_eq!(Edit::merge_down(&translucent, 1), None);
+
+        let mut multiply = two_layers();
+        multiply.layer_mut(0).blend_mode = BlendMode::Multiply;
+        assert_eq!(Edit::merge_down(&multiply, 1), None);
+
+        let mut hidden_below = two_layers();
+        hidden_below.layer_mut(0).visible = false;
+        assert_eq!(Edit::merge_down(&hidden_below, 1), None);
+    }
+
+    #[test]
+    fn layer_changes_are_undone_before_removals() {
+        let mut document = two_layers();
+        let mut history = History::default();
+        let before = document.layer(1).properties();
+        let after = LayerProperties {
+            name: "Renamed".into(),
+            visible: false,
+            opacity: 0.25,
+            blend_mode: BlendMode::Screen,
+            locked: true,
+        };
+        history.execute(&mut document, Edit::ChangeLayer { index: 1, before: before.clone(), after: after.clone() });
+        let layer = document.layer(1).clone();
+        history.execute(&mut document, Edit::RemoveLayer { index: 1, layer });
+
+        history.undo(&mut document);
+        assert_eq!(document.layer(1).properties(), after);
+        history.undo(&mut document);
+        assert_eq!(document.layer(1).properties(), before);
+        history.redo(&mut document);
+        assert_eq!(document.layer(1).properties(), after);
+    }
+}
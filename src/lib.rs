@@ -0,0 +1,9 @@
+pub mod camera;
+pub mod document;
+pub mod engine;
+pub mod export;
+pub mod history;
+pub mod project;
+pub mod spatial;
+pub mod tessellation;
+pub mod tools;
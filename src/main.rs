@@ -3,33 +3,165 @@ use winit::{
     event_loop::{ControlFlow, EventLoop},
     window::WindowBuilder,
 };
+use std::time::Instant;
 use glam::Vec2;
-use std::collections::VecDeque;
+use egui_winit::State as EguiWinitState;
 
-mod engine;
-mod tools;
+use renderer_2d::camera::Camera;
+use renderer_2d::document::{area_view_matrix, Document, Layer, LayerProperties, PIXELS_PER_UNIT};
+use renderer_2d::spatial;
+use renderer_2d::engine::{self, BlendMode};
+use renderer_2d::export::{self, PngOptions, SvgOptions};
+use renderer_2d::history::{Edit, History};
+use renderer_2d::project::{Project, ToolSettings};
+use renderer_2d::tessellation::{LineCap, LineJoin};
+use renderer_2d::document::Stroke;
+use renderer_2d::tools::{
+    Tool, ActiveTool, ToolType, ToolKey, InputSample, Modifiers, brush::{self, Brush}, bucket::Bucket, ellipse::Ellipse,
+    eraser::{Eraser, EraserMode}, line::{Line, LineStyle}, pen::Pen, polygon::Polygon,
+    rectangle::{Rectangle, RectangleStyle}, select::Select,
+    stabilizer::{Stabilizer, StabilizerKind}, pressure::{PressureCurve, VelocityPressure},
+};
+
+/// Furthest the cursor can move, in canvas units, between press and release
+/// for the release to count as a click.
+const CLICK_DISTANCE: f32 = 0.01;
+
+/// Longest time, in seconds, between two clicks of a double-click.
+const DOUBLE_CLICK_TIME: f64 = 0.4;
+
+/// Distance, in canvas units, within which Alt-clicking a path picks it up.
+const PICK_TOLERANCE: f32 = 0.01;
+
+/// Zoom factor for one step of the mouse wheel.
+const WHEEL_ZOOM_STEP: f32 = 1.2;
+
+/// Pixels of smooth scrolling that count as one wheel step.
+const PIXELS_PER_WHEEL_STEP: f32 = 40.0;
+
+/// How far, as a fraction of the view's size, the scene reaches past each
+/// edge of the view, so small pans don't rebuild it.
+const CULL_MARGIN: f32 = 0.5;
+
+/// The scene is rebuilt once it's this many times wider or taller than
+/// the view, after zooming in.
+const CULL_REBUILD_RATIO: f32 = 4.0;
 
-use tools::{Tool, ActiveTool, brush::Brush, eraser::Eraser, line::Line, rectangle::Rectangle};
+/// Largest width or height the document dialogs accept.
+const MAX_DOCUMENT_SIDE: u32 = 16384;
 
-#[derive(Debug, PartialEq, Clone, Copy)]
-enum ToolType {
-    Brush,
-    Eraser,
-    Line,
-    Rectangle,
+/// Sizes offered in the New Canvas dialog.
+const CANVAS_PRESETS: [(&str, [u32; 2]); 5] = [
+    ("800 × 600", [800, 600]),
+    ("HD 1280 × 720", [1280, 720]),
+    ("Full HD 1920 × 1080", [1920, 1080]),
+    ("Square 1024 × 1024", [1024, 1024]),
+    ("A4 150 dpi 1240 × 1754", [1240, 1754]),
+];
+
+/// The dialogs that change the document as a whole.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum DocumentDialog {
+    NewCanvas,
+    CanvasSize,
+    ImageSize,
+}
+
+/// What the open document dialog is editing.
+struct DocumentSettings {
+    dialog: Option<DocumentDialog>,
+    size: [u32; 2],
+    background: [f32; 4],
+    transparent: bool,
+    /// The part of the canvas that stays put in Canvas Size, from -1 to 1
+    /// on each axis.
+    anchor: Vec2,
+    /// Whether Image Size stretches the drawing or only the canvas.
+    scale_content: bool,
+    keep_proportions: bool,
+    infinite: bool,
+}
+
+impl DocumentSettings {
+    fn new() -> Self {
+        Self {
+            dialog: None,
+            size: renderer_2d::document::DEFAULT_SIZE,
+            background: renderer_2d::document::DEFAULT_BACKGROUND,
+            transparent: false,
+            anchor: Vec2::ZERO,
+            scale_content: true,
+            keep_proportions: true,
+            infinite: false,
+        }
+    }
+
+    /// Opens `dialog`, starting from the document's current settings.
+    fn open(&mut self, dialog: DocumentDialog, document: &Document) {
+        self.dialog = Some(dialog);
+        self.size = document.size();
+        self.transparent = document.background.is_none();
+        self.infinite = document.infinite;
+        if let Some(background) = document.background {
+            self.background = background;
+        }
+    }
 }
 
 struct Canvas {
     current_tool: ActiveTool,
     tool_type: ToolType,
-    persistent_vertices: Vec<engine::Vertex>,
+    document: Document,
+    active_layer: usize,
     current_vertices: Vec<engine::Vertex>,
     current_color: [f32; 4],
     brush_size: f32,
+    brush_spacing: f32,
+    stabilizer: Stabilizer,
+    width_curve: PressureCurve,
+    opacity_curve: PressureCurve,
+    blend_mode: BlendMode,
+    eraser_mode: EraserMode,
+    fill_shapes: bool,
+    close_polygons: bool,
+    // Fill of rectangles, or `None` to fill with the current color.
+    fill_color: Option<[f32; 4]>,
+    line_style: LineStyle,
+    rectangle_style: RectangleStyle,
+    corner_radius: f32,
+    fill_tolerance: f32,
+    // Whether the bucket finds regions on all visible layers rather than
+    // just the active one.
+    fill_all_layers: bool,
+    // Keyboard modifiers, stamped on every sample passed to the tools.
+    modifiers: Modifiers,
+    // The latest sample while drawing, replayed when modifiers change.
+    last_sample: Option<InputSample>,
+    // Where the current press started, to tell clicks from drags.
+    press: Option<InputSample>,
+    // The previous click, to detect double-clicks.
+    last_click: Option<InputSample>,
+    // A committed path taken out of the document while the pen edits it,
+    // with its layer and index, put back when the edit ends.
+    edited_stroke: Option<(usize, usize, Stroke)>,
+    // Removals made by the object eraser during the current drag, already
+    // applied to the document and committed as one undo step on release or
+    // before anything else changes the document.
+    erased_strokes: Vec<Edit>,
+    // A layer whose properties are being changed in the layers panel, with
+    // its properties from before, committed as one undo step when done.
+    layer_change: Option<(usize, LayerProperties)>,
     is_drawing: bool,
+    // The document changed in a way that needs the whole scene rebuilt.
     needs_update: bool,
-    history: VecDeque<Vec<engine::Vertex>>,
-    max_history: usize,
+    // Strokes added on top of their layer since the last upload, as layer
+    // and index. They're uploaded on their own unless the scene is rebuilt.
+    appended_strokes: Vec<(usize, usize)>,
+    // The shape in progress changed.
+    preview_changed: bool,
+    // Canvas area the scene was built for; strokes outside it are culled.
+    scene_area: Option<(Vec2, Vec2)>,
+    history: History,
 }
 
 impl Canvas {
@@ -37,14 +169,38 @@ impl Canvas {
         Self {
             current_tool: ActiveTool::Brush(Brush::new(0.01, [0.0, 0.0, 0.0, 1.0])),
             tool_type: ToolType::Brush,
-            persistent_vertices: Vec::new(),
+            document: Document::new(),
+            active_layer: 0,
             current_vertices: Vec::new(),
             current_color: [0.0, 0.0, 0.0, 1.0],
             brush_size: 0.01,
+            brush_spacing: brush::DEFAULT_SPACING,
+            stabilizer: Stabilizer::default(),
+            width_curve: PressureCurve::default(),
+            opacity_curve: PressureCurve::default(),
+            blend_mode: BlendMode::Normal,
+            eraser_mode: EraserMode::Pixel,
+            fill_shapes: false,
+            close_polygons: true,
+            fill_color: None,
+            line_style: LineStyle::default(),
+            rectangle_style: RectangleStyle::Fill,
+            corner_radius: 0.0,
+            fill_tolerance: 0.1,
+            fill_all_layers: false,
+            modifiers: Modifiers::default(),
+            last_sample: None,
+            press: None,
+            last_click: None,
+            edited_stroke: None,
+            erased_strokes: Vec::new(),
+            layer_change: None,
             is_drawing: false,
             needs_update: false,
-            history: VecDeque::with_capacity(20),
-            max_history: 20,
+            appended_strokes: Vec::new(),
+            preview_changed: false,
+            scene_area: None,
+            history: History::default(),
         }
     }
 
@@ -52,19 +208,162 @@ impl Canvas {
         if tool_type == self.tool_type {
             return;
         }
+        self.cancel_shape();
 
         self.tool_type = tool_type;
         self.current_tool = match tool_type {
             ToolType::Brush => ActiveTool::Brush(Brush::new(self.brush_size, self.current_color)),
-            ToolType::Eraser => ActiveTool::Eraser(Eraser::new(self.brush_size)),
-            ToolType::Line => ActiveTool::Line(Line::new(self.brush_size, self.current_color)),
-            ToolType::Rectangle => ActiveTool::Rectangle(Rectangle::new(self.current_color)),
+            ToolType::Eraser => ActiveTool::Eraser(Eraser::new(self.brush_size, self.eraser_mode)),
+            ToolType::Line => ActiveTool::Line(Line::new(self.brush_size, self.current_color, self.line_style)),
+            ToolType::Rectangle => {
+                let mut rectangle = Rectangle::new(
+                    self.brush_size,
+                    self.current_color,
+                    self.fill_color.unwrap_or(self.current_color),
+                    self.rectangle_style,
+                );
+                rectangle.set_corner_radius(self.corner_radius);
+                ActiveTool::Rectangle(rectangle)
+            }
+            ToolType::Ellipse => {
+                ActiveTool::Ellipse(Ellipse::new(self.brush_size, self.current_color, self.fill_shapes))
+            }
+            ToolType::Pen => ActiveTool::Pen(Pen::new(self.brush_size, self.current_color, self.line_style)),
+            ToolType::Bucket => {
+                ActiveTool::Bucket(Bucket::new(self.current_color, self.fill_tolerance))
+            }
+            ToolType::Select => ActiveTool::Select(Select::new()),
+            ToolType::Polygon => ActiveTool::Polygon(Polygon::new(
+                self.brush_size,
+                self.current_color,
+                self.close_polygons,
+                self.fill_shapes,
+            )),
         };
+        self.current_tool.set_blend_mode(self.blend_mode);
+        self.current_vertices.clear();
+        self.preview_changed = true;
+        if let ActiveTool::Brush(brush) = &mut self.current_tool {
+            brush.set_spacing(self.brush_spacing);
+            brush.set_stabilizer(self.stabilizer);
+            brush.set_pressure_curves(self.width_curve.clone(), self.opacity_curve.clone());
+        }
     }
 
     fn set_color(&mut self, color: [f32; 4]) {
         self.current_color = color;
         self.current_tool.set_color(color);
+        if self.fill_color.is_none() {
+            if let ActiveTool::Rectangle(rectangle) = &mut self.current_tool {
+                rectangle.set_fill_color(color);
+            }
+        }
+    }
+
+    /// Changes the hue of the current color but keeps its alpha.
+    fn set_rgb(&mut self, [r, g, b]: [f32; 3]) {
+        self.set_color([r, g, b, self.current_color[3]]);
+    }
+
+    fn set_blend_mode(&mut self, blend_mode: BlendMode) {
+        self.blend_mode = blend_mode;
+        self.current_tool.set_blend_mode(blend_mode);
+    }
+
+    fn set_brush_spacing(&mut self, spacing: f32) {
+        self.brush_spacing = spacing;
+        if let ActiveTool::Brush(brush) = &mut self.current_tool {
+            brush.set_spacing(spacing);
+        }
+    }
+
+    fn set_stabilizer(&mut self, stabilizer: Stabilizer) {
+        self.stabilizer = stabilizer;
+        if let ActiveTool::Brush(brush) = &mut self.current_tool {
+            brush.set_stabilizer(stabilizer);
+        }
+    }
+
+    fn set_pressure_curves(&mut self, width: PressureCurve, opacity: PressureCurve) {
+        self.width_curve = width;
+        self.opacity_curve = opacity;
+        if let ActiveTool::Brush(brush) = &mut self.current_tool {
+            brush.set_pressure_curves(self.width_curve.clone(), self.opacity_curve.clone());
+        }
+    }
+
+    fn set_fill_shapes(&mut self, fill: bool) {
+        self.fill_shapes = fill;
+        match &mut self.current_tool {
+            ActiveTool::Ellipse(ellipse) => ellipse.set_filled(fill),
+            ActiveTool::Polygon(polygon) => polygon.set_filled(fill),
+            _ => {}
+        }
+        self.refresh_preview();
+    }
+
+    fn set_line_style(&mut self, style: LineStyle) {
+        self.line_style = style;
+        match &mut self.current_tool {
+            ActiveTool::Line(line) => line.set_style(style),
+            ActiveTool::Pen(pen) => pen.set_style(style),
+            _ => {}
+        }
+        self.refresh_preview();
+    }
+
+    fn set_fill_color(&mut self, color: Option<[f32; 4]>) {
+        self.fill_color = color;
+        if let ActiveTool::Rectangle(rectangle) = &mut self.current_tool {
+            rectangle.set_fill_color(color.unwrap_or(self.current_color));
+        }
+    }
+
+    fn set_rectangle_style(&mut self, style: RectangleStyle) {
+        self.rectangle_style = style;
+        if let ActiveTool::Rectangle(rectangle) = &mut self.current_tool {
+            rectangle.set_style(style);
+        }
+    }
+
+    fn set_corner_radius(&mut self, radius: f32) {
+        self.corner_radius = radius;
+        if let ActiveTool::Rectangle(rectangle) = &mut self.current_tool {
+            rectangle.set_corner_radius(radius);
+        }
+    }
+
+    fn set_close_polygons(&mut self, closed: bool) {
+        self.close_polygons = closed;
+        if let ActiveTool::Polygon(polygon) = &mut self.current_tool {
+            polygon.set_closed(closed);
+        }
+        self.refresh_preview();
+    }
+
+    fn set_fill_tolerance(&mut self, tolerance: f32) {
+        self.fill_tolerance = tolerance;
+        if let ActiveTool::Bucket(bucket) = &mut self.current_tool {
+            bucket.set_tolerance(tolerance);
+        }
+    }
+
+    /// Updates the held modifiers. Shape previews follow right away, without
+    /// waiting for the cursor to move.
+    fn set_modifiers(&mut self, modifiers: Modifiers) {
+        self.modifiers = modifiers;
+        if self.tool_type.is_shape() {
+            if let Some(sample) = self.last_sample {
+                self.on_mouse_move(sample);
+            }
+        }
+    }
+
+    fn set_eraser_mode(&mut self, mode: EraserMode) {
+        self.eraser_mode = mode;
+        if self.tool_type == ToolType::Eraser {
+            self.current_tool = ActiveTool::Eraser(Eraser::new(self.brush_size, mode));
+        }
     }
 
     fn set_brush_size(&mut self, size: f32) {
@@ -72,58 +371,748 @@ impl Canvas {
         self.current_tool.set_size(size);
     }
 
-    fn on_mouse_down(&mut self, position: Vec2) {
+    fn can_draw(&self) -> bool {
+        let layer = self.document.layer(self.active_layer);
+        // Selecting doesn't change the layer.
+        matches!(self.current_tool, ActiveTool::Select(_)) || (layer.visible && !layer.locked)
+    }
+
+    fn on_mouse_down(&mut self, mut sample: InputSample) {
+        if !self.can_draw() {
+            return;
+        }
+        sample.modifiers = self.modifiers;
+        if self.pick_up_path(sample) {
+            return;
+        }
+        self.last_sample = Some(sample);
+        self.press = Some(sample);
         self.is_drawing = true;
-        self.current_tool.on_mouse_down(position);
+        self.current_tool.on_mouse_down(sample);
+        self.erase_objects();
         self.current_vertices = self.current_tool.get_vertices();
-        self.needs_update = true;
+        self.preview_changed = true;
     }
 
-    fn on_mouse_move(&mut self, position: Vec2) {
-        if self.is_drawing {
-            self.current_tool.on_mouse_move(position);
+    /// Forwards cursor movement while drawing, and also between clicks for
+    /// tools that build a shape over several of them.
+    fn on_mouse_move(&mut self, mut sample: InputSample) {
+        if self.is_drawing || self.current_tool.in_progress() {
+            sample.modifiers = self.modifiers;
+            self.last_sample = Some(sample);
+            self.current_tool.on_mouse_move(sample);
+            self.erase_objects();
             self.current_vertices = self.current_tool.get_vertices();
-            self.needs_update = true;
+            self.preview_changed = true;
         }
     }
 
-    fn on_mouse_up(&mut self, position: Vec2) {
+    fn on_mouse_up(&mut self, mut sample: InputSample) {
         if self.is_drawing {
-            let new_vertices = self.current_tool.on_mouse_up(position);
-            self.persistent_vertices.extend(new_vertices);
+            sample.modifiers = self.modifiers;
+            self.last_sample = None;
+            if let Some(stroke) = self.current_tool.on_mouse_up(sample) {
+                self.commit(stroke);
+            }
+            if let Some(stroke) = self.detect_click(sample) {
+                self.commit(stroke);
+            }
+            self.finish_erasing();
+            self.is_drawing = false;
+            self.refresh_preview();
+        }
+        self.is_drawing = false;
+        self.press = None;
+    }
+
+    /// Passes a release close to where the press started on to the tool as
+    /// a click, and as a double-click if it follows another click quickly.
+    fn detect_click(&mut self, sample: InputSample) -> Option<Stroke> {
+        let press = self.press?;
+        if press.position.distance(sample.position) > CLICK_DISTANCE {
+            self.last_click = None;
+            return None;
+        }
+        let double = self.last_click.is_some_and(|last| {
+            sample.time - last.time <= DOUBLE_CLICK_TIME
+                && last.position.distance(sample.position) <= CLICK_DISTANCE
+        });
+
+        if let Some(stroke) = self.current_tool.on_click(sample) {
+            self.last_click = None;
+            return Some(stroke);
+        }
+        if double {
+            self.last_click = None;
+            return self.current_tool.on_double_click(sample);
+        }
+        self.last_click = Some(sample);
+        None
+    }
+
+    fn on_key(&mut self, key: ToolKey) {
+        if let Some(stroke) = self.current_tool.on_key(key) {
+            self.commit(stroke);
+        }
+        self.refresh_preview();
+    }
+
+    /// Adds a finished stroke to the active layer as an undoable step, or
+    /// puts an edited path back in place of the original.
+    fn commit(&mut self, stroke: Stroke) {
+        if let Some((layer, index, before)) = self.edited_stroke.take() {
+            // Restore the original so history records the edit as a swap.
+            Edit::Insert { layer, index, stroke: before.clone() }.apply(&mut self.document);
+            self.history.execute(&mut self.document, Edit::Replace { layer, index, before, after: stroke });
+            self.needs_update = true;
+            return;
+        }
+        let layer = self.active_layer;
+        let index = self.document.layer(layer).strokes().len();
+        self.history.execute(&mut self.document, Edit::Insert { layer, index, stroke });
+        self.appended_strokes.push((layer, index));
+    }
+
+    /// With the pen idle, Alt-clicking a path in the active layer opens it
+    /// for editing. Returns true if a path was picked up.
+    fn pick_up_path(&mut self, sample: InputSample) -> bool {
+        let ActiveTool::Pen(pen) = &mut self.current_tool else {
+            return false;
+        };
+        if !sample.modifiers.alt || pen.in_progress() {
+            return false;
+        }
+        let layer = self.active_layer;
+        let strokes = self.document.layer(layer).strokes();
+        let picked = strokes.iter().enumerate().rev().find_map(|(index, stroke)| match stroke {
+            Stroke::Bezier(path) if stroke.hit_test(sample.position, PICK_TOLERANCE) => Some((index, path.clone())),
+            _ => None,
+        });
+        let Some((index, path)) = picked else {
+            return false;
+        };
+        let stroke = Stroke::Bezier(path.clone());
+        Edit::Remove { layer, index, stroke: stroke.clone() }.apply(&mut self.document);
+        self.edited_stroke = Some((layer, index, stroke));
+        self.needs_update = true;
+        pen.edit(path);
+        self.refresh_preview();
+        true
+    }
+
+    /// Drops the shape in progress, putting back any path being edited,
+    /// and commits any layer change or erasing in progress.
+    fn cancel_shape(&mut self) {
+        self.finish_layer_change();
+        self.finish_erasing();
+        if self.current_tool.in_progress() {
+            self.current_tool.on_key(ToolKey::Escape);
+        }
+        self.refresh_preview();
+    }
+
+    /// The document with any path being edited as it was before the edit,
+    /// for saving and exporting.
+    fn committed_document(&self) -> Document {
+        let mut document = self.document.clone();
+        if let Some((layer, index, stroke)) = &self.edited_stroke {
+            Edit::Insert { layer: *layer, index: *index, stroke: stroke.clone() }.apply(&mut document);
+        }
+        document
+    }
+
+    /// Shows the tool's shape in progress, or nothing once it is done. An
+    /// edit that ended without a result puts the original path back.
+    fn refresh_preview(&mut self) {
+        if !self.current_tool.in_progress() {
+            if let Some((layer, index, stroke)) = self.edited_stroke.take() {
+                Edit::Insert { layer, index, stroke }.apply(&mut self.document);
+                self.needs_update = true;
+            }
+        }
+        if self.is_drawing || self.current_tool.in_progress() {
+            self.current_vertices = self.current_tool.get_vertices();
+        } else {
             self.current_vertices.clear();
-            self.save_state();
+        }
+        self.preview_changed = true;
+    }
+
+    /// With the bucket active, fills the region around the last click on
+    /// a raster of the active layer or of every visible layer and commits
+    /// the fill. Only the part of the document in the window is rasterized,
+    /// at no more than one pixel per document pixel or per screen pixel, so
+    /// the raster is never larger than the window.
+    fn flood_fill(&mut self, renderer: &engine::Renderer2D, visible: (Vec2, Vec2), zoom: f32) {
+        let ActiveTool::Bucket(bucket) = &mut self.current_tool else {
+            return;
+        };
+        let Some(seed) = bucket.take_seed() else {
+            return;
+        };
+
+        let document = if self.fill_all_layers {
+            self.committed_document()
+        } else {
+            // On its own, so the region follows the layer's own colors.
+            let mut layer = self.document.layer(self.active_layer).clone();
+            layer.opacity = 1.0;
+            layer.blend_mode = BlendMode::Normal;
+            let mut document = Document::from_layers(vec![layer]);
+            document.set_size(self.document.size());
+            document.infinite = self.document.infinite;
+            document
+        };
+        let Some(area) = spatial::intersection(document.bounds(), visible) else {
+            return;
+        };
+        let max = renderer.max_texture_size() as f32;
+        let size = ((area.1 - area.0) * PIXELS_PER_UNIT * zoom.min(1.0)).ceil().clamp(Vec2::ONE, Vec2::splat(max));
+        let (width, height) = (size.x as u32, size.y as u32);
+        let pixels = renderer.render_to_pixels(
+            &document.tessellate_in(Some(area)),
+            area_view_matrix(area),
+            width,
+            height,
+            None,
+        );
+        let ActiveTool::Bucket(bucket) = &self.current_tool else {
+            return;
+        };
+        if let Some(stroke) = bucket.fill(&pixels, width, height, area, seed) {
+            self.commit(stroke);
+        }
+    }
+
+    /// With the object eraser active, removes strokes in the active layer
+    /// that the latest segment of the eraser path touches.
+    fn erase_objects(&mut self) {
+        let ActiveTool::Eraser(eraser) = &self.current_tool else {
+            return;
+        };
+        if eraser.mode() != EraserMode::Object {
+            return;
+        }
+        let path = eraser.path();
+        let (Some(&to), Some(&from)) = (path.last(), path.iter().rev().nth(1).or(path.last())) else {
+            return;
+        };
+
+        let layer = self.active_layer;
+        let touched = self.document.layer(layer).strokes_touched(from, to, self.brush_size);
+        // Highest index first, so the remaining indices stay valid.
+        for index in touched.into_iter().rev() {
+            let stroke = self.document.layer(layer).strokes()[index].clone();
+            let edit = Edit::Remove { layer, index, stroke };
+            edit.apply(&mut self.document);
+            self.erased_strokes.push(edit);
             self.needs_update = true;
         }
-        self.is_drawing = false;
     }
 
-    fn save_state(&mut self) {
-        if self.history.len() >= self.max_history {
-            self.history.pop_front();
+    /// Commits the strokes the object eraser removed so far as one undo
+    /// step. A drag that goes on after this starts a new step.
+    fn finish_erasing(&mut self) {
+        if self.erased_strokes.is_empty() {
+            return;
         }
-        self.history.push_back(self.persistent_vertices.clone());
+        // Undo the live removals so history can apply them as one step.
+        let edits = std::mem::take(&mut self.erased_strokes);
+        edits.iter().rev().for_each(|edit| edit.revert(&mut self.document));
+        self.history.execute(&mut self.document, Edit::Batch(edits));
+    }
+
+    fn can_undo(&self) -> bool {
+        self.history.can_undo()
+    }
+
+    fn can_redo(&self) -> bool {
+        self.history.can_redo()
     }
 
     fn undo(&mut self) {
-        if let Some(previous_state) = self.history.pop_back() {
-            self.persistent_vertices = previous_state;
+        self.cancel_shape();
+        if self.history.undo(&mut self.document) {
+            self.clamp_active_layer();
             self.needs_update = true;
         }
     }
 
+    fn redo(&mut self) {
+        self.cancel_shape();
+        if self.history.redo(&mut self.document) {
+            self.clamp_active_layer();
+            self.needs_update = true;
+        }
+    }
+
+    fn clamp_active_layer(&mut self) {
+        self.active_layer = self.active_layer.min(self.document.layers().len() - 1);
+    }
+
+    fn add_layer(&mut self) {
+        self.cancel_shape();
+        let index = self.active_layer + 1;
+        let layer = Layer::new(self.document.next_layer_name());
+        self.history.execute(&mut self.document, Edit::InsertLayer { index, layer });
+        self.active_layer = index;
+        self.needs_update = true;
+    }
+
+    fn delete_layer(&mut self) {
+        if self.document.layers().len() <= 1 {
+            return;
+        }
+        self.cancel_shape();
+        let index = self.active_layer;
+        let layer = self.document.layer(index).clone();
+        self.history.execute(&mut self.document, Edit::RemoveLayer { index, layer });
+        self.active_layer = index.saturating_sub(1);
+        self.needs_update = true;
+    }
+
+    /// Moves the active layer up (towards the top) or down by one step.
+    fn move_layer(&mut self, up: bool) {
+        let from = self.active_layer;
+        let to = if up { from + 1 } else { from.wrapping_sub(1) };
+        if to >= self.document.layers().len() {
+            return;
+        }
+        self.cancel_shape();
+        self.history.execute(&mut self.document, Edit::MoveLayer { from, to });
+        self.active_layer = to;
+        self.needs_update = true;
+    }
+
+    fn merge_layer_down(&mut self) {
+        let index = self.active_layer;
+        if index == 0 {
+            return;
+        }
+        self.cancel_shape();
+        let Some(edit) = Edit::merge_down(&self.document, index) else {
+            return;
+        };
+        self.history.execute(&mut self.document, edit);
+        self.active_layer = index - 1;
+        self.needs_update = true;
+    }
+
+    /// Gives the layer at `index` `properties` right away, so changes show
+    /// while a slider is dragged or a name typed. Once `done`, everything
+    /// since the first call is committed as one undo step.
+    fn change_layer(&mut self, index: usize, properties: LayerProperties, done: bool) {
+        if self.layer_change.as_ref().is_some_and(|(changed, _)| *changed != index) {
+            self.finish_layer_change();
+        }
+        if self.layer_change.is_none() {
+            self.layer_change = Some((index, self.document.layer(index).properties()));
+        }
+        self.document.layer_mut(index).set_properties(properties);
+        self.needs_update = true;
+        if done {
+            self.finish_layer_change();
+        }
+    }
+
+    fn finish_layer_change(&mut self) {
+        if let Some((index, before)) = self.layer_change.take() {
+            let after = self.document.layer(index).properties();
+            self.history.execute(&mut self.document, Edit::ChangeLayer { index, before, after });
+        }
+    }
+
+    /// The area picked with the selection tool, if any.
+    fn selection(&self) -> Option<(Vec2, Vec2)> {
+        match &self.current_tool {
+            ActiveTool::Select(select) => select.selection(),
+            _ => None,
+        }
+    }
+
+    /// Replaces the document with an empty one and forgets its history.
+    fn new_document(&mut self, size: [u32; 2], background: Option<[f32; 4]>, infinite: bool) {
+        self.cancel_shape();
+        self.edited_stroke = None;
+        self.document = Document::new();
+        self.document.set_size(size);
+        self.document.background = background;
+        self.document.infinite = infinite;
+        self.active_layer = 0;
+        self.history.clear();
+        self.current_vertices.clear();
+        self.is_drawing = false;
+        self.needs_update = true;
+    }
+
+    /// Applies a resize or crop of the whole document as an undoable step.
+    fn edit_document(&mut self, edit: Edit) {
+        self.cancel_shape();
+        self.history.execute(&mut self.document, edit);
+        self.needs_update = true;
+    }
+
     fn clear(&mut self) {
-        self.save_state();
-        self.persistent_vertices.clear();
+        self.cancel_shape();
+        let edit = Edit::clear(&self.document);
+        self.history.execute(&mut self.document, edit);
+        self.current_vertices.clear();
+        self.needs_update = true;
+    }
+
+    fn to_project(&self) -> Project {
+        Project {
+            document: self.committed_document(),
+            settings: ToolSettings {
+                color: self.current_color,
+                brush_size: self.brush_size,
+                brush_spacing: self.brush_spacing,
+                stabilizer: self.stabilizer,
+                width_curve: self.width_curve.clone(),
+                opacity_curve: self.opacity_curve.clone(),
+                tool_type: self.tool_type,
+                blend_mode: self.blend_mode,
+            },
+        }
+    }
+
+    fn load_project(&mut self, project: Project) {
+        self.cancel_shape();
+        self.edited_stroke = None;
+        self.document = project.document;
+        self.active_layer = self.document.layers().len() - 1;
+        self.history.clear();
         self.current_vertices.clear();
+        self.is_drawing = false;
+        self.change_tool(project.settings.tool_type);
+        self.set_color(project.settings.color);
+        self.set_brush_size(project.settings.brush_size);
+        self.set_brush_spacing(project.settings.brush_spacing);
+        self.set_stabilizer(project.settings.stabilizer);
+        self.set_pressure_curves(project.settings.width_curve, project.settings.opacity_curve);
+        self.set_blend_mode(project.settings.blend_mode);
         self.needs_update = true;
     }
 
-    fn get_all_vertices(&self) -> Vec<engine::Vertex> {
-        let mut all_vertices = self.persistent_vertices.clone();
-        all_vertices.extend(self.current_vertices.clone());
-        all_vertices
+    /// Keeps the scene covering `visible`, the canvas area on screen. It's
+    /// rebuilt when the view moves out of it or zooms far into it.
+    fn cull_to(&mut self, visible: (Vec2, Vec2)) {
+        let size = visible.1 - visible.0;
+        let covered = self.scene_area.is_some_and(|(min, max)| {
+            min.cmple(visible.0).all()
+                && max.cmpge(visible.1).all()
+                && (max - min).cmple(size * CULL_REBUILD_RATIO).all()
+        });
+        if !covered {
+            let margin = size * CULL_MARGIN;
+            self.scene_area = Some((visible.0 - margin, visible.1 + margin));
+            self.needs_update = true;
+        }
+    }
+
+    /// Brings the renderer up to date: rebuilds the scene if needed, or
+    /// else uploads just the strokes committed since, then the preview.
+    /// While drawing, only the stroke in progress is uploaded.
+    fn upload(&mut self, renderer: &mut engine::Renderer2D) {
+        if self.needs_update {
+            renderer.update_scene(&self.document.tessellate_in(self.scene_area));
+            self.appended_strokes.clear();
+        }
+        for (layer, index) in self.appended_strokes.drain(..) {
+            let Some(scene_layer) = self.document.scene_layer(layer) else {
+                continue;
+            };
+            let stroke = &self.document.layer(layer).strokes()[index];
+            renderer.append_stroke(scene_layer, &stroke.tessellate(), stroke.op());
+        }
+        // A rebuilt scene may have moved the preview's layer.
+        if self.needs_update || self.preview_changed {
+            renderer.set_preview(
+                self.document.scene_layer(self.active_layer),
+                &self.current_vertices,
+                self.current_tool.stroke_op(),
+            );
+        }
+        self.needs_update = false;
+        self.preview_changed = false;
+    }
+}
+
+/// Maps a cursor position in window pixels to clip space.
+fn window_to_screen(
+    position: winit::dpi::PhysicalPosition<f64>,
+    size: winit::dpi::PhysicalSize<u32>,
+) -> Vec2 {
+    let x = (position.x / size.width as f64) * 2.0 - 1.0;
+    let y = -((position.y / size.height as f64) * 2.0 - 1.0);
+    Vec2::new(x as f32, y as f32)
+}
+
+/// The window size as the camera's viewport.
+fn viewport(size: winit::dpi::PhysicalSize<u32>) -> Vec2 {
+    Vec2::new(size.width as f32, size.height as f32)
+}
+
+/// Builds a sample for the mouse at `position`, simulating its pressure.
+fn mouse_sample(pressure: &mut VelocityPressure, position: Vec2, time: f64) -> InputSample {
+    InputSample {
+        pressure: pressure.sample(position, time),
+        time,
+        ..InputSample::new(position)
+    }
+}
+
+/// Builds a sample for a touch, using its force where the device reports one.
+fn touch_sample(
+    touch: &Touch,
+    window_size: winit::dpi::PhysicalSize<u32>,
+    camera: &Camera,
+    time: f64,
+    simulated: &mut VelocityPressure,
+) -> InputSample {
+    let position = camera.screen_to_canvas(window_to_screen(touch.location, window_size), viewport(window_size));
+    let simulated = simulated.sample(position, time);
+    let (pressure, tilt) = match touch.force {
+        Some(force @ Force::Calibrated { altitude_angle, .. }) => {
+            (force.normalized() as f32, altitude_angle.map(|angle| angle as f32))
+        }
+        Some(force) => (force.normalized() as f32, None),
+        None => (simulated, None),
+    };
+    InputSample {
+        pressure,
+        tilt,
+        time,
+        ..InputSample::new(position)
+    }
+}
+
+/// Graph of `curve` with draggable control points. Clicking empty space adds
+/// a point, right-clicking one removes it.
+fn pressure_curve_editor(ui: &mut egui::Ui, id: &str, curve: &mut PressureCurve) -> bool {
+    let old = curve.clone();
+    let size = egui::vec2(ui.available_width().min(200.0), 80.0);
+    let (rect, _) = ui.allocate_exact_size(size, egui::Sense::hover());
+    let to_screen = |p: Vec2| egui::pos2(rect.left() + p.x * rect.width(), rect.bottom() - p.y * rect.height());
+    let to_curve = |p: egui::Pos2| Vec2::new((p.x - rect.left()) / rect.width(), (rect.bottom() - p.y) / rect.height());
+    let id = ui.id().with(id);
+
+    // Handles go first so they take clicks and drags over the background.
+    let mut removed = None;
+    let mut active = None;
+    for i in 0..curve.points().len() {
+        let handle = egui::Rect::from_center_size(to_screen(curve.points()[i]), egui::vec2(12.0, 12.0));
+        let response = ui.interact(handle, id.with(i), egui::Sense::click_and_drag());
+        if response.dragged() {
+            if let Some(pointer) = response.interact_pointer_pos() {
+                curve.move_point(i, to_curve(pointer));
+            }
+        }
+        if response.secondary_clicked() {
+            removed = Some(i);
+        }
+        if response.hovered() || response.dragged() {
+            active = Some(i);
+        }
+    }
+    let background = ui.interact(rect, id, egui::Sense::click());
+    if let Some(i) = removed {
+        curve.remove(i);
+        active = None;
+    } else if background.clicked() {
+        if let Some(pointer) = background.interact_pointer_pos() {
+            active = Some(curve.insert(to_curve(pointer)));
+        }
+    }
+
+    let painter = ui.painter_at(rect.expand(6.0));
+    let stroke = ui.visuals().widgets.active.fg_stroke;
+    painter.rect_filled(rect, 2.0, ui.visuals().extreme_bg_color);
+    let line = curve.points().iter().map(|&p| to_screen(p)).collect();
+    painter.add(egui::Shape::line(line, stroke));
+    for (i, &point) in curve.points().iter().enumerate() {
+        let radius = if active == Some(i) { 5.0 } else { 3.5 };
+        painter.circle_filled(to_screen(point), radius, stroke.color);
     }
+
+    *curve != old
+}
+
+/// Controls for caps, dashes and arrowheads, and joins if `joins` is set.
+/// Returns true if anything changed.
+fn line_style_editor(ui: &mut egui::Ui, style: &mut LineStyle, joins: bool) -> bool {
+    let old = *style;
+    ui.horizontal(|ui| {
+        egui::ComboBox::from_id_source("line_cap")
+            .selected_text(style.cap.name())
+            .show_ui(ui, |ui| {
+                for cap in LineCap::ALL {
+                    ui.selectable_value(&mut style.cap, cap, cap.name());
+                }
+            });
+        ui.label("Caps");
+    });
+    if joins {
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_id_source("line_join")
+                .selected_text(style.join.name())
+                .show_ui(ui, |ui| {
+                    for join in LineJoin::ALL {
+                        ui.selectable_value(&mut style.join, join, join.name());
+                    }
+                });
+            ui.label("Joins");
+        });
+    }
+
+    let mut dashed = style.is_dashed();
+    if ui.checkbox(&mut dashed, "Dashed").changed() {
+        (style.dash, style.gap) = if dashed { (0.04, 0.02) } else { (0.0, 0.0) };
+    }
+    if dashed {
+        ui.add(egui::Slider::new(&mut style.dash, 0.005..=0.2).text("Dash"));
+        ui.add(egui::Slider::new(&mut style.gap, 0.005..=0.2).text("Gap"));
+    }
+
+    ui.horizontal(|ui| {
+        ui.label("Arrows");
+        ui.checkbox(&mut style.start_arrow, "Start");
+        ui.checkbox(&mut style.end_arrow, "End");
+    });
+    *style != old
+}
+
+/// What a document dialog asks for.
+enum DocumentChange {
+    New { size: [u32; 2], background: Option<[f32; 4]>, infinite: bool },
+    Edit(Box<Edit>),
+}
+
+/// Shows the open document dialog, if any. Returns the change to make
+/// once it is confirmed.
+fn document_dialog(
+    ctx: &egui::Context,
+    settings: &mut DocumentSettings,
+    document: &Document,
+) -> Option<DocumentChange> {
+    let dialog = settings.dialog?;
+    let title = match dialog {
+        DocumentDialog::NewCanvas => "New Canvas",
+        DocumentDialog::CanvasSize => "Canvas Size",
+        DocumentDialog::ImageSize => "Image Size",
+    };
+    let mut open = true;
+    let mut confirmed = false;
+    let mut cancelled = false;
+    egui::Window::new(title)
+        .open(&mut open)
+        .collapsible(false)
+        .resizable(false)
+        .show(ctx, |ui| {
+            if dialog == DocumentDialog::NewCanvas {
+                let preset = CANVAS_PRESETS.iter().find(|(_, size)| *size == settings.size);
+                egui::ComboBox::from_label("Preset")
+                    .selected_text(preset.map_or("Custom", |(name, _)| name))
+                    .show_ui(ui, |ui| {
+                        for (name, size) in CANVAS_PRESETS {
+                            if ui.selectable_label(settings.size == size, name).clicked() {
+                                settings.size = size;
+                            }
+                        }
+                    });
+            }
+
+            let old_size = settings.size;
+            ui.horizontal(|ui| {
+                ui.label("Width");
+                ui.add(egui::DragValue::new(&mut settings.size[0]).clamp_range(1..=MAX_DOCUMENT_SIDE).suffix(" px"));
+                ui.label("Height");
+                ui.add(egui::DragValue::new(&mut settings.size[1]).clamp_range(1..=MAX_DOCUMENT_SIDE).suffix(" px"));
+            });
+
+            match dialog {
+                DocumentDialog::NewCanvas => {
+                    ui.checkbox(&mut settings.infinite, "Infinite canvas")
+                        .on_hover_text("No edges; the size only sets the first view");
+                    ui.checkbox(&mut settings.transparent, "Transparent background");
+                    if !settings.transparent {
+                        ui.horizontal(|ui| {
+                            ui.color_edit_button_rgba_unmultiplied(&mut settings.background);
+                            ui.label("Background");
+                        });
+                    }
+                }
+                DocumentDialog::CanvasSize => {
+                    ui.label("Anchor");
+                    egui::Grid::new("canvas_size_anchor").show(ui, |ui| {
+                        for y in [1.0, 0.0, -1.0] {
+                            for x in [-1.0, 0.0, 1.0] {
+                                let anchor = Vec2::new(x, y);
+                                let selected = settings.anchor == anchor;
+                                if ui.selectable_label(selected, if selected { "●" } else { "○" }).clicked() {
+                                    settings.anchor = anchor;
+                                }
+                            }
+                            ui.end_row();
+                        }
+                    });
+                }
+                DocumentDialog::ImageSize => {
+                    ui.checkbox(&mut settings.keep_proportions, "Keep proportions");
+                    ui.checkbox(&mut settings.scale_content, "Scale content")
+                        .on_hover_text("Otherwise the drawing keeps its size and stays centered");
+                    if settings.keep_proportions {
+                        let [width, height] = document.size().map(|side| side as f32);
+                        let size = &mut settings.size;
+                        if size[0] != old_size[0] {
+                            size[1] = (size[0] as f32 * height / width).round().clamp(1.0, MAX_DOCUMENT_SIDE as f32) as u32;
+                        } else if size[1] != old_size[1] {
+                            size[0] = (size[1] as f32 * width / height).round().clamp(1.0, MAX_DOCUMENT_SIDE as f32) as u32;
+                        }
+                    }
+                }
+            }
+
+            ui.horizontal(|ui| {
+                let ok = if dialog == DocumentDialog::NewCanvas { "Create" } else { "Apply" };
+                confirmed = ui.button(ok).clicked();
+                cancelled = ui.button("Cancel").clicked();
+            });
+        });
+    if !open || cancelled || confirmed {
+        settings.dialog = None;
+    }
+    if !confirmed {
+        return None;
+    }
+
+    let size = settings.size;
+    Some(match dialog {
+        DocumentDialog::NewCanvas => DocumentChange::New {
+            size,
+            background: (!settings.transparent).then_some(settings.background),
+            infinite: settings.infinite,
+        },
+        DocumentDialog::CanvasSize => DocumentChange::Edit(Box::new(Edit::canvas_size(document, size, settings.anchor))),
+        DocumentDialog::ImageSize if settings.scale_content => DocumentChange::Edit(Box::new(Edit::image_size(document, size))),
+        DocumentDialog::ImageSize => DocumentChange::Edit(Box::new(Edit::canvas_size(document, size, Vec2::ZERO))),
+    })
+}
+
+/// A drop-down for picking a blend mode. Returns true if it changed.
+fn blend_mode_combo(ui: &mut egui::Ui, id: &str, blend_mode: &mut BlendMode) -> bool {
+    let old = *blend_mode;
+    ui.horizontal(|ui| {
+        egui::ComboBox::from_id_source(id)
+            .selected_text(blend_mode.name())
+            .show_ui(ui, |ui| {
+                for mode in BlendMode::ALL {
+                    ui.selectable_value(blend_mode, mode, mode.name());
+                }
+            });
+        ui.label("Blend Mode");
+    });
+    *blend_mode != old
 }
 
 fn main() {
@@ -135,11 +1124,44 @@ fn main() {
         .unwrap();
 
     let mut renderer = pollster::block_on(engine::Renderer2D::new(&window));
+    let mut egui_state = EguiWinitState::new(&window);
+    let egui_ctx = egui::Context::default();
     let mut canvas = Canvas::new();
+    let mut modifiers = ModifiersState::empty();
+    let mut camera = Camera::default();
+    // Fits the document into the free part of the window once egui has
+    // laid out its panels.
+    let mut fit_pending = true;
+    // The cursor in clip space; `camera` maps it onto the canvas.
+    let mut cursor_screen = Vec2::ZERO;
+    // The button dragging the view, if any.
+    let mut pan_button: Option<MouseButton> = None;
+    let mut space_held = false;
+    let start_time = Instant::now();
+    let mut mouse_pressure = VelocityPressure::default();
+    let mut touch_pressure = VelocityPressure::default();
+    // The touch that is drawing, so other fingers don't interfere.
+    let mut active_touch: Option<u64> = None;
+    let mut project_path = format!("drawing.{}", renderer_2d::project::FILE_EXTENSION);
+    let mut project_status = String::new();
+    // The size export settings and the view were last set up for.
+    let mut document_size = canvas.document.size();
+    let mut document_settings = DocumentSettings::new();
+    let mut png_options = PngOptions {
+        width: document_size[0],
+        height: document_size[1],
+        transparent: false,
+    };
+    let mut png_path = String::from("drawing.png");
+    let mut svg_options = SvgOptions {
+        view_box: [0.0, 0.0, document_size[0] as f32, document_size[1] as f32],
+    };
+    let mut svg_path = String::from("drawing.svg");
+    let mut export_status = String::new();
 
     event_loop.run(move |event, _, control_flow| {
         if let Event::WindowEvent { event, .. } = &event {
-            if renderer.egui_state.on_event(&renderer.egui_ctx, event).consumed {
+            if egui_state.on_event(&egui_ctx, event).consumed {
                 return;
             }
         }
@@ -149,8 +1171,10 @@ fn main() {
                 ref event,
                 window_id,
             } if window_id == window.id() => match event {
-                WindowEvent::CloseRequested
-                | WindowEvent::KeyboardInput {
+                WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
+
+                // Escape cancels a shape in progress before it quits.
+                WindowEvent::KeyboardInput {
                     input:
                         KeyboardInput {
                             state: ElementState::Pressed,
@@ -158,35 +1182,115 @@ fn main() {
                             ..
                         },
                     ..
-                } => *control_flow = ControlFlow::Exit,
+                } if !canvas.current_tool.in_progress() => *control_flow = ControlFlow::Exit,
 
                 WindowEvent::CursorMoved { position, .. } => {
-                    if canvas.is_drawing {
-                        let size = window.inner_size();
-                        let x = (position.x / size.width as f64) * 2.0 - 1.0;
-                        let y = -((position.y / size.height as f64) * 2.0 - 1.0);
-                        canvas.on_mouse_move(Vec2::new(x as f32, y as f32));
-                        canvas.needs_update = true;
+                    let screen = window_to_screen(*position, window.inner_size());
+                    if pan_button.is_some() {
+                        camera.pan(screen - cursor_screen, viewport(window.inner_size()));
+                    }
+                    cursor_screen = screen;
+                    let cursor_position = camera.screen_to_canvas(cursor_screen, viewport(window.inner_size()));
+                    if (canvas.is_drawing || canvas.current_tool.in_progress()) && active_touch.is_none() {
+                        let time = start_time.elapsed().as_secs_f64();
+                        canvas.on_mouse_move(mouse_sample(&mut mouse_pressure, cursor_position, time));
                     }
                 },
 
+                WindowEvent::MouseWheel { delta, .. } => {
+                    let steps = match delta {
+                        MouseScrollDelta::LineDelta(_, y) => *y,
+                        MouseScrollDelta::PixelDelta(position) => position.y as f32 / PIXELS_PER_WHEEL_STEP,
+                    };
+                    camera.zoom_at(cursor_screen, WHEEL_ZOOM_STEP.powf(steps), viewport(window.inner_size()));
+                },
+
+                // Middle-drag, or drag with Space held, pans the view.
+                WindowEvent::MouseInput {
+                    state: ElementState::Pressed,
+                    button,
+                    ..
+                } if *button == MouseButton::Middle || (*button == MouseButton::Left && space_held) => {
+                    pan_button = Some(*button);
+                },
+                WindowEvent::MouseInput {
+                    state: ElementState::Released,
+                    button,
+                    ..
+                } if pan_button == Some(*button) => {
+                    pan_button = None;
+                },
+
                 WindowEvent::MouseInput {
                     state,
                     button: MouseButton::Left,
                     ..
                 } => {
-                    if let Ok(position) = window.inner_position() {
-                        let size = window.inner_size();
-                        let x = (position.x as f64 / size.width as f64) * 2.0 - 1.0;
-                        let y = -((position.y as f64 / size.height as f64) * 2.0 - 1.0);
-                        if *state == ElementState::Pressed {
-                            canvas.on_mouse_down(Vec2::new(x as f32, y as f32));
-                        } else {
-                            canvas.on_mouse_up(Vec2::new(x as f32, y as f32));
+                    let time = start_time.elapsed().as_secs_f64();
+                    let cursor_position = camera.screen_to_canvas(cursor_screen, viewport(window.inner_size()));
+                    if *state == ElementState::Pressed {
+                        mouse_pressure.reset();
+                        canvas.on_mouse_down(mouse_sample(&mut mouse_pressure, cursor_position, time));
+                    } else {
+                        canvas.on_mouse_up(mouse_sample(&mut mouse_pressure, cursor_position, time));
+                    }
+                },
+
+                WindowEvent::Touch(touch) => {
+                    let time = start_time.elapsed().as_secs_f64();
+                    match touch.phase {
+                        TouchPhase::Started if active_touch.is_none() => {
+                            active_touch = Some(touch.id);
+                            touch_pressure.reset();
+                            let sample = touch_sample(touch, window.inner_size(), &camera, time, &mut touch_pressure);
+                            canvas.on_mouse_down(sample);
+                        }
+                        TouchPhase::Moved if active_touch == Some(touch.id) => {
+                            let sample = touch_sample(touch, window.inner_size(), &camera, time, &mut touch_pressure);
+                            canvas.on_mouse_move(sample);
+                        }
+                        TouchPhase::Ended | TouchPhase::Cancelled if active_touch == Some(touch.id) => {
+                            active_touch = None;
+                            let sample = touch_sample(touch, window.inner_size(), &camera, time, &mut touch_pressure);
+                            canvas.on_mouse_up(sample);
                         }
+                        _ => {}
                     }
                 },
 
+                WindowEvent::ModifiersChanged(new_modifiers) => {
+                    modifiers = *new_modifiers;
+                    canvas.set_modifiers(Modifiers {
+                        shift: modifiers.shift(),
+                        alt: modifiers.alt(),
+                    });
+                },
+
+                WindowEvent::KeyboardInput {
+                    input: KeyboardInput {
+                        state,
+                        virtual_keycode: Some(VirtualKeyCode::Space),
+                        ..
+                    },
+                    ..
+                } => {
+                    space_held = *state == ElementState::Pressed;
+                },
+
+                WindowEvent::KeyboardInput {
+                    input: KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(key),
+                        ..
+                    },
+                    ..
+                } if modifiers.ctrl() => match key {
+                    VirtualKeyCode::Z if modifiers.shift() => canvas.redo(),
+                    VirtualKeyCode::Z => canvas.undo(),
+                    VirtualKeyCode::Y => canvas.redo(),
+                    _ => {}
+                },
+
                 WindowEvent::KeyboardInput {
                     input: KeyboardInput {
                         state: ElementState::Pressed,
@@ -195,15 +1299,27 @@ fn main() {
                     },
                     ..
                 } => {
-                    canvas.set_color(match key {
-                        VirtualKeyCode::Key1 => [1.0, 0.0, 0.0, 1.0],
-                        VirtualKeyCode::Key2 => [0.0, 1.0, 0.0, 1.0],
-                        VirtualKeyCode::Key3 => [0.0, 0.0, 1.0, 1.0],
-                        VirtualKeyCode::Key4 => [1.0, 1.0, 0.0, 1.0],
-                        VirtualKeyCode::Key5 => [1.0, 0.0, 1.0, 1.0],
-                        VirtualKeyCode::Key6 => [0.0, 0.0, 0.0, 1.0],
-                        _ => canvas.current_color,
-                    });
+                    let rgb = match key {
+                        VirtualKeyCode::Key1 => Some([1.0, 0.0, 0.0]),
+                        VirtualKeyCode::Key2 => Some([0.0, 1.0, 0.0]),
+                        VirtualKeyCode::Key3 => Some([0.0, 0.0, 1.0]),
+                        VirtualKeyCode::Key4 => Some([1.0, 1.0, 0.0]),
+                        VirtualKeyCode::Key5 => Some([1.0, 0.0, 1.0]),
+                        VirtualKeyCode::Key6 => Some([0.0, 0.0, 0.0]),
+                        _ => None,
+                    };
+                    if let Some(rgb) = rgb {
+                        canvas.set_rgb(rgb);
+                    }
+                    let tool_key = match key {
+                        VirtualKeyCode::Return | VirtualKeyCode::NumpadEnter => Some(ToolKey::Enter),
+                        VirtualKeyCode::Back => Some(ToolKey::Backspace),
+                        VirtualKeyCode::Escape => Some(ToolKey::Escape),
+                        _ => None,
+                    };
+                    if let Some(key) = tool_key {
+                        canvas.on_key(key);
+                    }
                 },
 
                 WindowEvent::Resized(physical_size) => {
@@ -215,24 +1331,54 @@ fn main() {
                 _ => {}
             },
             Event::RedrawRequested(window_id) if window_id == window.id() => {
-                let raw_input = renderer.egui_state.take_egui_input(&window);
+                let raw_input = egui_state.take_egui_input(&window);
                 
                 // Store if we need to clear canvas
                 let mut should_clear_canvas = false;
+                let mut should_undo = false;
+                let mut should_redo = false;
+                let mut should_save_project = false;
+                let mut should_open_project = false;
+                let mut should_export_png = false;
+                let mut should_export_svg = false;
+                let mut document_change: Option<DocumentChange> = None;
                 
-                let output = renderer.egui_ctx.run(raw_input, |ctx| {
+                let output = egui_ctx.run(raw_input, |ctx| {
                     egui::SidePanel::left("Tools").show(ctx, |ui| {
                         ui.heading("Tools");
                         
-                        let old_tool = canvas.tool_type;
-                        ui.radio_value(&mut canvas.tool_type, ToolType::Brush, "🖌 Brush");
-                        ui.radio_value(&mut canvas.tool_type, ToolType::Eraser, "⌫ Eraser");
-                        ui.radio_value(&mut canvas.tool_type, ToolType::Line, "📏 Line");
-                        ui.radio_value(&mut canvas.tool_type, ToolType::Rectangle, "⬜ Rectangle");
+                        let mut tool_type = canvas.tool_type;
+                        ui.radio_value(&mut tool_type, ToolType::Brush, "🖌 Brush");
+                        ui.radio_value(&mut tool_type, ToolType::Eraser, "⌫ Eraser");
+                        ui.radio_value(&mut tool_type, ToolType::Line, "📏 Line");
+                        ui.radio_value(&mut tool_type, ToolType::Rectangle, "⬜ Rectangle");
+                        ui.radio_value(&mut tool_type, ToolType::Ellipse, "⭕ Ellipse");
+                        ui.radio_value(&mut tool_type, ToolType::Polygon, "⬠ Polygon");
+                        ui.radio_value(&mut tool_type, ToolType::Pen, "✒ Pen");
+                        ui.radio_value(&mut tool_type, ToolType::Bucket, "🎨 Fill");
+                        ui.radio_value(&mut tool_type, ToolType::Select, "⬚ Select");
                         
-                        if old_tool != canvas.tool_type {
-                            canvas.change_tool(canvas.tool_type);
+                        if tool_type != canvas.tool_type {
+                            canvas.change_tool(tool_type);
                         }
+
+                        ui.horizontal(|ui| {
+                            if ui.add_enabled(canvas.can_undo(), egui::Button::new("↶ Undo")).clicked() {
+                                should_undo = true;
+                            }
+                            if ui.add_enabled(canvas.can_redo(), egui::Button::new("↷ Redo")).clicked() {
+                                should_redo = true;
+                            }
+                        });
+
+                        ui.collapsing("History", |ui| {
+                            const MIB: usize = 1024 * 1024;
+                            let mut budget_mib = canvas.history.budget_bytes() / MIB;
+                            if ui.add(egui::Slider::new(&mut budget_mib, 1..=1024).text("Budget (MiB)")).changed() {
+                                canvas.history.set_budget_bytes(budget_mib * MIB);
+                            }
+                            ui.label(format!("Using {} KiB", canvas.history.memory_usage() / 1024));
+                        });
                         
                         ui.separator();
                         
@@ -242,34 +1388,162 @@ fn main() {
                         if old_size != canvas.brush_size {
                             canvas.set_brush_size(canvas.brush_size);
                         }
+
+                        if canvas.tool_type == ToolType::Brush {
+                            let mut spacing = canvas.brush_spacing;
+                            if ui.add(egui::Slider::new(&mut spacing, 0.05..=2.0).text("Spacing")).changed() {
+                                canvas.set_brush_spacing(spacing);
+                            }
+
+                            let mut stabilizer = canvas.stabilizer;
+                            ui.horizontal(|ui| {
+                                egui::ComboBox::from_id_source("stabilizer")
+                                    .selected_text(stabilizer.kind.name())
+                                    .show_ui(ui, |ui| {
+                                        for kind in StabilizerKind::ALL {
+                                            ui.selectable_value(&mut stabilizer.kind, kind, kind.name());
+                                        }
+                                    });
+                                ui.label("Stabilizer");
+                            });
+                            if stabilizer.kind != StabilizerKind::None {
+                                ui.add(egui::Slider::new(&mut stabilizer.strength, 0.0..=1.0).text("Strength"));
+                            }
+                            if stabilizer != canvas.stabilizer {
+                                canvas.set_stabilizer(stabilizer);
+                            }
+
+                            ui.collapsing("Pressure", |ui| {
+                                let mut width = canvas.width_curve.clone();
+                                let mut opacity = canvas.opacity_curve.clone();
+                                ui.label("Width");
+                                let mut changed = pressure_curve_editor(ui, "width_curve", &mut width);
+                                ui.label("Opacity");
+                                changed |= pressure_curve_editor(ui, "opacity_curve", &mut opacity);
+                                if changed {
+                                    canvas.set_pressure_curves(width, opacity);
+                                }
+                            });
+                        }
+
+                        if canvas.tool_type == ToolType::Ellipse {
+                            let mut fill = canvas.fill_shapes;
+                            if ui.checkbox(&mut fill, "Filled").changed() {
+                                canvas.set_fill_shapes(fill);
+                            }
+                            ui.label("Shift: circle, Alt: from center");
+                        }
+
+                        if canvas.tool_type == ToolType::Line {
+                            let mut style = canvas.line_style;
+                            if line_style_editor(ui, &mut style, false) {
+                                canvas.set_line_style(style);
+                            }
+                            ui.label("Shift: snap to 15°");
+                        }
+
+                        if canvas.tool_type == ToolType::Pen {
+                            let mut style = canvas.line_style;
+                            if line_style_editor(ui, &mut style, true) {
+                                canvas.set_line_style(style);
+                            }
+                            ui.label("Click: corner, drag: curve");
+                            ui.label("Click the first point to close, double-click or Enter to finish");
+                            ui.label("Drag points and handles to adjust, Alt: break handles");
+                            ui.label("Alt-click a path to edit it");
+                        }
+
+                        if canvas.tool_type == ToolType::Rectangle {
+                            let mut style = canvas.rectangle_style;
+                            ui.horizontal(|ui| {
+                                for option in RectangleStyle::ALL {
+                                    ui.radio_value(&mut style, option, option.name());
+                                }
+                            });
+                            if style != canvas.rectangle_style {
+                                canvas.set_rectangle_style(style);
+                            }
+                            if style.has_fill() {
+                                let mut separate = canvas.fill_color.is_some();
+                                let mut fill_color = canvas.fill_color.unwrap_or(canvas.current_color);
+                                ui.horizontal(|ui| {
+                                    let toggled = ui.checkbox(&mut separate, "Fill Color").changed();
+                                    let picked = separate
+                                        && ui.color_edit_button_rgba_unmultiplied(&mut fill_color).changed();
+                                    if toggled || picked {
+                                        canvas.set_fill_color(separate.then_some(fill_color));
+                                    }
+                                })
+                                .response
+                                .on_hover_text("Unchecked, rectangles are filled with the current color");
+                            }
+                            let mut radius = canvas.corner_radius;
+                            if ui.add(egui::Slider::new(&mut radius, 0.0..=0.2).text("Corner Radius")).changed() {
+                                canvas.set_corner_radius(radius);
+                            }
+                            ui.label("Shift: square, Alt: from center");
+                        }
+
+                        if canvas.tool_type == ToolType::Polygon {
+                            let mut closed = canvas.close_polygons;
+                            if ui.checkbox(&mut closed, "Closed").changed() {
+                                canvas.set_close_polygons(closed);
+                            }
+                            let mut fill = canvas.fill_shapes;
+                            if ui.add_enabled(closed, egui::Checkbox::new(&mut fill, "Filled")).changed() {
+                                canvas.set_fill_shapes(fill);
+                            }
+                            ui.label("Click: add point, double-click or Enter: finish");
+                            ui.label("Backspace: remove point, Esc: cancel");
+                        }
+
+                        if canvas.tool_type == ToolType::Bucket {
+                            let mut tolerance = canvas.fill_tolerance;
+                            if ui.add(egui::Slider::new(&mut tolerance, 0.0..=1.0).text("Tolerance")).changed() {
+                                canvas.set_fill_tolerance(tolerance);
+                            }
+                            ui.checkbox(&mut canvas.fill_all_layers, "Sample all layers");
+                        }
+
+                        if canvas.tool_type == ToolType::Eraser {
+                            let mut eraser_mode = canvas.eraser_mode;
+                            ui.horizontal(|ui| {
+                                ui.radio_value(&mut eraser_mode, EraserMode::Pixel, "Pixels");
+                                ui.radio_value(&mut eraser_mode, EraserMode::Object, "Whole strokes");
+                            });
+                            if eraser_mode != canvas.eraser_mode {
+                                canvas.set_eraser_mode(eraser_mode);
+                            }
+                        }
+
+                        let mut blend_mode = canvas.blend_mode;
+                        if blend_mode_combo(ui, "stroke_blend_mode", &mut blend_mode) {
+                            canvas.set_blend_mode(blend_mode);
+                        }
                         
                         ui.separator();
                         
                         ui.heading("Colors");
-                        let mut color = [
-                            canvas.current_color[0],
-                            canvas.current_color[1],
-                            canvas.current_color[2],
-                        ];
-                        
-                        if ui.color_edit_button_rgb(&mut color).changed() {
-                            canvas.set_color([
-                                color[0],
-                                color[1],
-                                color[2],
-                                1.0,
-                            ]);
+                        let mut color = canvas.current_color;
+                        if ui.color_edit_button_rgba_unmultiplied(&mut color).changed() {
+                            canvas.set_color(color);
+                        }
+
+                        let mut alpha = canvas.current_color[3];
+                        if ui.add(egui::Slider::new(&mut alpha, 0.0..=1.0).text("Opacity")).changed() {
+                            let [r, g, b, _] = canvas.current_color;
+                            canvas.set_color([r, g, b, alpha]);
                         }
                         
                         ui.horizontal(|ui| {
                             for color in &[
-                                [1.0, 0.0, 0.0, 1.0],
-                                [0.0, 1.0, 0.0, 1.0],
-                                [0.0, 0.0, 1.0, 1.0],
-                                [0.0, 0.0, 0.0, 1.0],
+                                [1.0, 0.0, 0.0],
+                                [0.0, 1.0, 0.0],
+                                [0.0, 0.0, 1.0],
+                                [0.0, 0.0, 0.0],
                             ] {
                                 if ui.button("⬤").clicked() {
-                                    canvas.set_color(*color);
+                                    canvas.set_rgb(*color);
                                 }
                             }
                         });
@@ -279,7 +1553,172 @@ fn main() {
                         if ui.button("Clear Canvas").clicked() {
                             should_clear_canvas = true;
                         }
+
+                        ui.separator();
+
+                        ui.heading("Document");
+                        let [width, height] = canvas.document.size();
+                        if canvas.document.infinite {
+                            ui.label(format!("Infinite, drawing covers {} × {} px", width, height));
+                        } else {
+                            ui.label(format!("{} × {} px", width, height));
+                        }
+                        // Infinite documents have no edges to resize or crop.
+                        let bounded = !canvas.document.infinite;
+                        ui.horizontal_wrapped(|ui| {
+                            if ui.button("New…").clicked() {
+                                document_settings.open(DocumentDialog::NewCanvas, &canvas.document);
+                            }
+                            if ui.add_enabled(bounded, egui::Button::new("Canvas Size…")).clicked() {
+                                document_settings.open(DocumentDialog::CanvasSize, &canvas.document);
+                            }
+                            if ui.add_enabled(bounded, egui::Button::new("Image Size…")).clicked() {
+                                document_settings.open(DocumentDialog::ImageSize, &canvas.document);
+                            }
+                        });
+                        let selection = canvas.selection();
+                        let crop = ui
+                            .add_enabled(bounded && selection.is_some(), egui::Button::new("Crop to Selection"))
+                            .on_disabled_hover_text("Drag out an area with the Select tool first");
+                        if let (true, Some((min, max))) = (crop.clicked(), selection) {
+                            document_change = Some(DocumentChange::Edit(Box::new(Edit::crop(&canvas.document, min, max))));
+                        }
+
+                        ui.separator();
+
+                        ui.heading("View");
+                        ui.horizontal(|ui| {
+                            if ui.button("Fit").clicked() {
+                                fit_pending = true;
+                            }
+                            if ui.button("100%").clicked() {
+                                camera.set_zoom(1.0);
+                            }
+                            if ui.button("200%").clicked() {
+                                camera.set_zoom(2.0);
+                            }
+                        });
+                        ui.label(format!("Zoom: {:.0}%", camera.zoom * 100.0));
+                        ui.label("Wheel: zoom, Space or middle drag: pan");
+
+                        ui.separator();
+
+                        ui.heading("Project");
+                        ui.text_edit_singleline(&mut project_path);
+                        ui.horizontal(|ui| {
+                            if ui.button("💾 Save").clicked() {
+                                should_save_project = true;
+                            }
+                            if ui.button("📂 Open").clicked() {
+                                should_open_project = true;
+                            }
+                        });
+                        if !project_status.is_empty() {
+                            ui.label(&project_status);
+                        }
+
+                        ui.separator();
+
+                        ui.heading("Export");
+                        ui.text_edit_singleline(&mut png_path);
+                        ui.horizontal(|ui| {
+                            ui.add(egui::DragValue::new(&mut png_options.width).clamp_range(1..=8192));
+                            ui.label("×");
+                            ui.add(egui::DragValue::new(&mut png_options.height).clamp_range(1..=8192));
+                            ui.label("px");
+                        });
+                        ui.checkbox(&mut png_options.transparent, "Transparent background");
+                        if ui.button("🖼 Export PNG").clicked() {
+                            should_export_png = true;
+                        }
+
+                        ui.text_edit_singleline(&mut svg_path);
+                        ui.horizontal(|ui| {
+                            ui.label("viewBox");
+                            ui.add(egui::DragValue::new(&mut svg_options.view_box[2]).clamp_range(1.0..=f32::MAX));
+                            ui.label("×");
+                            ui.add(egui::DragValue::new(&mut svg_options.view_box[3]).clamp_range(1.0..=f32::MAX));
+                        });
+                        if ui.button("✒ Export SVG").clicked() {
+                            should_export_svg = true;
+                        }
+                        if !export_status.is_empty() {
+                            ui.label(&export_status);
+                        }
                     });
+
+                    egui::SidePanel::right("Layers").show(ctx, |ui| {
+                        ui.heading("Layers");
+
+                        let layer_count = canvas.document.layers().len();
+                        let active = canvas.active_layer;
+                        ui.horizontal(|ui| {
+                            if ui.button("➕").on_hover_text("Add layer").clicked() {
+                                canvas.add_layer();
+                            }
+                            if ui.add_enabled(layer_count > 1, egui::Button::new("🗑"))
+                                .on_hover_text("Delete layer").clicked() {
+                                canvas.delete_layer();
+                            }
+                            if ui.add_enabled(active + 1 < layer_count, egui::Button::new("⬆"))
+                                .on_hover_text("Move layer up").clicked() {
+                                canvas.move_layer(true);
+                            }
+                            if ui.add_enabled(active > 0, egui::Button::new("⬇"))
+                                .on_hover_text("Move layer down").clicked() {
+                                canvas.move_layer(false);
+                            }
+                            let mergeable = active > 0 && Edit::can_merge_down(&canvas.document, active);
+                            if ui.add_enabled(mergeable, egui::Button::new("⤓"))
+                                .on_hover_text("Merge down")
+                                .on_disabled_hover_text(
+                                    "Merge down needs both layers visible, fully opaque and Normal, \
+                                     with nothing erasing or blending over the layer below",
+                                )
+                                .clicked() {
+                                canvas.merge_layer_down();
+                            }
+                        });
+
+                        ui.separator();
+
+                        // Topmost layer first, like most paint programs.
+                        for index in (0..canvas.document.layers().len()).rev() {
+                            ui.horizontal(|ui| {
+                                let mut properties = canvas.document.layer(index).properties();
+                                let visible = ui.checkbox(&mut properties.visible, "").on_hover_text("Visible").changed();
+                                let locked = ui.toggle_value(&mut properties.locked, "🔒").on_hover_text("Locked").changed();
+                                let selected = ui.selectable_label(index == canvas.active_layer, &properties.name).clicked();
+                                if visible || locked {
+                                    canvas.change_layer(index, properties, true);
+                                }
+                                if selected {
+                                    canvas.active_layer = index;
+                                    canvas.preview_changed = true;
+                                }
+                            });
+                        }
+
+                        ui.separator();
+
+                        let active = canvas.active_layer;
+                        let mut properties = canvas.document.layer(active).properties();
+                        let name = ui.text_edit_singleline(&mut properties.name);
+                        if name.changed() || name.lost_focus() {
+                            canvas.change_layer(active, properties.clone(), name.lost_focus());
+                        }
+                        let opacity = ui.add(egui::Slider::new(&mut properties.opacity, 0.0..=1.0).text("Opacity"));
+                        if opacity.changed() || opacity.drag_released() {
+                            canvas.change_layer(active, properties.clone(), !opacity.dragged());
+                        }
+                        if blend_mode_combo(ui, "layer_blend_mode", &mut properties.blend_mode) {
+                            canvas.change_layer(active, properties, true);
+                        }
+                    });
+
+                    if let Some(change) = document_dialog(ctx, &mut document_settings, &canvas.document) {
+                        document_change = Some(change);
+                    }
                 });
 
                 // Handle canvas clearing outside the egui closure
@@ -288,13 +1727,83 @@ fn main() {
                     canvas.needs_update = true;
                 }
 
-                // Update vertices if needed
-                if canvas.needs_update {
-                    renderer.update_vertices(&canvas.get_all_vertices());
-                    canvas.needs_update = false;
+                match document_change {
+                    Some(DocumentChange::New { size, background, infinite }) => {
+                        canvas.new_document(size, background, infinite);
+                        fit_pending = true;
+                    }
+                    Some(DocumentChange::Edit(edit)) => canvas.edit_document(*edit),
+                    None => {}
+                }
+
+                if should_undo {
+                    canvas.undo();
+                }
+                if should_redo {
+                    canvas.redo();
+                }
+
+                if should_save_project {
+                    project_status = match canvas.to_project().save(&project_path) {
+                        Ok(()) => format!("Saved {}", project_path),
+                        Err(e) => format!("Save failed: {}", e),
+                    };
+                }
+
+                if should_open_project {
+                    project_status = match Project::load(&project_path) {
+                        Ok(project) => {
+                            canvas.load_project(project);
+                            fit_pending = true;
+                            format!("Opened {}", project_path)
+                        }
+                        Err(e) => format!("Open failed: {}", e),
+                    };
+                }
+
+                if should_export_png {
+                    export_status = match export::render_png(&renderer, &canvas.committed_document(), &png_options, &png_path) {
+                        Ok(()) => format!("Exported {}", png_path),
+                        Err(e) => format!("Export failed: {}", e),
+                    };
+                }
+
+                if should_export_svg {
+                    export_status = match export::export_svg(&canvas.committed_document(), &svg_options, &svg_path) {
+                        Ok(()) => format!("Exported {}", svg_path),
+                        Err(e) => format!("Export failed: {}", e),
+                    };
+                }
+
+                canvas.flood_fill(&renderer, camera.visible_area(viewport(renderer.size)), camera.zoom);
+
+                // Follow size changes from the dialogs, undo and opened projects.
+                if canvas.document.size() != document_size {
+                    document_size = canvas.document.size();
+                    let [width, height] = document_size;
+                    png_options.width = width;
+                    png_options.height = height;
+                    svg_options.view_box = [0.0, 0.0, width as f32, height as f32];
+                    // Infinite documents grow while drawing; refitting then would jump.
+                    fit_pending |= !canvas.document.infinite;
+                }
+
+                if fit_pending {
+                    // egui works in points; the camera in physical pixels.
+                    let area = egui_ctx.available_rect();
+                    let scale = egui_ctx.pixels_per_point();
+                    let corner = |pos: egui::Pos2| Vec2::new(pos.x, pos.y) * scale;
+                    camera.fit(canvas.document.bounds(), viewport(renderer.size), (corner(area.min), corner(area.max)));
+                    fit_pending = false;
                 }
+                canvas.cull_to(camera.visible_area(viewport(renderer.size)));
 
-                let primitives = renderer.egui_ctx.tessellate(output.shapes);
+                canvas.upload(&mut renderer);
+
+                let edges = (!canvas.document.infinite).then(|| canvas.document.bounds());
+                renderer.set_view(camera, edges);
+                renderer.set_background(canvas.document.clear_color());
+                let primitives = egui_ctx.tessellate(output.shapes);
                 
                 match renderer.render(&window, primitives, output.textures_delta) {
                     Ok(_) => {}
@@ -303,7 +1812,7 @@ fn main() {
                     Err(e) => eprintln!("{:?}", e),
                 }
 
-                renderer.egui_state.handle_platform_output(&window, &renderer.egui_ctx, output.platform_output);
+                egui_state.handle_platform_output(&window, &egui_ctx, output.platform_output);
             }
             Event::MainEventsCleared => {
                 window.request_redraw();
@@ -312,3 +1821,66 @@ fn main() {
         }
     });
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use renderer_2d::tools::ellipse::EllipseStroke;
+
+    fn dot(x: f32) -> Stroke {
+        Stroke::Ellipse(EllipseStroke {
+            center: Vec2::new(x, 0.0),
+            radii: Vec2::splat(0.1),
+            filled: true,
+            thickness: 0.0,
+            color: [0.0, 0.0, 0.0, 1.0],
+            blend_mode: BlendMode::Normal,
+        })
+    }
+
+    fn stroke_count(canvas: &Canvas) -> usize {
+        canvas.document.layer(0).strokes().len()
+    }
+
+    #[test]
+    fn undo_while_erasing_objects_commits_the_erasing_first() {
+        let mut canvas = Canvas::new();
+        canvas.commit(dot(0.0));
+        canvas.commit(dot(1.0));
+        canvas.set_eraser_mode(EraserMode::Object);
+        canvas.change_tool(ToolType::Eraser);
+
+        canvas.on_mouse_down(InputSample::new(Vec2::ZERO));
+        assert_eq!(stroke_count(&canvas), 1);
+        canvas.undo();
+        assert_eq!(stroke_count(&canvas), 2);
+        canvas.redo();
+        assert_eq!(stroke_count(&canvas), 1);
+
+        // The drag goes on after the undo and redo as a step of its own.
+        canvas.on_mouse_move(InputSample::new(Vec2::new(1.0, 0.0)));
+        canvas.on_mouse_up(InputSample::new(Vec2::new(1.0, 0.0)));
+        assert_eq!(stroke_count(&canvas), 0);
+        canvas.undo();
+        assert_eq!(stroke_count(&canvas), 1);
+        canvas.undo();
+        assert_eq!(canvas.document.layer(0).strokes(), &[dot(0.0), dot(1.0)]);
+    }
+
+    #[test]
+    fn loading_a_project_drops_the_shape_in_progress() {
+        let mut canvas = Canvas::new();
+        canvas.change_tool(ToolType::Polygon);
+        let project = canvas.to_project();
+        canvas.on_mouse_down(InputSample::new(Vec2::ZERO));
+        canvas.on_mouse_up(InputSample::new(Vec2::ZERO));
+        assert!(canvas.current_tool.in_progress());
+
+        canvas.load_project(project);
+        assert!(!canvas.current_tool.in_progress());
+        assert!(canvas.current_vertices.is_empty());
+        canvas.on_key(ToolKey::Enter);
+        assert!(canvas.document.layer(0).strokes().is_empty());
+        assert!(!canvas.can_undo());
+    }
+}
@@ -0,0 +1,797 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use glam::Vec2;
+
+use crate::document::{Document, Layer, Stroke, DEFAULT_BACKGROUND, DEFAULT_SIZE};
+use crate::engine::BlendMode;
+use crate::tessellation::{LineCap, LineJoin};
+use crate::tools::{
+    brush::BrushStroke, bucket::FillStroke, ellipse::EllipseStroke, eraser::EraseStroke, line::{LineStroke, LineStyle},
+    pen::{Anchor, BezierStroke}, polygon::PolygonStroke,
+    pressure::PressureCurve, rectangle::{RectangleStroke, RectangleStyle}, stabilizer::{Stabilizer, StabilizerKind}, ToolType,
+};
+
+/// Magic bytes at the start of every project file.
+const MAGIC: &[u8; 4] = b"R2DP";
+
+/// Current on-disk format version. Bump when the layout changes and keep
+/// reading older versions where possible.
+pub const FORMAT_VERSION: u32 = 1;
+
+pub const FILE_EXTENSION: &str = "r2d";
+
+#[derive(Debug)]
+pub enum ProjectError {
+    Io(io::Error),
+    /// The file does not start with the project magic bytes.
+    NotAProject,
+    /// The file was written by a newer version of the application.
+    UnsupportedVersion(u32),
+    /// The file is truncated or contains invalid data.
+    Corrupt(String),
+}
+
+impl fmt::Display for ProjectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProjectError::Io(err) => write!(f, "I/O error: {}", err),
+            ProjectError::NotAProject => write!(f, "not a project file"),
+            ProjectError::UnsupportedVersion(version) => write!(
+                f,
+                "project file version {} is newer than supported version {}",
+                version, FORMAT_VERSION
+            ),
+            ProjectError::Corrupt(reason) => write!(f, "corrupt project file: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for ProjectError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ProjectError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ProjectError {
+    fn from(err: io::Error) -> Self {
+        ProjectError::Io(err)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolSettings {
+    pub color: [f32; 4],
+    pub brush_size: f32,
+    pub brush_spacing: f32,
+    pub stabilizer: Stabilizer,
+    pub width_curve: PressureCurve,
+    pub opacity_curve: PressureCurve,
+    pub tool_type: ToolType,
+    pub blend_mode: BlendMode,
+}
+
+/// Everything needed to restore a drawing session.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Project {
+    pub document: Document,
+    pub settings: ToolSettings,
+}
+
+impl Project {
+    /// Writes the project next to `path` first and renames it into place,
+    /// so a failed save leaves the previous file intact.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), ProjectError> {
+        let path = path.as_ref();
+        let mut temporary = path.as_os_str().to_owned();
+        temporary.push(".tmp");
+        fs::write(&temporary, self.to_bytes())?;
+        if let Err(err) = fs::rename(&temporary, path) {
+            let _ = fs::remove_file(&temporary);
+            return Err(err.into());
+        }
+        Ok(())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ProjectError> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut w = Writer::default();
+        w.bytes(MAGIC);
+        w.u32(FORMAT_VERSION);
+        let [width, height] = self.document.empty_size();
+        w.u32(width);
+        w.u32(height);
+        w.u8(self.document.background.is_some() as u8);
+        w.color(self.document.background.unwrap_or(DEFAULT_BACKGROUND));
+        w.u8(self.document.infinite as u8);
+        w.color(self.settings.color);
+        w.f32(self.settings.brush_size);
+        w.u8(tool_type_tag(self.settings.tool_type));
+        w.blend_mode(self.settings.blend_mode);
+        w.f32(self.settings.brush_spacing);
+        w.stabilizer(self.settings.stabilizer);
+        w.pressure_curve(&self.settings.width_curve);
+        w.pressure_curve(&self.settings.opacity_curve);
+
+        let layers = self.document.layers();
+        w.u32(layers.len() as u32);
+        for layer in layers {
+            w.string(&layer.name);
+            w.u8(layer.visible as u8);
+            w.f32(layer.opacity);
+            w.blend_mode(layer.blend_mode);
+            w.u8(layer.locked as u8);
+            w.u32(layer.strokes().len() as u32);
+            for stroke in layer.strokes() {
+                write_stroke(&mut w, stroke);
+            }
+        }
+        w.buf
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ProjectError> {
+        if bytes.len() < MAGIC.len() || &bytes[..MAGIC.len()] != MAGIC {
+            return Err(ProjectError::NotAProject);
+        }
+        let mut r = Reader {
+            buf: &bytes[MAGIC.len()..],
+        };
+
+        let version = r.u32()?;
+        if version > FORMAT_VERSION {
+            return Err(ProjectError::UnsupportedVersion(version));
+        }
+        if version == 0 {
+            return Err(ProjectError::Corrupt("invalid format version 0".into()));
+        }
+
+        let document_size = r.document_size()?;
+        let has_background = r.bool()?;
+        let background = r.color()?;
+        let infinite = r.bool()?;
+        let settings = ToolSettings {
+            color: r.color()?,
+            brush_size: r.f32()?,
+            tool_type: tool_type_from_tag(r.u8()?)?,
+            blend_mode: r.blend_mode()?,
+            brush_spacing: r.f32()?,
+            stabilizer: r.stabilizer()?,
+            width_curve: r.pressure_curve()?,
+            opacity_curve: r.pressure_curve()?,
+        };
+
+        let count = r.count(1)?;
+        let mut layers = Vec::with_capacity(count);
+        for _ in 0..count {
+            let mut layer = Layer::new(r.string()?);
+            layer.visible = r.bool()?;
+            layer.opacity = r.f32()?.clamp(0.0, 1.0);
+            layer.blend_mode = r.blend_mode()?;
+            layer.locked = r.bool()?;
+            layers.push(layer.with_strokes(read_strokes(&mut r)?));
+        }
+        let mut document = Document::from_layers(layers);
+        document.set_size(document_size);
+        document.background = has_background.then_some(background);
+        document.infinite = infinite;
+
+        if !r.buf.is_empty() {
+            return Err(ProjectError::Corrupt(format!(
+                "{} unexpected trailing bytes",
+                r.buf.len()
+            )));
+        }
+
+        Ok(Self { document, settings })
+    }
+}
+
+const STROKE_BRUSH: u8 = 0;
+const STROKE_LINE: u8 = 1;
+const STROKE_RECTANGLE: u8 = 2;
+const STROKE_ERASE: u8 = 3;
+const STROKE_ELLIPSE: u8 = 4;
+const STROKE_POLYGON: u8 = 5;
+const STROKE_BEZIER: u8 = 6;
+const STROKE_FILL: u8 = 7;
+
+fn write_stroke(w: &mut Writer, stroke: &Stroke) {
+    match stroke {
+        Stroke::Brush(s) => {
+            w.u8(STROKE_BRUSH);
+            w.f32(s.size);
+            w.f32(s.spacing);
+            w.color(s.color);
+            w.blend_mode(s.blend_mode);
+            w.stabilizer(s.stabilizer);
+            w.pressure_curve(&s.width_curve);
+            w.pressure_curve(&s.opacity_curve);
+            w.points(&s.points);
+            s.pressures.iter().for_each(|p| w.f32(*p));
+        }
+        Stroke::Line(s) => {
+            w.u8(STROKE_LINE);
+            w.vec2(s.start);
+            w.vec2(s.end);
+            w.f32(s.thickness);
+            w.color(s.color);
+            w.blend_mode(s.blend_mode);
+            w.line_style(s.style);
+        }
+        Stroke::Rectangle(s) => {
+            w.u8(STROKE_RECTANGLE);
+            w.vec2(s.start);
+            w.vec2(s.end);
+            w.color(s.color);
+            w.blend_mode(s.blend_mode);
+            w.u8(s.style as u8);
+            w.f32(s.thickness);
+            w.f32(s.corner_radius);
+            w.color(s.fill_color);
+        }
+        Stroke::Ellipse(s) => {
+            w.u8(STROKE_ELLIPSE);
+            w.vec2(s.center);
+            w.vec2(s.radii);
+            w.u8(s.filled as u8);
+            w.f32(s.thickness);
+            w.color(s.color);
+            w.blend_mode(s.blend_mode);
+        }
+        Stroke::Polygon(s) => {
+            w.u8(STROKE_POLYGON);
+            w.points(&s.points);
+            w.u8(s.closed as u8);
+            w.u8(s.filled as u8);
+            w.f32(s.thickness);
+            w.color(s.color);
+            w.blend_mode(s.blend_mode);
+        }
+        Stroke::Bezier(s) => {
+            w.u8(STROKE_BEZIER);
+            w.anchors(&s.anchors);
+            w.u8(s.closed as u8);
+            w.f32(s.thickness);
+            w.line_style(s.style);
+            w.color(s.color);
+            w.blend_mode(s.blend_mode);
+        }
+        Stroke::Fill(s) => {
+            w.u8(STROKE_FILL);
+            w.rects(&s.rects);
+            w.color(s.color);
+            w.blend_mode(s.blend_mode);
+        }
+        Stroke::Erase(s) => {
+            w.u8(STROKE_ERASE);
+            w.f32(s.size);
+            w.points(&s.points);
+        }
+    }
+}
+
+fn read_strokes(r: &mut Reader) -> Result<Vec<Stroke>, ProjectError> {
+    let count = r.count(1)?;
+    (0..count).map(|_| read_stroke(r)).collect()
+}
+
+fn read_stroke(r: &mut Reader) -> Result<Stroke, ProjectError> {
+    let stroke = match r.u8()? {
+        STROKE_BRUSH => {
+            let size = r.f32()?;
+            let spacing = r.f32()?;
+            let color = r.color()?;
+            let blend_mode = r.blend_mode()?;
+            let stabilizer = r.stabilizer()?;
+            let width_curve = r.pressure_curve()?;
+            let opacity_curve = r.pressure_curve()?;
+            let points = r.points()?;
+            Stroke::Brush(BrushStroke {
+                pressures: r.pressures(points.len())?,
+                points,
+                size,
+                spacing,
+                color,
+                blend_mode,
+                stabilizer,
+                width_curve,
+                opacity_curve,
+            })
+        }
+        STROKE_LINE => Stroke::Line(LineStroke {
+            start: r.vec2()?,
+            end: r.vec2()?,
+            thickness: r.f32()?,
+            color: r.color()?,
+            blend_mode: r.blend_mode()?,
+            style: r.line_style()?,
+        }),
+        STROKE_RECTANGLE => {
+            let start = r.vec2()?;
+            let end = r.vec2()?;
+            let color = r.color()?;
+            let blend_mode = r.blend_mode()?;
+            let index = r.u8()?;
+            let style = RectangleStyle::from_index(index as u32)
+                .ok_or_else(|| ProjectError::Corrupt(format!("unknown rectangle style {}", index)))?;
+            Stroke::Rectangle(RectangleStroke {
+                start,
+                end,
+                style,
+                thickness: r.f32()?,
+                corner_radius: r.f32()?,
+                color,
+                fill_color: r.color()?,
+                blend_mode,
+            })
+        }
+        STROKE_ELLIPSE => Stroke::Ellipse(EllipseStroke {
+            center: r.vec2()?,
+            radii: r.vec2()?,
+            filled: r.bool()?,
+            thickness: r.f32()?,
+            color: r.color()?,
+            blend_mode: r.blend_mode()?,
+        }),
+        STROKE_POLYGON => Stroke::Polygon(PolygonStroke {
+            points: r.points()?,
+            closed: r.bool()?,
+            filled: r.bool()?,
+            thickness: r.f32()?,
+            color: r.color()?,
+            blend_mode: r.blend_mode()?,
+        }),
+        STROKE_BEZIER => Stroke::Bezier(BezierStroke {
+            anchors: r.anchors()?,
+            closed: r.bool()?,
+            thickness: r.f32()?,
+            style: r.line_style()?,
+            color: r.color()?,
+            blend_mode: r.blend_mode()?,
+        }),
+        STROKE_FILL => Stroke::Fill(FillStroke {
+            rects: r.rects()?,
+            color: r.color()?,
+            blend_mode: r.blend_mode()?,
+        }),
+        STROKE_ERASE => {
+            let size = r.f32()?;
+            Stroke::Erase(EraseStroke {
+                points: r.points()?,
+                size,
+            })
+        }
+        tag => return Err(ProjectError::Corrupt(format!("unknown stroke type {}", tag))),
+    };
+    Ok(stroke)
+}
+
+fn tool_type_tag(tool_type: ToolType) -> u8 {
+    match tool_type {
+        ToolType::Brush => 0,
+        ToolType::Eraser => 1,
+        ToolType::Line => 2,
+        ToolType::Rectangle => 3,
+        ToolType::Ellipse => 4,
+        ToolType::Polygon => 5,
+        ToolType::Pen => 6,
+        ToolType::Bucket => 7,
+        ToolType::Select => 8,
+    }
+}
+
+fn tool_type_from_tag(tag: u8) -> Result<ToolType, ProjectError> {
+    match tag {
+        0 => Ok(ToolType::Brush),
+        1 => Ok(ToolType::Eraser),
+        2 => Ok(ToolType::Line),
+        3 => Ok(ToolType::Rectangle),
+        4 => Ok(ToolType::Ellipse),
+        5 => Ok(ToolType::Polygon),
+        6 => Ok(ToolType::Pen),
+        7 => Ok(ToolType::Bucket),
+        8 => Ok(ToolType::Select),
+        _ => Err(ProjectError::Corrupt(format!("unknown tool type {}", tag))),
+    }
+}
+
+// All values are stored little-endian.
+#[derive(Default)]
+struct Writer {
+    buf: Vec<u8>,
+}
+
+impl Writer {
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    fn u8(&mut self, value: u8) {
+        self.buf.push(value);
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn blend_mode(&mut self, value: BlendMode) {
+        self.u8(value as u8);
+    }
+
+    fn stabilizer(&mut self, value: Stabilizer) {
+        self.u8(value.kind as u8);
+        self.f32(value.strength);
+    }
+
+    fn line_style(&mut self, value: LineStyle) {
+        self.u8(value.cap as u8);
+        self.u8(value.join as u8);
+        self.f32(value.dash);
+        self.f32(value.gap);
+        self.u8(value.start_arrow as u8);
+        self.u8(value.end_arrow as u8);
+    }
+
+    fn f32(&mut self, value: f32) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn vec2(&mut self, value: Vec2) {
+        self.f32(value.x);
+        self.f32(value.y);
+    }
+
+    fn color(&mut self, color: [f32; 4]) {
+        color.iter().for_each(|c| self.f32(*c));
+    }
+
+    fn string(&mut self, value: &str) {
+        self.u32(value.len() as u32);
+        self.bytes(value.as_bytes());
+    }
+
+    fn points(&mut self, points: &[Vec2]) {
+        self.u32(points.len() as u32);
+        points.iter().for_each(|p| self.vec2(*p));
+    }
+
+    fn anchors(&mut self, anchors: &[Anchor]) {
+        self.u32(anchors.len() as u32);
+        for anchor in anchors {
+            self.vec2(anchor.point);
+            self.vec2(anchor.handle_in);
+            self.vec2(anchor.handle_out);
+        }
+    }
+
+    fn rects(&mut self, rects: &[[Vec2; 2]]) {
+        self.u32(rects.len() as u32);
+        rects.iter().flatten().for_each(|p| self.vec2(*p));
+    }
+
+    fn pressure_curve(&mut self, curve: &PressureCurve) {
+        self.points(curve.points());
+    }
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+}
+
+impl Reader<'_> {
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], ProjectError> {
+        if self.buf.len() < N {
+            return Err(ProjectError::Corrupt("unexpected end of file".into()));
+        }
+        let (head, rest) = self.buf.split_at(N);
+        self.buf = rest;
+        Ok(head.try_into().unwrap())
+    }
+
+    fn u8(&mut self) -> Result<u8, ProjectError> {
+        Ok(self.take::<1>()?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, ProjectError> {
+        Ok(u32::from_le_bytes(self.take()?))
+    }
+
+    /// A zero size falls back to the default.
+    fn document_size(&mut self) -> Result<[u32; 2], ProjectError> {
+        let size = [self.u32()?, self.u32()?];
+        if size.contains(&0) {
+            return Ok(DEFAULT_SIZE);
+        }
+        Ok(size)
+    }
+
+    fn stabilizer(&mut self) -> Result<Stabilizer, ProjectError> {
+        let index = self.u8()?;
+        let kind = StabilizerKind::from_index(index as u32)
+            .ok_or_else(|| ProjectError::Corrupt(format!("unknown stabilizer {}", index)))?;
+        Ok(Stabilizer::new(kind, self.f32()?))
+    }
+
+    fn line_style(&mut self) -> Result<LineStyle, ProjectError> {
+        let index = self.u8()?;
+        let cap = LineCap::from_index(index as u32)
+            .ok_or_else(|| ProjectError::Corrupt(format!("unknown line cap {}", index)))?;
+        let index = self.u8()?;
+        let join = LineJoin::from_index(index as u32)
+            .ok_or_else(|| ProjectError::Corrupt(format!("unknown line join {}", index)))?;
+        Ok(LineStyle {
+            cap,
+            join,
+            dash: self.f32()?,
+            gap: self.f32()?,
+            start_arrow: self.bool()?,
+            end_arrow: self.bool()?,
+        })
+    }
+
+    fn blend_mode(&mut self) -> Result<BlendMode, ProjectError> {
+        let index = self.u8()?;
+        BlendMode::from_index(index as u32)
+            .ok_or_else(|| ProjectError::Corrupt(format!("unknown blend mode {}", index)))
+    }
+
+    fn bool(&mut self) -> Result<bool, ProjectError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            value => Err(ProjectError::Corrupt(format!("invalid boolean {}", value))),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, ProjectError> {
+        let len = self.count(1)?;
+        let (bytes, rest) = self.buf.split_at(len);
+        self.buf = rest;
+        String::from_utf8(bytes.to_vec())
+            .map_err(|_| ProjectError::Corrupt("invalid UTF-8 in string".into()))
+    }
+
+    fn f32(&mut self) -> Result<f32, ProjectError> {
+        let value = f32::from_le_bytes(self.take()?);
+        if !value.is_finite() {
+            return Err(ProjectError::Corrupt("non-finite number".into()));
+        }
+        Ok(value)
+    }
+
+    fn vec2(&mut self) -> Result<Vec2, ProjectError> {
+        Ok(Vec2::new(self.f32()?, self.f32()?))
+    }
+
+    fn color(&mut self) -> Result<[f32; 4], ProjectError> {
+        Ok([self.f32()?, self.f32()?, self.f32()?, self.f32()?])
+    }
+
+    /// Reads an element count, rejecting counts that cannot fit in the rest
+    /// of the file so corrupt files don't trigger huge allocations.
+    fn count(&mut self, min_element_size: usize) -> Result<usize, ProjectError> {
+        let count = self.u32()? as usize;
+        if count.saturating_mul(min_element_size) > self.buf.len() {
+            return Err(ProjectError::Corrupt(format!(
+                "element count {} exceeds file size",
+                count
+            )));
+        }
+        Ok(count)
+    }
+
+    fn points(&mut self) -> Result<Vec<Vec2>, ProjectError> {
+        let count = self.count(8)?;
+        (0..count).map(|_| self.vec2()).collect()
+    }
+
+    fn anchors(&mut self) -> Result<Vec<Anchor>, ProjectError> {
+        let count = self.count(24)?;
+        (0..count)
+            .map(|_| {
+                Ok(Anchor {
+                    point: self.vec2()?,
+                    handle_in: self.vec2()?,
+                    handle_out: self.vec2()?,
+                })
+            })
+            .collect()
+    }
+
+    fn rects(&mut self) -> Result<Vec<[Vec2; 2]>, ProjectError> {
+        let count = self.count(16)?;
+        (0..count).map(|_| Ok([self.vec2()?, self.vec2()?])).collect()
+    }
+
+    fn pressure_curve(&mut self) -> Result<PressureCurve, ProjectError> {
+        PressureCurve::from_points(self.points()?)
+            .ok_or_else(|| ProjectError::Corrupt("invalid pressure curve".into()))
+    }
+
+    /// Pressure for each of `count` brush points.
+    fn pressures(&mut self, count: usize) -> Result<Vec<f32>, ProjectError> {
+        (0..count).map(|_| self.f32()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn project() -> Project {
+        let style = LineStyle {
+            cap: LineCap::Round,
+            join: LineJoin::Bevel,
+            dash: 0.1,
+            gap: 0.05,
+            start_arrow: true,
+            end_arrow: false,
+        };
+        let strokes = vec![
+            Stroke::Brush(BrushStroke {
+                points: vec![Vec2::ZERO, Vec2::new(0.5, 0.25)],
+                pressures: vec![0.5, 1.0],
+                size: 0.1,
+                spacing: 0.2,
+                color: [1.0, 0.0, 0.0, 1.0],
+                blend_mode: BlendMode::Multiply,
+                stabilizer: Stabilizer::new(StabilizerKind::LazyMouse, 0.5),
+                width_curve: PressureCurve::linear(0.2, 1.0),
+                opacity_curve: PressureCurve::default(),
+            }),
+            Stroke::Line(LineStroke {
+                start: Vec2::new(-1.0, 0.0),
+                end: Vec2::new(1.0, 0.5),
+                thickness: 0.05,
+                style,
+                color: [0.0, 1.0, 0.0, 0.5],
+                blend_mode: BlendMode::Normal,
+            }),
+            Stroke::Rectangle(RectangleStroke {
+                start: Vec2::new(-0.5, -0.5),
+                end: Vec2::new(0.5, 0.25),
+                style: RectangleStyle::FillAndOutline,
+                thickness: 0.02,
+                corner_radius: 0.1,
+                color: [0.0, 0.0, 1.0, 1.0],
+                fill_color: [1.0, 1.0, 0.0, 0.5],
+                blend_mode: BlendMode::Screen,
+            }),
+            Stroke::Erase(EraseStroke {
+                points: vec![Vec2::new(0.1, 0.1), Vec2::new(0.2, 0.3)],
+                size: 0.05,
+            }),
+            Stroke::Ellipse(EllipseStroke {
+                center: Vec2::new(0.3, -0.2),
+                radii: Vec2::new(0.2, 0.1),
+                filled: false,
+                thickness: 0.01,
+                color: [0.5, 0.5, 0.5, 1.0],
+                blend_mode: BlendMode::Normal,
+            }),
+            Stroke::Polygon(PolygonStroke {
+                points: vec![Vec2::ZERO, Vec2::X, Vec2::Y],
+                closed: true,
+                filled: true,
+                thickness: 0.03,
+                color: [0.2, 0.4, 0.6, 0.8],
+                blend_mode: BlendMode::Normal,
+            }),
+            Stroke::Bezier(BezierStroke {
+                anchors: vec![
+                    Anchor {
+                        point: Vec2::ZERO,
+                        handle_in: Vec2::new(-0.1, 0.0),
+                        handle_out: Vec2::new(0.1, 0.0),
+                    },
+                    Anchor {
+                        point: Vec2::ONE,
+                        handle_in: Vec2::new(0.9, 1.0),
+                        handle_out: Vec2::new(1.1, 1.0),
+                    },
+                ],
+                closed: false,
+                thickness: 0.02,
+                style,
+                color: [0.0, 0.0, 0.0, 1.0],
+                blend_mode: BlendMode::Normal,
+            }),
+            Stroke::Fill(FillStroke {
+                rects: vec![[Vec2::ZERO, Vec2::splat(0.1)], [Vec2::splat(0.1), Vec2::splat(0.3)]],
+                color: [0.9, 0.1, 0.1, 1.0],
+                blend_mode: BlendMode::Normal,
+            }),
+        ];
+
+        let mut hidden = Layer::new("Hidden \u{e9}");
+        hidden.visible = false;
+        hidden.locked = true;
+        hidden.opacity = 0.5;
+        hidden.blend_mode = BlendMode::Screen;
+        let mut document = Document::from_layers(vec![Layer::new("Layer 1").with_strokes(strokes), hidden]);
+        document.set_size([640, 480]);
+        document.background = Some([0.1, 0.2, 0.3, 1.0]);
+
+        Project {
+            document,
+            settings: ToolSettings {
+                color: [0.25, 0.5, 0.75, 1.0],
+                brush_size: 0.04,
+                brush_spacing: 0.3,
+                stabilizer: Stabilizer::new(StabilizerKind::CatmullRom, 0.7),
+                width_curve: PressureCurve::linear(0.0, 1.0),
+                opacity_curve: PressureCurve::linear(0.5, 1.0),
+                tool_type: ToolType::Pen,
+                blend_mode: BlendMode::Multiply,
+            },
+        }
+    }
+
+    fn is_corrupt(result: Result<Project, ProjectError>) -> bool {
+        matches!(result, Err(ProjectError::Corrupt(_)))
+    }
+
+    #[test]
+    fn round_trips_every_stroke() {
+        let project = project();
+        assert_eq!(Project::from_bytes(&project.to_bytes()).unwrap(), project);
+    }
+
+    #[test]
+    fn saves_and_loads_files() {
+        let path = std::env::temp_dir().join(format!("renderer_2d_{}.{}", std::process::id(), FILE_EXTENSION));
+        let project = project();
+        project.save(&path).unwrap();
+        let loaded = Project::load(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unwrap(), project);
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        assert!(matches!(Project::from_bytes(b""), Err(ProjectError::NotAProject)));
+        assert!(matches!(Project::from_bytes(b"\x89PNG\r\n"), Err(ProjectError::NotAProject)));
+    }
+
+    #[test]
+    fn rejects_newer_versions() {
+        let mut bytes = project().to_bytes();
+        bytes[MAGIC.len()..MAGIC.len() + 4].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        assert!(matches!(
+            Project::from_bytes(&bytes),
+            Err(ProjectError::UnsupportedVersion(version)) if version == FORMAT_VERSION + 1
+        ));
+    }
+
+    #[test]
+    fn rejects_truncated_files() {
+        let bytes = project().to_bytes();
+        for len in MAGIC.len()..bytes.len() {
+            assert!(is_corrupt(Project::from_bytes(&bytes[..len])), "truncated to {} bytes", len);
+        }
+        let mut trailing = bytes;
+        trailing.push(0);
+        assert!(is_corrupt(Project::from_bytes(&trailing)));
+    }
+
+    #[test]
+    fn rejects_corrupt_lengths() {
+        let project = Project {
+            document: Document::new(),
+            ..project()
+        };
+        let mut bytes = project.to_bytes();
+        // An empty document ends with its only layer's stroke count.
+        let len = bytes.len();
+        bytes[len - 4..].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(is_corrupt(Project::from_bytes(&bytes)));
+    }
+}
@@ -8,15 +8,20 @@ struct VertexOutput {
     @location(0) color: vec4<f32>,
 };
 
+// Maps canvas coordinates to clip space, for panning and zooming.
+@group(0) @binding(0)
+var<uniform> view: mat4x4<f32>;
+
 @vertex
 fn vs_main(in: VertexInput) -> VertexOutput {
     var out: VertexOutput;
-    out.clip_position = vec4<f32>(in.position, 0.0, 1.0);
+    out.clip_position = view * vec4<f32>(in.position, 0.0, 1.0);
     out.color = in.color;
     return out;
 }
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    return in.color;
+    // Premultiplied alpha, to match the pipeline's blend state.
+    return vec4<f32>(in.color.rgb * in.color.a, in.color.a);
 } 
\ No newline at end of file
@@ -0,0 +1,306 @@
+use std::collections::HashMap;
+
+use glam::{IVec2, Vec2};
+
+/// Side of a grid cell in canvas units.
+const CELL_SIZE: f32 = 0.25;
+
+/// Entries covering more cells than this go in one list that every query
+/// checks, instead of into each cell.
+const MAX_CELLS_PER_ENTRY: i64 = 64;
+
+/// Minimum and maximum corner of an axis-aligned box.
+pub type Bounds = (Vec2, Vec2);
+
+/// Smallest box containing both `a` and `b`.
+pub fn union(a: Bounds, b: Bounds) -> Bounds {
+    (a.0.min(b.0), a.1.max(b.1))
+}
+
+/// The box `a` and `b` share, if they overlap.
+pub fn intersection(a: Bounds, b: Bounds) -> Option<Bounds> {
+    overlaps(a, b).then(|| (a.0.max(b.0), a.1.min(b.1)))
+}
+
+fn overlaps(a: Bounds, b: Bounds) -> bool {
+    a.0.cmple(b.1).all() && b.0.cmple(a.1).all()
+}
+
+/// Bounding boxes of a list of strokes, bucketed into a uniform grid so the
+/// strokes in an area can be found without visiting every one. Entries are
+/// addressed by their position in the list and follow insertions and
+/// removals the way the list does.
+///
+/// Cells hold a key per entry rather than its position, so inserting or
+/// removing an entry only touches its own cells. Keys grow along the list
+/// with gaps between them for later insertions; they're only handed out
+/// again when an insertion finds no gap left.
+#[derive(Debug, Clone, Default)]
+pub struct GridIndex {
+    /// The key and box of each entry, in list order, with `None` for
+    /// entries that draw nothing. Keys increase along the list.
+    entries: Vec<(u64, Option<Bounds>)>,
+    cells: HashMap<IVec2, Vec<u64>>,
+    /// Keys of entries too big to add to each of their cells.
+    large: Vec<u64>,
+    /// Union of all boxes.
+    total: Option<Bounds>,
+}
+
+/// Distance between the keys handed out to consecutive entries.
+const KEY_GAP: u64 = 1 << 32;
+
+impl PartialEq for GridIndex {
+    // The keys and cells depend on the edit history, so only the boxes are
+    // compared.
+    fn eq(&self, other: &Self) -> bool {
+        self.entries.len() == other.entries.len()
+            && self.entries.iter().zip(&other.entries).all(|(a, b)| a.1 == b.1)
+    }
+}
+
+impl GridIndex {
+    pub fn new(bounds: Vec<Option<Bounds>>) -> Self {
+        let mut index = Self::default();
+        index.rebuild(bounds);
+        index
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The box of the entry at `index`.
+    pub fn bounds(&self, index: usize) -> Option<Bounds> {
+        self.entries[index].1
+    }
+
+    /// Union of all boxes, or `None` if no entry draws anything.
+    pub fn total_bounds(&self) -> Option<Bounds> {
+        self.total
+    }
+
+    /// Adds an entry at `index`, shifting later entries up by one.
+    pub fn insert(&mut self, index: usize, bounds: Option<Bounds>) {
+        let before = index.checked_sub(1).map_or(0, |previous| self.entries[previous].0);
+        let key = match self.entries.get(index) {
+            Some(&(after, _)) => before + (after - before) / 2,
+            None => before.saturating_add(KEY_GAP),
+        };
+        if key == before || self.entries.get(index).is_some_and(|&(after, _)| key == after) {
+            // No gap left around `index`; hand out fresh keys to everything.
+            let mut all: Vec<Option<Bounds>> = self.entries.iter().map(|entry| entry.1).collect();
+            all.insert(index, bounds);
+            self.rebuild(all);
+            return;
+        }
+        self.entries.insert(index, (key, bounds));
+        self.add_to_cells(key, bounds);
+    }
+
+    /// Removes the entry at `index`, shifting later entries down by one.
+    pub fn remove(&mut self, index: usize) {
+        let (key, bounds) = self.entries.remove(index);
+        self.remove_from_cells(key, bounds);
+        if self.touches_total(bounds) {
+            self.recompute_total();
+        }
+    }
+
+    /// Changes the box of the entry at `index`.
+    pub fn replace(&mut self, index: usize, bounds: Option<Bounds>) {
+        let (key, old) = self.entries[index];
+        self.remove_from_cells(key, old);
+        self.entries[index].1 = bounds;
+        if bounds != old && self.touches_total(old) {
+            self.recompute_total();
+        }
+        self.add_to_cells(key, bounds);
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Bytes owned by the index on the heap, roughly.
+    pub fn heap_size(&self) -> usize {
+        let cells: usize = self
+            .cells
+            .values()
+            .map(|keys| std::mem::size_of::<(IVec2, Vec<u64>)>() + keys.capacity() * std::mem::size_of::<u64>())
+            .sum();
+        self.entries.capacity() * std::mem::size_of::<(u64, Option<Bounds>)>()
+            + cells
+            + self.large.capacity() * std::mem::size_of::<u64>()
+    }
+
+    /// Indices, in ascending order, of the entries whose box overlaps `area`.
+    pub fn query(&self, area: Bounds) -> Vec<usize> {
+        let overlapping = |(_, bounds): &(u64, Option<Bounds>)| bounds.is_some_and(|bounds| overlaps(bounds, area));
+        let (min_cell, max_cell) = cell_range(area);
+        if cell_count(min_cell, max_cell) > self.entries.len() as i64 {
+            // Looking through every cell would take longer than every entry.
+            return (0..self.entries.len()).filter(|&index| overlapping(&self.entries[index])).collect();
+        }
+
+        let mut keys = self.large.clone();
+        for y in min_cell.y..=max_cell.y {
+            for x in min_cell.x..=max_cell.x {
+                if let Some(cell) = self.cells.get(&IVec2::new(x, y)) {
+                    keys.extend_from_slice(cell);
+                }
+            }
+        }
+        keys.sort_unstable();
+        keys.dedup();
+        // Keys increase along the list, so positions come out in order.
+        keys.into_iter()
+            .filter_map(|key| self.entries.binary_search_by_key(&key, |entry| entry.0).ok())
+            .filter(|&index| overlapping(&self.entries[index]))
+            .collect()
+    }
+
+    /// Replaces every entry with `bounds`, spacing the keys evenly.
+    fn rebuild(&mut self, bounds: Vec<Option<Bounds>>) {
+        self.clear();
+        self.entries = bounds
+            .into_iter()
+            .enumerate()
+            .map(|(index, bounds)| ((index as u64 + 1) * KEY_GAP, bounds))
+            .collect();
+        for index in 0..self.entries.len() {
+            let (key, bounds) = self.entries[index];
+            self.add_to_cells(key, bounds);
+        }
+    }
+
+    fn add_to_cells(&mut self, key: u64, bounds: Option<Bounds>) {
+        let Some(bounds) = bounds else {
+            return;
+        };
+        self.total = Some(self.total.map_or(bounds, |total| union(total, bounds)));
+        let (min_cell, max_cell) = cell_range(bounds);
+        if cell_count(min_cell, max_cell) > MAX_CELLS_PER_ENTRY {
+            self.large.push(key);
+            return;
+        }
+        for y in min_cell.y..=max_cell.y {
+            for x in min_cell.x..=max_cell.x {
+                self.cells.entry(IVec2::new(x, y)).or_default().push(key);
+            }
+        }
+    }
+
+    fn remove_from_cells(&mut self, key: u64, bounds: Option<Bounds>) {
+        let Some(bounds) = bounds else {
+            return;
+        };
+        let (min_cell, max_cell) = cell_range(bounds);
+        if cell_count(min_cell, max_cell) > MAX_CELLS_PER_ENTRY {
+            self.large.retain(|&other| other != key);
+            return;
+        }
+        for y in min_cell.y..=max_cell.y {
+            for x in min_cell.x..=max_cell.x {
+                let cell = IVec2::new(x, y);
+                if let Some(keys) = self.cells.get_mut(&cell) {
+                    keys.retain(|&other| other != key);
+                    if keys.is_empty() {
+                        self.cells.remove(&cell);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Whether `bounds` reaches an edge of the total box, so the total may
+    /// shrink without it. Boxes inside the total can't change it.
+    fn touches_total(&self, bounds: Option<Bounds>) -> bool {
+        match (bounds, self.total) {
+            (Some((min, max)), Some((total_min, total_max))) => {
+                min.cmple(total_min).any() || max.cmpge(total_max).any()
+            }
+            _ => false,
+        }
+    }
+
+    fn recompute_total(&mut self) {
+        self.total = self.entries.iter().filter_map(|entry| entry.1).reduce(union);
+    }
+}
+
+/// First and last cell covered by `bounds`.
+fn cell_range((min, max): Bounds) -> (IVec2, IVec2) {
+    ((min / CELL_SIZE).floor().as_ivec2(), (max / CELL_SIZE).floor().as_ivec2())
+}
+
+/// Number of cells from `min` to `max` inclusive, which can exceed `i32`.
+fn cell_count(min: IVec2, max: IVec2) -> i64 {
+    let size = max.as_i64vec2() - min.as_i64vec2() + 1;
+    size.x * size.y
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square(x: f32, y: f32, size: f32) -> Option<Bounds> {
+        Some((Vec2::new(x, y), Vec2::new(x + size, y + size)))
+    }
+
+    fn area(x0: f32, y0: f32, x1: f32, y1: f32) -> Bounds {
+        (Vec2::new(x0, y0), Vec2::new(x1, y1))
+    }
+
+    #[test]
+    fn finds_only_overlapping_entries() {
+        let index = GridIndex::new(vec![
+            square(0.0, 0.0, 0.1),
+            square(5.0, 5.0, 0.1),
+            None,
+            square(-100.0, -100.0, 200.0),
+            square(0.05, 0.05, 0.01),
+        ]);
+        assert_eq!(index.query(area(-0.1, -0.1, 0.2, 0.2)), vec![0, 3, 4]);
+        assert_eq!(index.query(area(4.0, 4.0, 6.0, 6.0)), vec![1, 3]);
+        assert_eq!(index.query(area(200.0, 200.0, 201.0, 201.0)), Vec::<usize>::new());
+        assert_eq!(index.total_bounds(), Some(area(-100.0, -100.0, 100.0, 100.0)));
+    }
+
+    #[test]
+    fn follows_insertions_and_removals() {
+        let mut index = GridIndex::new(vec![square(0.0, 0.0, 0.1), square(1.0, 1.0, 0.1)]);
+        index.insert(0, square(1.0, 1.0, 0.1));
+        assert_eq!(index.query(area(0.9, 0.9, 1.2, 1.2)), vec![0, 2]);
+        assert_eq!(index.query(area(0.0, 0.0, 0.1, 0.1)), vec![1]);
+
+        index.remove(1);
+        assert_eq!(index.query(area(0.0, 0.0, 0.1, 0.1)), Vec::<usize>::new());
+        assert_eq!(index.query(area(0.9, 0.9, 1.2, 1.2)), vec![0, 1]);
+
+        index.replace(0, square(-3.0, -3.0, 0.1));
+        assert_eq!(index.query(area(0.9, 0.9, 1.2, 1.2)), vec![1]);
+        assert_eq!(index.total_bounds(), Some(area(-3.0, -3.0, 1.1, 1.1)));
+        assert_eq!(index, GridIndex::new(vec![square(-3.0, -3.0, 0.1), square(1.0, 1.0, 0.1)]));
+    }
+
+    #[test]
+    fn keeps_order_when_keys_run_out() {
+        // Inserting at the front halves the gap each time until keys have
+        // to be handed out again.
+        let mut index = GridIndex::new(vec![square(0.0, 0.0, 0.1)]);
+        let mut expected = vec![square(0.0, 0.0, 0.1)];
+        for i in 0..100 {
+            let bounds = square(i as f32 * 0.01, 0.0, 0.1);
+            index.insert(0, bounds);
+            expected.insert(0, bounds);
+        }
+        assert_eq!(index, GridIndex::new(expected));
+        assert_eq!(index.query(area(0.0, 0.0, 0.005, 0.1)), vec![99, 100]);
+        assert_eq!(index.query(area(0.0, 0.0, 2.0, 2.0)), (0..=100).collect::<Vec<_>>());
+    }
+}
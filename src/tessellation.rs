@@ -0,0 +1,734 @@
+use std::f32::consts::{PI, TAU};
+
+use glam::Vec2;
+
+use crate::engine::Vertex;
+
+/// Largest gap, in canvas units, allowed between a round cap or join and the
+/// polygon approximating it.
+const ROUND_TOLERANCE: f32 = 0.0002;
+
+/// Largest distance, in canvas units, allowed between a curve and the
+/// polyline approximating it.
+const FLATTEN_TOLERANCE: f32 = 0.0002;
+
+/// Deepest a curve is split while flattening, for curves too wild to meet
+/// the tolerance.
+const MAX_FLATTEN_DEPTH: u32 = 16;
+
+/// Miter joins longer than this many line widths become bevels, as in SVG.
+pub const MITER_LIMIT: f32 = 4.0;
+
+/// How the open ends of a stroked path are drawn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LineCap {
+    /// The stroke stops square at the end point.
+    #[default]
+    Butt,
+    /// A half circle around the end point.
+    Round,
+    /// The stroke continues half its width past the end point.
+    Square,
+}
+
+impl LineCap {
+    pub const ALL: [LineCap; 3] = [LineCap::Butt, LineCap::Round, LineCap::Square];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            LineCap::Butt => "Butt",
+            LineCap::Round => "Round",
+            LineCap::Square => "Square",
+        }
+    }
+
+    pub fn from_index(index: u32) -> Option<Self> {
+        Self::ALL.get(index as usize).copied()
+    }
+}
+
+/// How the corners of a stroked path are drawn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LineJoin {
+    /// Sharp corners, beveled past `MITER_LIMIT`.
+    #[default]
+    Miter,
+    Round,
+    Bevel,
+}
+
+impl LineJoin {
+    pub const ALL: [LineJoin; 3] = [LineJoin::Miter, LineJoin::Round, LineJoin::Bevel];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            LineJoin::Miter => "Miter",
+            LineJoin::Round => "Round",
+            LineJoin::Bevel => "Bevel",
+        }
+    }
+
+    pub fn from_index(index: u32) -> Option<Self> {
+        Self::ALL.get(index as usize).copied()
+    }
+}
+
+/// Resamples a polyline so consecutive points are `spacing` apart along its
+/// length. The first and last points are always kept. A non-positive
+/// spacing returns the points unchanged.
+pub fn resample(points: &[Vec2], spacing: f32) -> Vec<Vec2> {
+    let (Some(&first), Some(&last)) = (points.first(), points.last()) else {
+        return Vec::new();
+    };
+    if spacing <= 0.0 || !spacing.is_finite() {
+        return points.to_vec();
+    }
+
+    let mut resampled = vec![first];
+    // Distance travelled along the path since the last emitted point.
+    let mut travelled = 0.0;
+    for segment in points.windows(2) {
+        let (start, end) = (segment[0], segment[1]);
+        let length = start.distance(end);
+        let mut along = spacing - travelled;
+        while along <= length {
+            resampled.push(start.lerp(end, along / length));
+            along += spacing;
+        }
+        travelled = length - (along - spacing);
+    }
+    if resampled.last() != Some(&last) {
+        resampled.push(last);
+    }
+    resampled
+}
+
+/// Tessellates a polyline `radius` wide on each side, with round caps and
+/// joins. A single point becomes a dot. Caps and joins overlap the segment
+/// quads; the renderer draws each stroke so the overlap doesn't show.
+pub fn round_polyline(points: &[Vec2], radius: f32, color: [f32; 4]) -> Vec<Vertex> {
+    variable_round_polyline(points, &vec![radius; points.len()], &vec![color; points.len()])
+}
+
+/// Like `round_polyline`, but with a radius and color for every point. Both
+/// are interpolated along each segment.
+pub fn variable_round_polyline(points: &[Vec2], radii: &[f32], colors: &[[f32; 4]]) -> Vec<Vertex> {
+    let mut vertices = Vec::new();
+
+    // Repeated points have no direction to build a quad from.
+    let min_length = radii.iter().copied().fold(0.0, f32::max) * 1e-3;
+    let mut path: Vec<usize> = Vec::with_capacity(points.len());
+    for (i, &point) in points.iter().enumerate() {
+        if path.last().is_none_or(|&last| points[last].distance(point) > min_length) {
+            path.push(i);
+        }
+    }
+
+    match path.as_slice() {
+        [] => return vertices,
+        &[i] => {
+            push_arc(&mut vertices, points[i], Vec2::new(radii[i], 0.0), TAU, colors[i]);
+            return vertices;
+        }
+        _ => {}
+    }
+
+    let normal = |from: usize, to: usize| (points[to] - points[from]).normalize().perp();
+
+    for segment in path.windows(2) {
+        let (start, end) = (segment[0], segment[1]);
+        let n = normal(start, end);
+        let (a, b) = (n * radii[start], n * radii[end]);
+        let (p, q) = (points[start], points[end]);
+        let (c, d) = (colors[start], colors[end]);
+        push_shaded_triangle(&mut vertices, [p + a, p - a, q + b], [c, c, d]);
+        push_shaded_triangle(&mut vertices, [p - a, q - b, q + b], [c, d, d]);
+    }
+
+    // Fill the wedge on the outside of each turn.
+    for window in path.windows(3) {
+        let (before, center, after) = (window[0], window[1], window[2]);
+        let incoming = (points[center] - points[before]).normalize();
+        let outgoing = (points[after] - points[center]).normalize();
+        let turn = incoming.perp_dot(outgoing).atan2(incoming.dot(outgoing));
+        if turn.abs() < f32::EPSILON {
+            continue;
+        }
+        let n = normal(before, center) * radii[center];
+        let start = if turn > 0.0 { -n } else { n };
+        push_arc(&mut vertices, points[center], start, turn, colors[center]);
+    }
+
+    // Half circles at both ends, sweeping around the outside.
+    let (first, second) = (path[0], path[1]);
+    push_arc(&mut vertices, points[first], normal(first, second) * radii[first], PI, colors[first]);
+    let (before, last) = (path[path.len() - 2], path[path.len() - 1]);
+    push_arc(&mut vertices, points[last], -normal(before, last) * radii[last], PI, colors[last]);
+
+    vertices
+}
+
+/// Tessellates a polyline `radius` wide on each side with the given caps and
+/// joins. A single point becomes a dot for round and square caps and
+/// disappears with butt caps, which have nothing to extend from. A polyline
+/// that ends at its first point is closed with a join.
+pub fn stroke_polyline(points: &[Vec2], radius: f32, cap: LineCap, join: LineJoin, color: [f32; 4]) -> Vec<Vertex> {
+    let mut vertices = Vec::new();
+
+    // Repeated points have no direction to build a quad from.
+    let mut path: Vec<Vec2> = Vec::with_capacity(points.len());
+    for &point in points {
+        if path.last().is_none_or(|last| last.distance(point) > radius * 1e-3) {
+            path.push(point);
+        }
+    }
+
+    match (path.as_slice(), cap) {
+        ([], _) | ([_], LineCap::Butt) => return vertices,
+        (&[point], LineCap::Round) => {
+            push_arc(&mut vertices, point, Vec2::new(radius, 0.0), TAU, color);
+            return vertices;
+        }
+        (&[point], LineCap::Square) => {
+            let (a, b) = (point - radius, point + radius);
+            push_triangle(&mut vertices, a, Vec2::new(b.x, a.y), b, color);
+            push_triangle(&mut vertices, a, b, Vec2::new(a.x, b.y), color);
+            return vertices;
+        }
+        _ => {}
+    }
+
+    for segment in path.windows(2) {
+        let (p, q) = (segment[0], segment[1]);
+        let n = (q - p).normalize().perp() * radius;
+        push_triangle(&mut vertices, p + n, p - n, q + n, color);
+        push_triangle(&mut vertices, p - n, q - n, q + n, color);
+    }
+
+    for window in path.windows(3) {
+        let incoming = (window[1] - window[0]).normalize();
+        let outgoing = (window[2] - window[1]).normalize();
+        push_join(&mut vertices, window[1], incoming, outgoing, radius, join, color);
+    }
+
+    let last = path.len() - 1;
+    let end_direction = (path[last] - path[last - 1]).normalize();
+    if path.len() > 3 && path[0].distance(path[last]) <= radius * 1e-3 {
+        // A path that ends where it started is joined there, not capped.
+        let start_direction = (path[1] - path[0]).normalize();
+        push_join(&mut vertices, path[0], end_direction, start_direction, radius, join, color);
+    } else {
+        push_cap(&mut vertices, path[0], (path[0] - path[1]).normalize(), radius, cap, color);
+        push_cap(&mut vertices, path[last], end_direction, radius, cap, color);
+    }
+
+    vertices
+}
+
+/// Fills the outside of the turn from `incoming` to `outgoing` (both unit
+/// directions) at `point`.
+fn push_join(
+    vertices: &mut Vec<Vertex>,
+    point: Vec2,
+    incoming: Vec2,
+    outgoing: Vec2,
+    radius: f32,
+    join: LineJoin,
+    color: [f32; 4],
+) {
+    let turn = incoming.perp_dot(outgoing).atan2(incoming.dot(outgoing));
+    if turn.abs() < f32::EPSILON {
+        return;
+    }
+    // The outside of a left turn is on the right.
+    let side = if turn > 0.0 { -radius } else { radius };
+    let (from, to) = (incoming.perp() * side, outgoing.perp() * side);
+    match join {
+        LineJoin::Round => push_arc(vertices, point, from, turn, color),
+        LineJoin::Miter if (turn / 2.0).cos() > 1.0 / MITER_LIMIT => {
+            let tip = point + (from + to).normalize() * radius / (turn / 2.0).cos();
+            push_triangle(vertices, point, point + from, tip, color);
+            push_triangle(vertices, point, tip, point + to, color);
+        }
+        LineJoin::Miter | LineJoin::Bevel => push_triangle(vertices, point, point + from, point + to, color),
+    }
+}
+
+/// Caps the end at `point` of a stroke leaving in unit direction `outward`.
+fn push_cap(vertices: &mut Vec<Vertex>, point: Vec2, outward: Vec2, radius: f32, cap: LineCap, color: [f32; 4]) {
+    let n = outward.perp() * radius;
+    match cap {
+        LineCap::Butt => {}
+        LineCap::Round => push_arc(vertices, point, n, -PI, color),
+        LineCap::Square => {
+            let out = outward * radius;
+            push_triangle(vertices, point + n, point - n, point - n + out, color);
+            push_triangle(vertices, point + n, point - n + out, point + n + out, color);
+        }
+    }
+}
+
+/// Splits a polyline into dashes `dash` long with `gap` between them,
+/// starting with a dash. A non-positive dash or gap keeps it whole.
+pub fn dash_polyline(points: &[Vec2], dash: f32, gap: f32) -> Vec<Vec<Vec2>> {
+    if dash <= 0.0 || gap <= 0.0 || points.len() < 2 {
+        return vec![points.to_vec()];
+    }
+
+    let mut dashes = Vec::new();
+    let mut current = vec![points[0]];
+    let mut drawing = true;
+    // Length left in the current dash or gap.
+    let mut remaining = dash;
+    for segment in points.windows(2) {
+        let (start, end) = (segment[0], segment[1]);
+        let length = start.distance(end);
+        let mut along = 0.0;
+        while length - along > remaining {
+            along += remaining;
+            let point = start.lerp(end, along / length);
+            if drawing {
+                current.push(point);
+                dashes.push(std::mem::take(&mut current));
+                remaining = gap;
+            } else {
+                current.push(point);
+                remaining = dash;
+            }
+            drawing = !drawing;
+        }
+        remaining -= length - along;
+        if drawing {
+            current.push(end);
+        }
+    }
+    if drawing && current.len() > 1 {
+        dashes.push(current);
+    }
+    dashes
+}
+
+/// Cuts `start` and `end` units of length off the two ends of a polyline.
+/// Returns nothing if the cuts overlap.
+pub fn trim_polyline(points: &[Vec2], start: f32, end: f32) -> Vec<Vec2> {
+    let lengths = cumulative_lengths(points);
+    let total = lengths[lengths.len() - 1];
+    let (from, to) = (start.max(0.0), total - end.max(0.0));
+    if points.is_empty() || from > to {
+        return Vec::new();
+    }
+
+    let mut trimmed = vec![point_along(points, &lengths, from)];
+    for (point, &length) in points.iter().zip(&lengths) {
+        if length > from && length < to {
+            trimmed.push(*point);
+        }
+    }
+    trimmed.push(point_along(points, &lengths, to));
+    trimmed
+}
+
+/// The point `distance` along a polyline with the given `cumulative_lengths`.
+fn point_along(points: &[Vec2], lengths: &[f32], distance: f32) -> Vec2 {
+    if points.len() < 2 {
+        return points[0];
+    }
+    let next = lengths.partition_point(|&l| l < distance).clamp(1, lengths.len() - 1);
+    let (a, b) = (lengths[next - 1], lengths[next]);
+    let t = if b > a { ((distance - a) / (b - a)).clamp(0.0, 1.0) } else { 1.0 };
+    points[next - 1].lerp(points[next], t)
+}
+
+/// Appends points along the cubic Bezier curve from `p0` to `p3` with
+/// control points `p1` and `p2` to `out`, leaving out `p0`. The curve is
+/// split in half until each piece is within `FLATTEN_TOLERANCE` of a
+/// straight line, so gentle curves get few points and tight ones many.
+pub fn flatten_cubic(out: &mut Vec<Vec2>, [p0, p1, p2, p3]: [Vec2; 4]) {
+    flatten_cubic_to_depth(out, [p0, p1, p2, p3], 0);
+}
+
+fn flatten_cubic_to_depth(out: &mut Vec<Vec2>, [p0, p1, p2, p3]: [Vec2; 4], depth: u32) {
+    let flat = segment_distance(p0, p3, p1).max(segment_distance(p0, p3, p2)) <= FLATTEN_TOLERANCE;
+    if flat || depth >= MAX_FLATTEN_DEPTH {
+        out.push(p3);
+        return;
+    }
+    // De Casteljau split at t = 0.5.
+    let (a, b, c) = (p0.lerp(p1, 0.5), p1.lerp(p2, 0.5), p2.lerp(p3, 0.5));
+    let (d, e) = (a.lerp(b, 0.5), b.lerp(c, 0.5));
+    let middle = d.lerp(e, 0.5);
+    flatten_cubic_to_depth(out, [p0, a, d, middle], depth + 1);
+    flatten_cubic_to_depth(out, [middle, e, c, p3], depth + 1);
+}
+
+/// Carries per-point `values` from the polyline `from` over to `to`, a
+/// polyline that follows the same course, e.g. after smoothing or
+/// resampling. Points are matched by the fraction of length travelled.
+pub fn transfer_along(from: &[Vec2], values: &[f32], to: &[Vec2]) -> Vec<f32> {
+    let Some(&first) = values.first() else {
+        return vec![0.0; to.len()];
+    };
+    let from_lengths = cumulative_lengths(from);
+    let to_lengths = cumulative_lengths(to);
+    let (from_total, to_total) = (from_lengths[from_lengths.len() - 1], to_lengths[to_lengths.len() - 1]);
+    if from_total <= 0.0 || to_total <= 0.0 {
+        return vec![first; to.len()];
+    }
+
+    to_lengths
+        .iter()
+        .map(|length| {
+            let target = length / to_total * from_total;
+            let next = from_lengths.partition_point(|&l| l < target).clamp(1, from_lengths.len() - 1);
+            let (a, b) = (from_lengths[next - 1], from_lengths[next]);
+            let t = if b > a { (target - a) / (b - a) } else { 1.0 };
+            values[next - 1] + (values[next] - values[next - 1]) * t
+        })
+        .collect()
+}
+
+/// Length travelled along `points` up to each point.
+fn cumulative_lengths(points: &[Vec2]) -> Vec<f32> {
+    let mut total = 0.0;
+    let mut lengths = Vec::with_capacity(points.len().max(1));
+    lengths.push(0.0);
+    for segment in points.windows(2) {
+        total += segment[0].distance(segment[1]);
+        lengths.push(total);
+    }
+    lengths
+}
+
+/// Tessellates a filled polygon, convex or not. See `triangulate`.
+pub fn polygon(points: &[Vec2], color: [f32; 4]) -> Vec<Vertex> {
+    let mut vertices = Vec::new();
+    for [a, b, c] in triangulate(points) {
+        push_triangle(&mut vertices, points[a], points[b], points[c], color);
+    }
+    vertices
+}
+
+/// Splits the polygon through `points` into triangles by ear clipping,
+/// returning indices into `points`. Works with either winding. Outlines
+/// that cross themselves still produce triangles, but they may not cover
+/// the polygon exactly.
+pub fn triangulate(points: &[Vec2]) -> Vec<[usize; 3]> {
+    // Repeated points would make zero-area ears.
+    let mut remaining: Vec<usize> = Vec::with_capacity(points.len());
+    for (i, point) in points.iter().enumerate() {
+        if remaining.last().is_none_or(|&last| points[last] != *point) {
+            remaining.push(i);
+        }
+    }
+    while remaining.len() > 1 && points[remaining[0]] == points[remaining[remaining.len() - 1]] {
+        remaining.pop();
+    }
+    if remaining.len() < 3 {
+        return Vec::new();
+    }
+    // Ears are found assuming counter-clockwise order.
+    let area: f32 = (0..remaining.len())
+        .map(|i| points[remaining[i]].perp_dot(points[remaining[(i + 1) % remaining.len()]]))
+        .sum();
+    if area < 0.0 {
+        remaining.reverse();
+    }
+
+    let mut triangles = Vec::with_capacity(remaining.len() - 2);
+    let mut i = 0;
+    // Vertices checked since the last ear. A full lap without one means the
+    // outline crosses itself, so the current vertex is clipped regardless.
+    let mut checked = 0;
+    while remaining.len() > 3 {
+        let n = remaining.len();
+        let (prev, curr, next) = (remaining[(i + n - 1) % n], remaining[i], remaining[(i + 1) % n]);
+        if checked >= n || is_ear(points, &remaining, [prev, curr, next]) {
+            triangles.push([prev, curr, next]);
+            remaining.remove(i);
+            if i == remaining.len() {
+                i = 0;
+            }
+            checked = 0;
+        } else {
+            i = (i + 1) % n;
+            checked += 1;
+        }
+    }
+    triangles.push([remaining[0], remaining[1], remaining[2]]);
+    triangles
+}
+
+/// Whether the counter-clockwise corner `triangle` can be cut off: it turns
+/// left and no other remaining vertex lies inside it.
+fn is_ear(points: &[Vec2], remaining: &[usize], triangle: [usize; 3]) -> bool {
+    let [a, b, c] = triangle.map(|i| points[i]);
+    if (b - a).perp_dot(c - b) <= 0.0 {
+        return false;
+    }
+    remaining.iter().all(|&i| {
+        let p = points[i];
+        triangle.contains(&i)
+            || p == a
+            || p == b
+            || p == c
+            || (b - a).perp_dot(p - a) < 0.0
+            || (c - b).perp_dot(p - b) < 0.0
+            || (a - c).perp_dot(p - c) < 0.0
+    })
+}
+
+/// Tessellates a filled axis-aligned ellipse as a fan around its center.
+pub fn ellipse(center: Vec2, radii: Vec2, color: [f32; 4]) -> Vec<Vertex> {
+    let outline = ellipse_points(center, radii, arc_segments(radii.max_element(), TAU));
+    let mut vertices = Vec::with_capacity(outline.len() * 3);
+    for i in 0..outline.len() {
+        let next = outline[(i + 1) % outline.len()];
+        push_triangle(&mut vertices, center, outline[i], next, color);
+    }
+    vertices
+}
+
+/// Tessellates the outline of an axis-aligned ellipse, `half_width` to
+/// either side of it. Becomes a filled ellipse when the outline is wider
+/// than the hole in the middle.
+pub fn ellipse_ring(center: Vec2, radii: Vec2, half_width: f32, color: [f32; 4]) -> Vec<Vertex> {
+    let inner_radii = radii - half_width;
+    let outer_radii = radii + half_width;
+    if inner_radii.min_element() <= 0.0 {
+        return ellipse(center, outer_radii, color);
+    }
+
+    let segments = arc_segments(outer_radii.max_element(), TAU);
+    let outer = ellipse_points(center, outer_radii, segments);
+    let inner = ellipse_points(center, inner_radii, segments);
+    ring(&outer, &inner, color)
+}
+
+/// Tessellates a filled axis-aligned rectangle from `min` to `max` with
+/// corners rounded to `radius`. The radius is limited to half the shorter
+/// side.
+pub fn rounded_rect(min: Vec2, max: Vec2, radius: f32, color: [f32; 4]) -> Vec<Vertex> {
+    let radius = radius.clamp(0.0, (max - min).min_element() / 2.0);
+    let segments = if radius > 0.0 { arc_segments(radius, PI / 2.0) } else { 0 };
+    let outline = rounded_rect_points(min, max, radius, segments);
+    let center = (min + max) / 2.0;
+    let mut vertices = Vec::with_capacity(outline.len() * 3);
+    for i in 0..outline.len() {
+        let next = outline[(i + 1) % outline.len()];
+        push_triangle(&mut vertices, center, outline[i], next, color);
+    }
+    vertices
+}
+
+/// Tessellates the outline of a rounded rectangle, `half_width` to either
+/// side of it. Square corners stay square on the outside. Becomes a filled
+/// rectangle when the outline is wider than the hole in the middle.
+pub fn rounded_rect_ring(min: Vec2, max: Vec2, radius: f32, half_width: f32, color: [f32; 4]) -> Vec<Vertex> {
+    let radius = radius.clamp(0.0, (max - min).min_element() / 2.0);
+    let outer_radius = if radius > 0.0 { radius + half_width } else { 0.0 };
+    let (outer_min, outer_max) = (min - half_width, max + half_width);
+    let (inner_min, inner_max) = (min + half_width, max - half_width);
+    if inner_max.cmple(inner_min).any() {
+        return rounded_rect(outer_min, outer_max, outer_radius, color);
+    }
+
+    let segments = if radius > 0.0 { arc_segments(outer_radius, PI / 2.0) } else { 0 };
+    let outer = rounded_rect_points(outer_min, outer_max, outer_radius, segments);
+    let inner = rounded_rect_points(inner_min, inner_max, (radius - half_width).max(0.0), segments);
+    ring(&outer, &inner, color)
+}
+
+/// Points counter-clockwise around a rounded rectangle, `segments + 1` per
+/// corner.
+fn rounded_rect_points(min: Vec2, max: Vec2, radius: f32, segments: usize) -> Vec<Vec2> {
+    let corners = [
+        Vec2::new(max.x - radius, min.y + radius),
+        Vec2::new(max.x - radius, max.y - radius),
+        Vec2::new(min.x + radius, max.y - radius),
+        Vec2::new(min.x + radius, min.y + radius),
+    ];
+    let mut points = Vec::with_capacity(4 * (segments + 1));
+    for (quarter, corner) in corners.into_iter().enumerate() {
+        let start = (quarter as f32 - 1.0) * PI / 2.0;
+        for step in 0..=segments {
+            let angle = start + PI / 2.0 * step as f32 / segments.max(1) as f32;
+            points.push(corner + Vec2::from_angle(angle) * radius);
+        }
+    }
+    points
+}
+
+/// Fills the band between two closed outlines with the same number of points.
+fn ring(outer: &[Vec2], inner: &[Vec2], color: [f32; 4]) -> Vec<Vertex> {
+    let mut vertices = Vec::with_capacity(outer.len() * 6);
+    for i in 0..outer.len() {
+        let next = (i + 1) % outer.len();
+        push_triangle(&mut vertices, inner[i], outer[i], outer[next], color);
+        push_triangle(&mut vertices, inner[i], outer[next], inner[next], color);
+    }
+    vertices
+}
+
+/// `segments` points evenly spaced by angle around an ellipse.
+fn ellipse_points(center: Vec2, radii: Vec2, segments: usize) -> Vec<Vec2> {
+    (0..segments)
+        .map(|i| center + Vec2::from_angle(TAU * i as f32 / segments as f32) * radii)
+        .collect()
+}
+
+/// Shortest distance from `point` to the polyline through `points`.
+pub fn polyline_distance(points: &[Vec2], point: Vec2) -> f32 {
+    match points {
+        [] => f32::INFINITY,
+        [only] => only.distance(point),
+        _ => points
+            .windows(2)
+            .map(|segment| segment_distance(segment[0], segment[1], point))
+            .fold(f32::INFINITY, f32::min),
+    }
+}
+
+fn segment_distance(start: Vec2, end: Vec2, point: Vec2) -> f32 {
+    let segment = end - start;
+    let t = if segment.length_squared() > 0.0 {
+        ((point - start).dot(segment) / segment.length_squared()).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    (start + segment * t).distance(point)
+}
+
+/// Fan of triangles around `center`, starting at offset `start` and turning
+/// by `sweep` radians (counter-clockwise when positive).
+fn push_arc(vertices: &mut Vec<Vertex>, center: Vec2, start: Vec2, sweep: f32, color: [f32; 4]) {
+    let segments = arc_segments(start.length(), sweep);
+    let step = Vec2::from_angle(sweep / segments as f32);
+    let mut offset = start;
+    for _ in 0..segments {
+        let next = step.rotate(offset);
+        push_triangle(vertices, center, center + offset, center + next, color);
+        offset = next;
+    }
+}
+
+/// Number of segments keeping an arc within `ROUND_TOLERANCE` of the circle.
+fn arc_segments(radius: f32, sweep: f32) -> usize {
+    let full_circle = if radius > ROUND_TOLERANCE {
+        (PI / (1.0 - ROUND_TOLERANCE / radius).acos()).ceil()
+    } else {
+        0.0
+    };
+    let full_circle = full_circle.clamp(8.0, 128.0);
+    ((full_circle * sweep.abs() / TAU).ceil() as usize).max(1)
+}
+
+fn push_triangle(vertices: &mut Vec<Vertex>, a: Vec2, b: Vec2, c: Vec2, color: [f32; 4]) {
+    push_shaded_triangle(vertices, [a, b, c], [color; 3]);
+}
+
+/// Pushes a triangle wound counter-clockwise, which the stroke pipeline
+/// treats as front-facing.
+fn push_shaded_triangle(vertices: &mut Vec<Vertex>, positions: [Vec2; 3], colors: [[f32; 4]; 3]) {
+    let [a, b, c] = positions;
+    let order = if (b - a).perp_dot(c - a) < 0.0 { [0, 2, 1] } else { [0, 1, 2] };
+    for i in order {
+        vertices.push(Vertex {
+            position: positions[i].to_array(),
+            color: colors[i],
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn area(a: Vec2, b: Vec2, c: Vec2) -> f32 {
+        (b - a).perp_dot(c - a) / 2.0
+    }
+
+    #[test]
+    fn triangulates_concave_polygons() {
+        // An arrow pointing right, with a reflex vertex at the notch.
+        let points = [
+            Vec2::new(0.0, 0.0),
+            Vec2::new(2.0, 1.0),
+            Vec2::new(0.0, 2.0),
+            Vec2::new(0.5, 1.0),
+        ];
+        let triangles = triangulate(&points);
+        assert_eq!(triangles.len(), 2);
+        let total: f32 = triangles
+            .iter()
+            .map(|&[a, b, c]| area(points[a], points[b], points[c]))
+            .inspect(|&area| assert!(area > 0.0))
+            .sum();
+        assert!((total - 1.5).abs() < 1e-5);
+    }
+
+    #[test]
+    fn triangulates_either_winding() {
+        let square = [Vec2::ZERO, Vec2::X, Vec2::ONE, Vec2::Y];
+        let mut reversed = square;
+        reversed.reverse();
+        for points in [square, reversed] {
+            let total: f32 = triangulate(&points)
+                .iter()
+                .map(|&[a, b, c]| area(points[a], points[b], points[c]))
+                .sum();
+            assert!((total - 1.0).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn skips_degenerate_polygons() {
+        assert!(triangulate(&[Vec2::ZERO, Vec2::X]).is_empty());
+        assert!(triangulate(&[Vec2::ZERO, Vec2::X, Vec2::X, Vec2::ZERO]).is_empty());
+    }
+
+    #[test]
+    fn strokes_zero_length_lines_by_cap() {
+        let point = [Vec2::ONE, Vec2::ONE];
+        assert!(stroke_polyline(&point, 0.1, LineCap::Butt, LineJoin::Miter, [1.0; 4]).is_empty());
+        for cap in [LineCap::Round, LineCap::Square] {
+            let vertices = stroke_polyline(&point, 0.1, cap, LineJoin::Miter, [1.0; 4]);
+            assert!(!vertices.is_empty());
+            assert!(vertices.iter().all(|v| v.position.iter().all(|c| c.is_finite())));
+        }
+    }
+
+    #[test]
+    fn square_caps_extend_past_the_ends() {
+        let line = [Vec2::ZERO, Vec2::X];
+        let extent = |cap| {
+            stroke_polyline(&line, 0.1, cap, LineJoin::Miter, [1.0; 4])
+                .iter()
+                .map(|v| v.position[0])
+                .fold(0.0, f32::max)
+        };
+        assert!((extent(LineCap::Butt) - 1.0).abs() < 1e-6);
+        assert!((extent(LineCap::Square) - 1.1).abs() < 1e-6);
+    }
+
+    #[test]
+    fn dashes_alternate_along_the_path() {
+        let line = [Vec2::ZERO, Vec2::new(0.5, 0.0), Vec2::new(1.0, 0.0)];
+        let dashes = dash_polyline(&line, 0.3, 0.1);
+        let spans: Vec<(f32, f32)> = dashes.iter().map(|d| (d[0].x, d[d.len() - 1].x)).collect();
+        let expected = [(0.0, 0.3), (0.4, 0.7), (0.8, 1.0)];
+        assert_eq!(spans.len(), expected.len());
+        for ((start, end), (expected_start, expected_end)) in spans.into_iter().zip(expected) {
+            assert!((start - expected_start).abs() < 1e-6 && (end - expected_end).abs() < 1e-6);
+        }
+        // The middle dash carries the corner point.
+        assert_eq!(dashes[1].len(), 3);
+    }
+
+    #[test]
+    fn trims_both_ends() {
+        let line = [Vec2::ZERO, Vec2::X, Vec2::new(1.0, 1.0)];
+        let trimmed = trim_polyline(&line, 0.5, 0.25);
+        assert_eq!(trimmed, vec![Vec2::new(0.5, 0.0), Vec2::X, Vec2::new(1.0, 0.75)]);
+        assert!(trim_polyline(&line, 1.5, 1.0).is_empty());
+    }
+}
@@ -1,94 +1,181 @@
-use super::Tool;
-use crate::engine::Vertex;
+use super::pressure::PressureCurve;
+use super::stabilizer::Stabilizer;
+use super::{InputSample, Tool};
+use crate::document::Stroke;
+use crate::engine::{BlendMode, StrokeOp, Vertex};
+use crate::tessellation;
 use glam::Vec2;
 
+/// Default distance between points of a brush path, as a fraction of the
+/// brush diameter.
+pub const DEFAULT_SPACING: f32 = 0.25;
+
+/// A committed freehand brush path.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BrushStroke {
+    /// Cursor samples as they came in.
+    pub points: Vec<Vec2>,
+    /// Pen pressure (0 to 1) at each of `points`. Strokes without one
+    /// pressure per point are drawn at full pressure.
+    pub pressures: Vec<f32>,
+    pub size: f32,
+    /// Distance between points of the resampled path, as a fraction of the
+    /// brush diameter. Smaller is smoother but costs more vertices.
+    pub spacing: f32,
+    pub color: [f32; 4],
+    pub blend_mode: BlendMode,
+    /// Smoothing applied to the samples before they are resampled.
+    pub stabilizer: Stabilizer,
+    /// Scales `size` by pressure.
+    pub width_curve: PressureCurve,
+    /// Scales the color's alpha by pressure.
+    pub opacity_curve: PressureCurve,
+}
+
+impl BrushStroke {
+    pub fn new(size: f32, color: [f32; 4]) -> Self {
+        Self {
+            points: Vec::new(),
+            pressures: Vec::new(),
+            size,
+            spacing: DEFAULT_SPACING,
+            color,
+            blend_mode: BlendMode::Normal,
+            stabilizer: Stabilizer::default(),
+            width_curve: PressureCurve::default(),
+            opacity_curve: PressureCurve::default(),
+        }
+    }
+
+    /// The samples smoothed by the stabilizer and resampled at `spacing`,
+    /// i.e. the path that gets drawn.
+    pub fn path(&self) -> Vec<Vec2> {
+        let smoothed = self.stabilizer.apply(&self.points);
+        tessellation::resample(&smoothed, self.spacing * self.size * 2.0)
+    }
+
+    pub fn push(&mut self, sample: InputSample) {
+        self.points.push(sample.position);
+        self.pressures.push(sample.pressure);
+    }
+
+    fn has_pressure(&self) -> bool {
+        !self.pressures.is_empty() && self.pressures.len() == self.points.len()
+    }
+
+    /// Whether width and opacity are the same along the whole stroke.
+    pub fn is_uniform(&self) -> bool {
+        let constant_pressure = !self.has_pressure() || self.pressures.windows(2).all(|w| w[0] == w[1]);
+        constant_pressure || (self.width_curve.is_constant() && self.opacity_curve.is_constant())
+    }
+
+    pub fn mean_pressure(&self) -> f32 {
+        if !self.has_pressure() {
+            return 1.0;
+        }
+        self.pressures.iter().sum::<f32>() / self.pressures.len() as f32
+    }
+
+    /// Radius and color for a point drawn at `pressure`.
+    pub fn style_at(&self, pressure: f32) -> (f32, [f32; 4]) {
+        let mut color = self.color;
+        color[3] *= self.opacity_curve.evaluate(pressure);
+        (self.size * self.width_curve.evaluate(pressure), color)
+    }
+
+    pub fn tessellate(&self) -> Vec<Vertex> {
+        let path = self.path();
+        let pressures = if self.has_pressure() {
+            tessellation::transfer_along(&self.points, &self.pressures, &path)
+        } else {
+            vec![1.0; path.len()]
+        };
+        let (radii, colors): (Vec<f32>, Vec<[f32; 4]>) =
+            pressures.iter().map(|&pressure| self.style_at(pressure)).unzip();
+        tessellation::variable_round_polyline(&path, &radii, &colors)
+    }
+
+    pub fn hit_test(&self, point: Vec2, tolerance: f32) -> bool {
+        tessellation::polyline_distance(&self.stabilizer.apply(&self.points), point) <= self.size + tolerance
+    }
+}
+
 #[derive(Debug)]
 pub struct Brush {
-    points: Vec<(Vec2, [f32; 4])>,
-    brush_size: f32,
-    color: [f32; 4],
+    stroke: BrushStroke,
 }
 
 impl Brush {
     pub fn new(brush_size: f32, color: [f32; 4]) -> Self {
         Self {
-            points: Vec::new(),
-            brush_size,
-            color,
+            stroke: BrushStroke::new(brush_size, color),
         }
     }
 
     pub fn set_color(&mut self, color: [f32; 4]) {
-        self.color = color;
+        self.stroke.color = color;
     }
 
     pub fn set_size(&mut self, size: f32) {
-        self.brush_size = size;
+        self.stroke.size = size;
+    }
+
+    pub fn set_spacing(&mut self, spacing: f32) {
+        self.stroke.spacing = spacing;
+    }
+
+    pub fn set_stabilizer(&mut self, stabilizer: Stabilizer) {
+        self.stroke.stabilizer = stabilizer;
+    }
+
+    pub fn set_pressure_curves(&mut self, width: PressureCurve, opacity: PressureCurve) {
+        self.stroke.width_curve = width;
+        self.stroke.opacity_curve = opacity;
     }
 }
 
 impl Tool for Brush {
-    fn on_mouse_down(&mut self, position: Vec2) {
-        self.points.push((position, self.color));
+    fn on_mouse_down(&mut self, sample: InputSample) {
+        self.stroke.push(sample);
     }
 
-    fn on_mouse_move(&mut self, position: Vec2) -> Vec<Vertex> {
-        self.points.push((position, self.color));
+    fn on_mouse_move(&mut self, sample: InputSample) -> Vec<Vertex> {
+        self.stroke.push(sample);
         self.get_vertices()
     }
 
-    fn on_mouse_up(&mut self, _position: Vec2) -> Vec<Vertex> {
-        self.get_vertices()
+    fn on_mouse_up(&mut self, _sample: InputSample) -> Option<Stroke> {
+        // Start the next stroke with the same settings and no samples.
+        let points = std::mem::take(&mut self.stroke.points);
+        let pressures = std::mem::take(&mut self.stroke.pressures);
+        let stroke = BrushStroke {
+            points,
+            pressures,
+            ..self.stroke.clone()
+        };
+        if stroke.points.is_empty() {
+            return None;
+        }
+        Some(Stroke::Brush(stroke))
     }
 
     fn get_vertices(&self) -> Vec<Vertex> {
-        let mut vertices = Vec::new();
-        
-        for (pos, color) in &self.points {
-            let size = self.brush_size;
-            
-            let top_left = [pos.x - size, pos.y + size];
-            let top_right = [pos.x + size, pos.y + size];
-            let bottom_left = [pos.x - size, pos.y - size];
-            let bottom_right = [pos.x + size, pos.y - size];
-
-            // First triangle
-            vertices.push(Vertex {
-                position: top_left,
-                color: *color,
-            });
-            vertices.push(Vertex {
-                position: bottom_left,
-                color: *color,
-            });
-            vertices.push(Vertex {
-                position: top_right,
-                color: *color,
-            });
-
-            // Second triangle
-            vertices.push(Vertex {
-                position: bottom_left,
-                color: *color,
-            });
-            vertices.push(Vertex {
-                position: bottom_right,
-                color: *color,
-            });
-            vertices.push(Vertex {
-                position: top_right,
-                color: *color,
-            });
-        }
-
-        vertices
+        self.stroke.tessellate()
     }
 
     fn set_color(&mut self, color: [f32; 4]) {
-        self.color = color;
+        self.stroke.color = color;
     }
 
     fn set_size(&mut self, size: f32) {
-        self.brush_size = size;
+        self.stroke.size = size;
     }
-} 
\ No newline at end of file
+
+    fn stroke_op(&self) -> StrokeOp {
+        StrokeOp::Paint(self.stroke.blend_mode)
+    }
+
+    fn set_blend_mode(&mut self, blend_mode: BlendMode) {
+        self.stroke.blend_mode = blend_mode;
+    }
+}
@@ -0,0 +1,291 @@
+use super::{InputSample, Tool};
+use crate::document::Stroke;
+use crate::engine::{BlendMode, StrokeOp, Vertex};
+use glam::Vec2;
+
+/// A committed flood fill. The region is found on a raster, so it is kept
+/// as the filled pixels merged into rectangles, in canvas coordinates.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FillStroke {
+    /// Minimum and maximum corner of each rectangle.
+    pub rects: Vec<[Vec2; 2]>,
+    pub color: [f32; 4],
+    pub blend_mode: BlendMode,
+}
+
+impl FillStroke {
+    pub fn tessellate(&self) -> Vec<Vertex> {
+        let mut vertices = Vec::with_capacity(self.rects.len() * 6);
+        for [min, max] in &self.rects {
+            let corners = [
+                [min.x, min.y],
+                [max.x, min.y],
+                [max.x, max.y],
+                [min.x, max.y],
+            ];
+            for i in [0, 1, 2, 0, 2, 3] {
+                vertices.push(Vertex {
+                    position: corners[i],
+                    color: self.color,
+                });
+            }
+        }
+        vertices
+    }
+
+    pub fn hit_test(&self, point: Vec2, tolerance: f32) -> bool {
+        self.rects
+            .iter()
+            .any(|[min, max]| point.cmpge(*min - tolerance).all() && point.cmple(*max + tolerance).all())
+    }
+
+    pub fn translate(&mut self, offset: Vec2) {
+        for rect in &mut self.rects {
+            rect[0] += offset;
+            rect[1] += offset;
+        }
+    }
+}
+
+/// Fills the region around a clicked point. The bucket only records the
+/// click; the canvas renders the raster and passes it to `fill`.
+#[derive(Debug)]
+pub struct Bucket {
+    seed: Option<Vec2>,
+    /// Largest difference in any channel, from 0 to 1, between the clicked
+    /// pixel and the pixels the fill spreads to.
+    tolerance: f32,
+    color: [f32; 4],
+    blend_mode: BlendMode,
+}
+
+impl Bucket {
+    pub fn new(color: [f32; 4], tolerance: f32) -> Self {
+        Self {
+            seed: None,
+            tolerance,
+            color,
+            blend_mode: BlendMode::Normal,
+        }
+    }
+
+    pub fn set_tolerance(&mut self, tolerance: f32) {
+        self.tolerance = tolerance;
+    }
+
+    /// The point clicked since the last call, if any.
+    pub fn take_seed(&mut self) -> Option<Vec2> {
+        self.seed.take()
+    }
+
+    /// Fills the region around `seed` in `pixels`, straight-alpha RGBA8
+    /// rows from top to bottom covering `bounds`, the minimum and maximum
+    /// corner of the area the raster shows. Returns `None` if `seed` is off
+    /// the raster.
+    pub fn fill(&self, pixels: &[u8], width: u32, height: u32, bounds: (Vec2, Vec2), seed: Vec2) -> Option<Stroke> {
+        let size = Vec2::new(width as f32, height as f32);
+        let (min, max) = bounds;
+        let extent = max - min;
+        let pixel = (Vec2::new(seed.x - min.x, max.y - seed.y) / extent * size).floor();
+        if pixel.cmplt(Vec2::ZERO).any() || pixel.cmpge(size).any() {
+            return None;
+        }
+
+        let mask = flood_fill(pixels, width as usize, height as usize, pixel.as_uvec2().into(), self.tolerance);
+        let to_canvas = |x: usize, y: usize| {
+            Vec2::new(min.x + x as f32 / size.x * extent.x, max.y - y as f32 / size.y * extent.y)
+        };
+        let rects = mask_rects(&mask, width as usize)
+            .into_iter()
+            .map(|[x0, y0, x1, y1]| [to_canvas(x0, y1), to_canvas(x1, y0)])
+            .collect();
+        Some(Stroke::Fill(FillStroke {
+            rects,
+            color: self.color,
+            blend_mode: self.blend_mode,
+        }))
+    }
+}
+
+impl Tool for Bucket {
+    fn on_mouse_down(&mut self, sample: InputSample) {
+        self.seed = Some(sample.position);
+    }
+
+    fn on_mouse_move(&mut self, _sample: InputSample) -> Vec<Vertex> {
+        Vec::new()
+    }
+
+    fn on_mouse_up(&mut self, _sample: InputSample) -> Option<Stroke> {
+        None
+    }
+
+    fn get_vertices(&self) -> Vec<Vertex> {
+        Vec::new()
+    }
+
+    fn set_color(&mut self, color: [f32; 4]) {
+        self.color = color;
+    }
+
+    fn set_size(&mut self, _size: f32) {
+        // Fills have no size
+    }
+
+    fn stroke_op(&self) -> StrokeOp {
+        StrokeOp::Paint(self.blend_mode)
+    }
+
+    fn set_blend_mode(&mut self, blend_mode: BlendMode) {
+        self.blend_mode = blend_mode;
+    }
+}
+
+/// Marks the pixels connected to `seed` whose color is within `tolerance`
+/// of the seed's. Colors are compared premultiplied, so all fully
+/// transparent pixels match each other.
+fn flood_fill(pixels: &[u8], width: usize, height: usize, [x, y]: [u32; 2], tolerance: f32) -> Vec<bool> {
+    let premultiplied = |i: usize| {
+        let [r, g, b, a] = [0, 1, 2, 3].map(|c| pixels[i * 4 + c] as f32 / 255.0);
+        [r * a, g * a, b * a, a]
+    };
+    let target = premultiplied(y as usize * width + x as usize);
+    let matches = |i: usize| {
+        let color = premultiplied(i);
+        (0..4).all(|c| (color[c] - target[c]).abs() <= tolerance)
+    };
+
+    // Scanline fill: each entry is a pixel whose whole run gets filled.
+    let mut mask = vec![false; width * height];
+    let mut stack = vec![(x as usize, y as usize)];
+    while let Some((x, y)) = stack.pop() {
+        let row = y * width;
+        if mask[row + x] || !matches(row + x) {
+            continue;
+        }
+        let mut start = x;
+        while start > 0 && !mask[row + start - 1] && matches(row + start - 1) {
+            start -= 1;
+        }
+        let mut end = x + 1;
+        while end < width && !mask[row + end] && matches(row + end) {
+            end += 1;
+        }
+        mask[row + start..row + end].fill(true);
+
+        for neighbor in [y.wrapping_sub(1), y + 1] {
+            if neighbor >= height {
+                continue;
+            }
+            // One seed per run of matching pixels along the neighboring row.
+            let mut inside = false;
+            for x in start..end {
+                let i = neighbor * width + x;
+                let fillable = !mask[i] && matches(i);
+                if fillable && !inside {
+                    stack.push((x, neighbor));
+                }
+                inside = fillable;
+            }
+        }
+    }
+    mask
+}
+
+/// Covers the set pixels of `mask` with rectangles `[x0, y0, x1, y1]`,
+/// exclusive of `x1` and `y1`. Runs in each row are merged with identical
+/// runs in the rows below.
+fn mask_rects(mask: &[bool], width: usize) -> Vec<[usize; 4]> {
+    let mut done = Vec::new();
+    // Rectangles that reach the previous row.
+    let mut open: Vec<[usize; 4]> = Vec::new();
+    for (y, row) in mask.chunks(width).enumerate() {
+        let mut runs = Vec::new();
+        let mut x = 0;
+        while x < width {
+            if row[x] {
+                let start = x;
+                while x < width && row[x] {
+                    x += 1;
+                }
+                runs.push((start, x));
+            }
+            x += 1;
+        }
+
+        let mut next = Vec::with_capacity(runs.len());
+        for (x0, x1) in runs {
+            match open.iter().position(|rect| rect[0] == x0 && rect[2] == x1) {
+                Some(i) => {
+                    let mut rect = open.swap_remove(i);
+                    rect[3] = y + 1;
+                    next.push(rect);
+                }
+                None => next.push([x0, y, x1, y + 1]),
+            }
+        }
+        done.append(&mut open);
+        open = next;
+    }
+    done.append(&mut open);
+    done
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A `size` x `size` white raster with a black square outline from
+    /// `min` to `max` inclusive.
+    fn outlined_square(size: usize, min: usize, max: usize) -> Vec<u8> {
+        let mut pixels = vec![255; size * size * 4];
+        for y in min..=max {
+            for x in min..=max {
+                if x == min || x == max || y == min || y == max {
+                    pixels[(y * size + x) * 4..][..3].fill(0);
+                }
+            }
+        }
+        pixels
+    }
+
+    #[test]
+    fn fills_inside_the_outline_only() {
+        let pixels = outlined_square(10, 2, 7);
+        let mask = flood_fill(&pixels, 10, 10, [4, 4], 0.0);
+        assert_eq!(mask.iter().filter(|&&m| m).count(), 16);
+        assert!(mask[3 * 10 + 3] && mask[6 * 10 + 6]);
+        assert!(!mask[2 * 10 + 4] && !mask[0]);
+
+        let outside = flood_fill(&pixels, 10, 10, [0, 0], 0.0);
+        assert_eq!(outside.iter().filter(|&&m| m).count(), 100 - 36);
+    }
+
+    #[test]
+    fn tolerance_lets_the_fill_cross_similar_colors() {
+        let mut pixels = outlined_square(10, 2, 7);
+        // Lighten the outline to a mid gray.
+        for value in pixels.chunks_mut(4) {
+            if value[0] == 0 {
+                value[..3].fill(200);
+            }
+        }
+        let strict = flood_fill(&pixels, 10, 10, [4, 4], 0.1);
+        let loose = flood_fill(&pixels, 10, 10, [4, 4], 0.25);
+        assert_eq!(strict.iter().filter(|&&m| m).count(), 16);
+        assert!(loose.iter().all(|&m| m));
+    }
+
+    #[test]
+    fn merges_runs_into_rectangles() {
+        let pixels = outlined_square(10, 2, 7);
+        let mask = flood_fill(&pixels, 10, 10, [4, 4], 0.0);
+        assert_eq!(mask_rects(&mask, 10), vec![[3, 3, 7, 7]]);
+
+        let outside = flood_fill(&pixels, 10, 10, [0, 0], 0.0);
+        let rects = mask_rects(&outside, 10);
+        let area: usize = rects.iter().map(|[x0, y0, x1, y1]| (x1 - x0) * (y1 - y0)).sum();
+        assert_eq!(area, 100 - 36);
+        assert_eq!(rects.len(), 4);
+    }
+}
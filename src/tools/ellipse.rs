@@ -0,0 +1,196 @@
+use super::{drag_box, InputSample, Modifiers, Tool};
+use crate::document::Stroke;
+use crate::engine::{BlendMode, StrokeOp, Vertex};
+use crate::tessellation;
+use glam::Vec2;
+
+/// A committed axis-aligned ellipse.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EllipseStroke {
+    pub center: Vec2,
+    pub radii: Vec2,
+    /// Filled ellipses ignore `thickness`.
+    pub filled: bool,
+    /// Half the width of the outline.
+    pub thickness: f32,
+    pub color: [f32; 4],
+    pub blend_mode: BlendMode,
+}
+
+impl EllipseStroke {
+    pub fn tessellate(&self) -> Vec<Vertex> {
+        if self.filled {
+            tessellation::ellipse(self.center, self.radii, self.color)
+        } else {
+            tessellation::ellipse_ring(self.center, self.radii, self.thickness, self.color)
+        }
+    }
+
+    pub fn hit_test(&self, point: Vec2, tolerance: f32) -> bool {
+        let offset = point - self.center;
+        let scaled = (offset / self.radii.max(Vec2::splat(f32::EPSILON))).length();
+        // Distance to the outline along the ray from the center, which is
+        // close to the true distance unless the ellipse is very flat. The
+        // center itself has no ray, so it's the shorter radius away.
+        let distance = if scaled > 0.0 {
+            offset.length() * (1.0 - 1.0 / scaled).abs()
+        } else {
+            self.radii.abs().min_element()
+        };
+        if self.filled {
+            scaled <= 1.0 || distance <= tolerance
+        } else {
+            distance <= self.thickness + tolerance
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Ellipse {
+    start: Option<Vec2>,
+    end: Option<Vec2>,
+    modifiers: Modifiers,
+    thickness: f32,
+    filled: bool,
+    color: [f32; 4],
+    blend_mode: BlendMode,
+}
+
+impl Ellipse {
+    pub fn new(thickness: f32, color: [f32; 4], filled: bool) -> Self {
+        Self {
+            start: None,
+            end: None,
+            modifiers: Modifiers::default(),
+            thickness,
+            filled,
+            color,
+            blend_mode: BlendMode::Normal,
+        }
+    }
+
+    pub fn set_filled(&mut self, filled: bool) {
+        self.filled = filled;
+    }
+
+    fn current_stroke(&self) -> Option<EllipseStroke> {
+        let (start, end) = self.start.zip(self.end)?;
+        let (center, radii) = drag_box(start, end, self.modifiers);
+        Some(EllipseStroke {
+            center,
+            radii,
+            filled: self.filled,
+            thickness: self.thickness,
+            color: self.color,
+            blend_mode: self.blend_mode,
+        })
+    }
+}
+
+impl Tool for Ellipse {
+    fn on_mouse_down(&mut self, sample: InputSample) {
+        self.start = Some(sample.position);
+        self.end = Some(sample.position);
+        self.modifiers = sample.modifiers;
+    }
+
+    fn on_mouse_move(&mut self, sample: InputSample) -> Vec<Vertex> {
+        self.end = Some(sample.position);
+        self.modifiers = sample.modifiers;
+        self.get_vertices()
+    }
+
+    fn on_mouse_up(&mut self, sample: InputSample) -> Option<Stroke> {
+        self.end = Some(sample.position);
+        self.modifiers = sample.modifiers;
+        let stroke = self.current_stroke();
+        self.start = None;
+        self.end = None;
+        // A click without a drag has nothing to show.
+        stroke.filter(|s| s.radii.cmpgt(Vec2::ZERO).any()).map(Stroke::Ellipse)
+    }
+
+    fn get_vertices(&self) -> Vec<Vertex> {
+        self.current_stroke()
+            .map(|stroke| stroke.tessellate())
+            .unwrap_or_default()
+    }
+
+    fn set_color(&mut self, color: [f32; 4]) {
+        self.color = color;
+    }
+
+    fn set_size(&mut self, size: f32) {
+        self.thickness = size;
+    }
+
+    fn stroke_op(&self) -> StrokeOp {
+        StrokeOp::Paint(self.blend_mode)
+    }
+
+    fn set_blend_mode(&mut self, blend_mode: BlendMode) {
+        self.blend_mode = blend_mode;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn drag(ellipse: &mut Ellipse, from: Vec2, to: Vec2, shift: bool, alt: bool) -> Option<EllipseStroke> {
+        let modifiers = Modifiers { shift, alt };
+        ellipse.on_mouse_down(InputSample { modifiers, ..InputSample::new(from) });
+        ellipse.on_mouse_move(InputSample { modifiers, ..InputSample::new((from + to) / 2.0) });
+        match ellipse.on_mouse_up(InputSample { modifiers, ..InputSample::new(to) })? {
+            Stroke::Ellipse(stroke) => Some(stroke),
+            other => panic!("expected an ellipse, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn drags_out_the_box_around_the_ellipse() {
+        let mut ellipse = Ellipse::new(0.01, [0.0, 0.0, 0.0, 1.0], true);
+        let stroke = drag(&mut ellipse, Vec2::ZERO, Vec2::new(0.5, -0.25), false, false).unwrap();
+        assert_eq!(stroke.center, Vec2::new(0.25, -0.125));
+        assert_eq!(stroke.radii, Vec2::new(0.25, 0.125));
+
+        // A click without a drag draws nothing.
+        assert_eq!(drag(&mut ellipse, Vec2::ZERO, Vec2::ZERO, false, false), None);
+    }
+
+    #[test]
+    fn shift_draws_circles_and_alt_draws_from_the_center() {
+        let mut ellipse = Ellipse::new(0.01, [0.0, 0.0, 0.0, 1.0], true);
+        let start = Vec2::new(0.25, 0.25);
+        let end = Vec2::new(0.75, 0.5);
+
+        let circle = drag(&mut ellipse, start, end, true, false).unwrap();
+        assert_eq!(circle.center, Vec2::new(0.5, 0.5));
+        assert_eq!(circle.radii, Vec2::splat(0.25));
+
+        let centered = drag(&mut ellipse, start, end, false, true).unwrap();
+        assert_eq!(centered.center, start);
+        assert_eq!(centered.radii, Vec2::new(0.5, 0.25));
+
+        let both = drag(&mut ellipse, start, end, true, true).unwrap();
+        assert_eq!(both.center, start);
+        assert_eq!(both.radii, Vec2::splat(0.5));
+    }
+
+    #[test]
+    fn fills_or_outlines() {
+        let mut ellipse = Ellipse::new(0.01, [0.0, 0.0, 0.0, 1.0], true);
+        let filled = drag(&mut ellipse, Vec2::splat(-0.5), Vec2::splat(0.5), false, false).unwrap();
+        ellipse.set_filled(false);
+        let outline = drag(&mut ellipse, Vec2::splat(-0.5), Vec2::splat(0.5), false, false).unwrap();
+        assert!(filled.filled && !outline.filled);
+        assert_eq!(outline.thickness, 0.01);
+
+        let edge = Vec2::new(0.5, 0.0);
+        assert!(filled.hit_test(Vec2::ZERO, 0.0));
+        assert!(!outline.hit_test(Vec2::ZERO, 0.0));
+        assert!(filled.hit_test(edge, 0.0) && outline.hit_test(edge, 0.0));
+        assert!(!filled.hit_test(Vec2::new(0.6, 0.0), 0.0));
+        assert!(!outline.hit_test(Vec2::new(0.6, 0.0), 0.0));
+    }
+}
@@ -1,14 +1,17 @@
-use super::Tool;
-use crate::engine::Vertex;
+use super::{InputSample, Tool};
+use crate::document::Stroke;
+use crate::engine::{BlendMode, StrokeOp, Vertex};
+use crate::tessellation;
 use glam::Vec2;
 
-#[derive(Debug)]
-pub struct Eraser {
-    points: Vec<Vec2>,
-    size: f32,
+/// A committed eraser path.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EraseStroke {
+    pub points: Vec<Vec2>,
+    pub size: f32,
 }
 
-impl Eraser {
+impl EraseStroke {
     pub fn new(size: f32) -> Self {
         Self {
             points: Vec::new(),
@@ -16,66 +19,75 @@ impl Eraser {
         }
     }
 
+    /// Round polyline covering the erased area. Only its alpha matters,
+    /// see `StrokeOp::Erase`.
+    pub fn tessellate(&self) -> Vec<Vertex> {
+        tessellation::round_polyline(&self.points, self.size, [0.0, 0.0, 0.0, 1.0])
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EraserMode {
+    /// Erases pixels under the eraser, leaving them transparent.
+    #[default]
+    Pixel,
+    /// Deletes whole strokes the eraser touches. The eraser only tracks the
+    /// path; the canvas removes the strokes from the document.
+    Object,
+}
+
+#[derive(Debug)]
+pub struct Eraser {
+    stroke: EraseStroke,
+    mode: EraserMode,
+}
+
+impl Eraser {
+    pub fn new(size: f32, mode: EraserMode) -> Self {
+        Self {
+            stroke: EraseStroke::new(size),
+            mode,
+        }
+    }
+
+    pub fn mode(&self) -> EraserMode {
+        self.mode
+    }
+
+    /// Points the eraser has passed over during the current drag.
+    pub fn path(&self) -> &[Vec2] {
+        &self.stroke.points
+    }
+
     pub fn set_size(&mut self, size: f32) {
-        self.size = size;
+        self.stroke.size = size;
     }
 }
 
 impl Tool for Eraser {
-    fn on_mouse_down(&mut self, position: Vec2) {
-        self.points.push(position);
+    fn on_mouse_down(&mut self, sample: InputSample) {
+        self.stroke.points.push(sample.position);
     }
 
-    fn on_mouse_move(&mut self, position: Vec2) -> Vec<Vertex> {
-        self.points.push(position);
+    fn on_mouse_move(&mut self, sample: InputSample) -> Vec<Vertex> {
+        self.stroke.points.push(sample.position);
         self.get_vertices()
     }
 
-    fn on_mouse_up(&mut self, _position: Vec2) -> Vec<Vertex> {
-        self.get_vertices()
+    fn on_mouse_up(&mut self, _sample: InputSample) -> Option<Stroke> {
+        let fresh = EraseStroke::new(self.stroke.size);
+        let stroke = std::mem::replace(&mut self.stroke, fresh);
+        if stroke.points.is_empty() || self.mode == EraserMode::Object {
+            return None;
+        }
+        Some(Stroke::Erase(stroke))
     }
 
     fn get_vertices(&self) -> Vec<Vertex> {
-        let mut vertices = Vec::new();
-        
-        for pos in &self.points {
-            let size = self.size;
-            
-            let top_left = [pos.x - size, pos.y + size];
-            let top_right = [pos.x + size, pos.y + size];
-            let bottom_left = [pos.x - size, pos.y - size];
-            let bottom_right = [pos.x + size, pos.y - size];
-
-            // First triangle
-            vertices.push(Vertex {
-                position: top_left,
-                color: [1.0, 1.0, 1.0, 1.0], // White color for eraser
-            });
-            vertices.push(Vertex {
-                position: bottom_left,
-                color: [1.0, 1.0, 1.0, 1.0],
-            });
-            vertices.push(Vertex {
-                position: top_right,
-                color: [1.0, 1.0, 1.0, 1.0],
-            });
-
-            // Second triangle
-            vertices.push(Vertex {
-                position: bottom_left,
-                color: [1.0, 1.0, 1.0, 1.0],
-            });
-            vertices.push(Vertex {
-                position: bottom_right,
-                color: [1.0, 1.0, 1.0, 1.0],
-            });
-            vertices.push(Vertex {
-                position: top_right,
-                color: [1.0, 1.0, 1.0, 1.0],
-            });
+        match self.mode {
+            EraserMode::Pixel => self.stroke.tessellate(),
+            EraserMode::Object => Vec::new(),
         }
-
-        vertices
     }
 
     fn set_color(&mut self, _color: [f32; 4]) {
@@ -83,6 +95,14 @@ impl Tool for Eraser {
     }
 
     fn set_size(&mut self, size: f32) {
-        self.size = size;
+        self.stroke.size = size;
+    }
+
+    fn stroke_op(&self) -> StrokeOp {
+        StrokeOp::Erase
     }
-} 
\ No newline at end of file
+
+    fn set_blend_mode(&mut self, _blend_mode: BlendMode) {
+        // Eraser doesn't use blend modes
+    }
+}
@@ -1,22 +1,139 @@
-use super::Tool;
-use crate::engine::Vertex;
+use std::f32::consts::PI;
+
+use super::{snap_angle, InputSample, Modifiers, Tool};
+use crate::document::Stroke;
+use crate::engine::{BlendMode, StrokeOp, Vertex};
+use crate::tessellation::{self, LineCap, LineJoin};
 use glam::Vec2;
 
+/// Angle Shift snaps lines to, in radians (15°).
+const SNAP_ANGLE: f32 = PI / 12.0;
+
+/// Length of an arrowhead, in line widths.
+const ARROW_LENGTH: f32 = 5.0;
+
+/// Half the width of an arrowhead's base, in line widths.
+const ARROW_HALF_WIDTH: f32 = 2.0;
+
+/// How a stroked path is drawn, shared by the tools that draw open paths.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct LineStyle {
+    pub cap: LineCap,
+    pub join: LineJoin,
+    /// Length of each dash in canvas units. Solid unless both this and
+    /// `gap` are positive.
+    pub dash: f32,
+    pub gap: f32,
+    pub start_arrow: bool,
+    pub end_arrow: bool,
+}
+
+impl LineStyle {
+    pub fn is_dashed(&self) -> bool {
+        self.dash > 0.0 && self.gap > 0.0
+    }
+
+    /// How far past its path a line stroked `radius` wide on each side can
+    /// draw. `corners` is whether the path has joins, which can reach
+    /// further than the line's width.
+    pub fn reach(&self, radius: f32, corners: bool) -> f32 {
+        let cap = if self.cap == LineCap::Square { std::f32::consts::SQRT_2 } else { 1.0 };
+        let join = if corners && self.join == LineJoin::Miter { tessellation::MITER_LIMIT } else { 1.0 };
+        let arrow = if self.start_arrow || self.end_arrow { ARROW_HALF_WIDTH * 2.0 } else { 1.0 };
+        radius * cap.max(join).max(arrow)
+    }
+
+    /// Splits the path through `points`, stroked `radius` wide on each side,
+    /// into the part drawn as a line and the arrowhead triangles. The line
+    /// stops at the base of each arrowhead so its caps don't poke out past
+    /// the tip. Arrowheads shrink to fit short paths.
+    pub fn layout(&self, points: &[Vec2], radius: f32) -> (Vec<Vec2>, Vec<[Vec2; 3]>) {
+        let arrows = self.start_arrow as u8 + self.end_arrow as u8;
+        let total: f32 = points.windows(2).map(|w| w[0].distance(w[1])).sum();
+        if arrows == 0 || total <= 0.0 {
+            return (points.to_vec(), Vec::new());
+        }
+
+        let length = (ARROW_LENGTH * radius * 2.0).min(total / arrows as f32);
+        let trim = |enabled: bool| if enabled { length } else { 0.0 };
+        let path = tessellation::trim_polyline(points, trim(self.start_arrow), trim(self.end_arrow));
+        let half_width = ARROW_HALF_WIDTH * radius * 2.0;
+        let arrow = |tip: Vec2, base: Vec2| {
+            let n = (tip - base).normalize_or_zero().perp() * half_width;
+            [tip, base + n, base - n]
+        };
+
+        let mut heads = Vec::new();
+        if self.start_arrow {
+            heads.push(arrow(points[0], path[0]));
+        }
+        if self.end_arrow {
+            heads.push(arrow(points[points.len() - 1], path[path.len() - 1]));
+        }
+        (path, heads)
+    }
+
+    pub fn tessellate(&self, points: &[Vec2], radius: f32, color: [f32; 4]) -> Vec<Vertex> {
+        let (path, arrows) = self.layout(points, radius);
+        let mut vertices = Vec::new();
+        for dash in tessellation::dash_polyline(&path, self.dash, self.gap) {
+            vertices.extend(tessellation::stroke_polyline(&dash, radius, self.cap, self.join, color));
+        }
+        for arrow in arrows {
+            vertices.extend(tessellation::polygon(&arrow, color));
+        }
+        vertices
+    }
+}
+
+/// A committed straight line segment.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LineStroke {
+    pub start: Vec2,
+    pub end: Vec2,
+    pub thickness: f32,
+    pub style: LineStyle,
+    pub color: [f32; 4],
+    pub blend_mode: BlendMode,
+}
+
+impl LineStroke {
+    pub fn tessellate(&self) -> Vec<Vertex> {
+        self.style.tessellate(&[self.start, self.end], self.thickness, self.color)
+    }
+
+    pub fn hit_test(&self, point: Vec2, tolerance: f32) -> bool {
+        let segment = self.end - self.start;
+        let t = if segment.length_squared() > 0.0 {
+            ((point - self.start).dot(segment) / segment.length_squared()).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        (self.start + segment * t).distance(point) <= self.thickness + tolerance
+    }
+}
+
 #[derive(Debug)]
 pub struct Line {
     start: Option<Vec2>,
     end: Option<Vec2>,
+    modifiers: Modifiers,
     color: [f32; 4],
     thickness: f32,
+    style: LineStyle,
+    blend_mode: BlendMode,
 }
 
 impl Line {
-    pub fn new(thickness: f32, color: [f32; 4]) -> Self {
+    pub fn new(thickness: f32, color: [f32; 4], style: LineStyle) -> Self {
         Self {
             start: None,
             end: None,
+            modifiers: Modifiers::default(),
             color,
             thickness,
+            style,
+            blend_mode: BlendMode::Normal,
         }
     }
 
@@ -28,72 +145,51 @@ impl Line {
         self.thickness = size;
     }
 
-    fn generate_line_vertices(&self, start: Vec2, end: Vec2) -> Vec<Vertex> {
-        let mut vertices = Vec::new();
-        let direction = (end - start).normalize();
-        let perpendicular = Vec2::new(-direction.y, direction.x) * self.thickness;
-
-        let v1 = start + perpendicular;
-        let v2 = start - perpendicular;
-        let v3 = end + perpendicular;
-        let v4 = end - perpendicular;
-
-        // First triangle
-        vertices.push(Vertex {
-            position: [v1.x, v1.y],
-            color: self.color,
-        });
-        vertices.push(Vertex {
-            position: [v2.x, v2.y],
-            color: self.color,
-        });
-        vertices.push(Vertex {
-            position: [v3.x, v3.y],
-            color: self.color,
-        });
+    pub fn set_style(&mut self, style: LineStyle) {
+        self.style = style;
+    }
 
-        // Second triangle
-        vertices.push(Vertex {
-            position: [v2.x, v2.y],
-            color: self.color,
-        });
-        vertices.push(Vertex {
-            position: [v4.x, v4.y],
-            color: self.color,
-        });
-        vertices.push(Vertex {
-            position: [v3.x, v3.y],
+    fn current_stroke(&self) -> Option<LineStroke> {
+        let (start, end) = self.start.zip(self.end)?;
+        let end = if self.modifiers.shift { snap_angle(start, end, SNAP_ANGLE) } else { end };
+        Some(LineStroke {
+            start,
+            end,
+            thickness: self.thickness,
+            style: self.style,
             color: self.color,
-        });
-
-        vertices
+            blend_mode: self.blend_mode,
+        })
     }
 }
 
 impl Tool for Line {
-    fn on_mouse_down(&mut self, position: Vec2) {
-        self.start = Some(position);
-        self.end = Some(position);
+    fn on_mouse_down(&mut self, sample: InputSample) {
+        self.start = Some(sample.position);
+        self.end = Some(sample.position);
+        self.modifiers = sample.modifiers;
     }
 
-    fn on_mouse_move(&mut self, position: Vec2) -> Vec<Vertex> {
-        self.end = Some(position);
+    fn on_mouse_move(&mut self, sample: InputSample) -> Vec<Vertex> {
+        self.end = Some(sample.position);
+        self.modifiers = sample.modifiers;
         self.get_vertices()
     }
 
-    fn on_mouse_up(&mut self, position: Vec2) -> Vec<Vertex> {
-        self.end = Some(position);
-        let vertices = self.get_vertices();
+    fn on_mouse_up(&mut self, sample: InputSample) -> Option<Stroke> {
+        self.end = Some(sample.position);
+        self.modifiers = sample.modifiers;
+        let stroke = self.current_stroke();
         self.start = None;
         self.end = None;
-        vertices
+        // A click without a drag has no direction to draw in.
+        stroke.filter(|s| s.start != s.end).map(Stroke::Line)
     }
 
     fn get_vertices(&self) -> Vec<Vertex> {
-        match (self.start, self.end) {
-            (Some(start), Some(end)) => self.generate_line_vertices(start, end),
-            _ => Vec::new(),
-        }
+        self.current_stroke()
+            .map(|stroke| stroke.tessellate())
+            .unwrap_or_default()
     }
 
     fn set_color(&mut self, color: [f32; 4]) {
@@ -103,4 +199,12 @@ impl Tool for Line {
     fn set_size(&mut self, size: f32) {
         self.thickness = size;
     }
-} 
\ No newline at end of file
+
+    fn stroke_op(&self) -> StrokeOp {
+        StrokeOp::Paint(self.blend_mode)
+    }
+
+    fn set_blend_mode(&mut self, blend_mode: BlendMode) {
+        self.blend_mode = blend_mode;
+    }
+}
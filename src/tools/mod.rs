@@ -1,24 +1,147 @@
 use glam::Vec2;
-use crate::engine::Vertex;
+use crate::document::Stroke;
+use crate::engine::{BlendMode, StrokeOp, Vertex};
+
+/// Keyboard modifiers held during a sample.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub alt: bool,
+}
+
+/// One pointer sample from a mouse, pen or touch screen.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InputSample {
+    pub position: Vec2,
+    /// From 0 to 1. Devices that don't report pressure get a simulated one.
+    pub pressure: f32,
+    /// Angle between the pen and the surface in radians, if reported.
+    pub tilt: Option<f32>,
+    /// Seconds since an arbitrary start.
+    pub time: f64,
+    pub modifiers: Modifiers,
+}
+
+impl InputSample {
+    /// A sample at full pressure with no tilt or timing.
+    pub fn new(position: Vec2) -> Self {
+        Self {
+            position,
+            pressure: 1.0,
+            tilt: None,
+            time: 0.0,
+            modifiers: Modifiers::default(),
+        }
+    }
+}
+
+/// Keys the tools respond to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ToolKey {
+    Enter,
+    Backspace,
+    Escape,
+}
+
+/// Rotates `end` around `start` to the nearest multiple of `step` radians.
+pub fn snap_angle(start: Vec2, end: Vec2, step: f32) -> Vec2 {
+    let offset = end - start;
+    let angle = (offset.y.atan2(offset.x) / step).round() * step;
+    start + Vec2::from_angle(angle) * offset.length()
+}
+
+/// Center and half size of the box dragged from `start` to `end`. Shift
+/// makes it square and Alt grows it around `start` instead of from a corner.
+pub fn drag_box(start: Vec2, end: Vec2, modifiers: Modifiers) -> (Vec2, Vec2) {
+    let mut delta = end - start;
+    if modifiers.shift {
+        let side = delta.abs().max_element();
+        delta = Vec2::new(side.copysign(delta.x), side.copysign(delta.y));
+    }
+    if modifiers.alt {
+        (start, delta.abs())
+    } else {
+        (start + delta / 2.0, (delta / 2.0).abs())
+    }
+}
 
 pub trait Tool {
-    fn on_mouse_down(&mut self, position: Vec2);
-    fn on_mouse_move(&mut self, position: Vec2) -> Vec<Vertex>;
-    fn on_mouse_up(&mut self, position: Vec2) -> Vec<Vertex>;
+    fn on_mouse_down(&mut self, sample: InputSample);
+    fn on_mouse_move(&mut self, sample: InputSample) -> Vec<Vertex>;
+    /// Finishes the current interaction, returning the stroke to commit.
+    fn on_mouse_up(&mut self, sample: InputSample) -> Option<Stroke>;
     fn get_vertices(&self) -> Vec<Vertex>;
     fn set_color(&mut self, color: [f32; 4]);
     fn set_size(&mut self, size: f32);
+    /// How the tool's strokes are applied, for previews.
+    fn stroke_op(&self) -> StrokeOp;
+    fn set_blend_mode(&mut self, blend_mode: BlendMode);
+
+    /// A press and release in about the same place. Comes after
+    /// `on_mouse_up`. Returns a stroke if the click finishes one.
+    fn on_click(&mut self, _sample: InputSample) -> Option<Stroke> {
+        None
+    }
+
+    /// The second of two quick clicks in about the same place. Comes after
+    /// `on_click` for that click.
+    fn on_double_click(&mut self, _sample: InputSample) -> Option<Stroke> {
+        None
+    }
+
+    fn on_key(&mut self, _key: ToolKey) -> Option<Stroke> {
+        None
+    }
+
+    /// Whether the tool holds an unfinished shape between presses. The
+    /// canvas keeps forwarding cursor moves and showing the preview then.
+    fn in_progress(&self) -> bool {
+        false
+    }
 }
 
 pub mod brush;
+pub mod bucket;
+pub mod ellipse;
 pub mod eraser;
 pub mod line;
+pub mod pen;
+pub mod polygon;
+pub mod pressure;
 pub mod rectangle;
+pub mod select;
+pub mod stabilizer;
 
 use brush::Brush;
+use bucket::Bucket;
+use ellipse::Ellipse;
 use eraser::Eraser;
 use line::Line;
+use pen::Pen;
+use polygon::Polygon;
 use rectangle::Rectangle;
+use select::Select;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ToolType {
+    Brush,
+    Eraser,
+    Line,
+    Rectangle,
+    Ellipse,
+    Polygon,
+    Pen,
+    Bucket,
+    Select,
+}
+
+impl ToolType {
+    /// Tools that draw a shape between the press and the cursor, whose
+    /// preview changes with Shift and Alt.
+    pub fn is_shape(self) -> bool {
+        matches!(self, ToolType::Line | ToolType::Rectangle | ToolType::Ellipse)
+    }
+}
 
 #[derive(Debug)]
 pub enum ActiveTool {
@@ -26,33 +149,53 @@ pub enum ActiveTool {
     Eraser(Eraser),
     Line(Line),
     Rectangle(Rectangle),
+    Ellipse(Ellipse),
+    Polygon(Polygon),
+    Pen(Pen),
+    Bucket(Bucket),
+    Select(Select),
 }
 
 impl Tool for ActiveTool {
-    fn on_mouse_down(&mut self, position: Vec2) {
+    fn on_mouse_down(&mut self, sample: InputSample) {
         match self {
-            ActiveTool::Brush(t) => t.on_mouse_down(position),
-            ActiveTool::Eraser(t) => t.on_mouse_down(position),
-            ActiveTool::Line(t) => t.on_mouse_down(position),
-            ActiveTool::Rectangle(t) => t.on_mouse_down(position),
+            ActiveTool::Brush(t) => t.on_mouse_down(sample),
+            ActiveTool::Eraser(t) => t.on_mouse_down(sample),
+            ActiveTool::Line(t) => t.on_mouse_down(sample),
+            ActiveTool::Rectangle(t) => t.on_mouse_down(sample),
+            ActiveTool::Ellipse(t) => t.on_mouse_down(sample),
+            ActiveTool::Polygon(t) => t.on_mouse_down(sample),
+            ActiveTool::Pen(t) => t.on_mouse_down(sample),
+            ActiveTool::Bucket(t) => t.on_mouse_down(sample),
+            ActiveTool::Select(t) => t.on_mouse_down(sample),
         }
     }
 
-    fn on_mouse_move(&mut self, position: Vec2) -> Vec<Vertex> {
+    fn on_mouse_move(&mut self, sample: InputSample) -> Vec<Vertex> {
         match self {
-            ActiveTool::Brush(t) => t.on_mouse_move(position),
-            ActiveTool::Eraser(t) => t.on_mouse_move(position),
-            ActiveTool::Line(t) => t.on_mouse_move(position),
-            ActiveTool::Rectangle(t) => t.on_mouse_move(position),
+            ActiveTool::Brush(t) => t.on_mouse_move(sample),
+            ActiveTool::Eraser(t) => t.on_mouse_move(sample),
+            ActiveTool::Line(t) => t.on_mouse_move(sample),
+            ActiveTool::Rectangle(t) => t.on_mouse_move(sample),
+            ActiveTool::Ellipse(t) => t.on_mouse_move(sample),
+            ActiveTool::Polygon(t) => t.on_mouse_move(sample),
+            ActiveTool::Pen(t) => t.on_mouse_move(sample),
+            ActiveTool::Bucket(t) => t.on_mouse_move(sample),
+            ActiveTool::Select(t) => t.on_mouse_move(sample),
         }
     }
 
-    fn on_mouse_up(&mut self, position: Vec2) -> Vec<Vertex> {
+    fn on_mouse_up(&mut self, sample: InputSample) -> Option<Stroke> {
         match self {
-            ActiveTool::Brush(t) => t.on_mouse_up(position),
-            ActiveTool::Eraser(t) => t.on_mouse_up(position),
-            ActiveTool::Line(t) => t.on_mouse_up(position),
-            ActiveTool::Rectangle(t) => t.on_mouse_up(position),
+            ActiveTool::Brush(t) => t.on_mouse_up(sample),
+            ActiveTool::Eraser(t) => t.on_mouse_up(sample),
+            ActiveTool::Line(t) => t.on_mouse_up(sample),
+            ActiveTool::Rectangle(t) => t.on_mouse_up(sample),
+            ActiveTool::Ellipse(t) => t.on_mouse_up(sample),
+            ActiveTool::Polygon(t) => t.on_mouse_up(sample),
+            ActiveTool::Pen(t) => t.on_mouse_up(sample),
+            ActiveTool::Bucket(t) => t.on_mouse_up(sample),
+            ActiveTool::Select(t) => t.on_mouse_up(sample),
         }
     }
 
@@ -62,6 +205,11 @@ impl Tool for ActiveTool {
             ActiveTool::Eraser(t) => t.get_vertices(),
             ActiveTool::Line(t) => t.get_vertices(),
             ActiveTool::Rectangle(t) => t.get_vertices(),
+            ActiveTool::Ellipse(t) => t.get_vertices(),
+            ActiveTool::Polygon(t) => t.get_vertices(),
+            ActiveTool::Pen(t) => t.get_vertices(),
+            ActiveTool::Bucket(t) => t.get_vertices(),
+            ActiveTool::Select(t) => t.get_vertices(),
         }
     }
 
@@ -70,6 +218,10 @@ impl Tool for ActiveTool {
             ActiveTool::Brush(t) => t.set_color(color),
             ActiveTool::Line(t) => t.set_color(color),
             ActiveTool::Rectangle(t) => t.set_color(color),
+            ActiveTool::Ellipse(t) => t.set_color(color),
+            ActiveTool::Polygon(t) => t.set_color(color),
+            ActiveTool::Pen(t) => t.set_color(color),
+            ActiveTool::Bucket(t) => t.set_color(color),
             _ => {},
         }
     }
@@ -79,7 +231,72 @@ impl Tool for ActiveTool {
             ActiveTool::Brush(t) => t.set_size(size),
             ActiveTool::Eraser(t) => t.set_size(size),
             ActiveTool::Line(t) => t.set_size(size),
+            ActiveTool::Rectangle(t) => t.set_size(size),
+            ActiveTool::Ellipse(t) => t.set_size(size),
+            ActiveTool::Polygon(t) => t.set_size(size),
+            ActiveTool::Pen(t) => t.set_size(size),
+            _ => {},
+        }
+    }
+
+    fn stroke_op(&self) -> StrokeOp {
+        match self {
+            ActiveTool::Brush(t) => t.stroke_op(),
+            ActiveTool::Eraser(t) => t.stroke_op(),
+            ActiveTool::Line(t) => t.stroke_op(),
+            ActiveTool::Rectangle(t) => t.stroke_op(),
+            ActiveTool::Ellipse(t) => t.stroke_op(),
+            ActiveTool::Polygon(t) => t.stroke_op(),
+            ActiveTool::Pen(t) => t.stroke_op(),
+            ActiveTool::Bucket(t) => t.stroke_op(),
+            ActiveTool::Select(t) => t.stroke_op(),
+        }
+    }
+
+    fn set_blend_mode(&mut self, blend_mode: BlendMode) {
+        match self {
+            ActiveTool::Brush(t) => t.set_blend_mode(blend_mode),
+            ActiveTool::Line(t) => t.set_blend_mode(blend_mode),
+            ActiveTool::Rectangle(t) => t.set_blend_mode(blend_mode),
+            ActiveTool::Ellipse(t) => t.set_blend_mode(blend_mode),
+            ActiveTool::Polygon(t) => t.set_blend_mode(blend_mode),
+            ActiveTool::Pen(t) => t.set_blend_mode(blend_mode),
+            ActiveTool::Bucket(t) => t.set_blend_mode(blend_mode),
             _ => {},
         }
     }
+
+    fn on_click(&mut self, sample: InputSample) -> Option<Stroke> {
+        match self {
+            ActiveTool::Polygon(t) => t.on_click(sample),
+            ActiveTool::Pen(t) => t.on_click(sample),
+            _ => None,
+        }
+    }
+
+    fn on_double_click(&mut self, sample: InputSample) -> Option<Stroke> {
+        match self {
+            ActiveTool::Polygon(t) => t.on_double_click(sample),
+            ActiveTool::Pen(t) => t.on_double_click(sample),
+            _ => None,
+        }
+    }
+
+    fn on_key(&mut self, key: ToolKey) -> Option<Stroke> {
+        match self {
+            ActiveTool::Polygon(t) => t.on_key(key),
+            ActiveTool::Pen(t) => t.on_key(key),
+            ActiveTool::Select(t) => t.on_key(key),
+            _ => None,
+        }
+    }
+
+    fn in_progress(&self) -> bool {
+        match self {
+            ActiveTool::Polygon(t) => t.in_progress(),
+            ActiveTool::Pen(t) => t.in_progress(),
+            ActiveTool::Select(t) => t.in_progress(),
+            _ => false,
+        }
+    }
 }
\ No newline at end of file
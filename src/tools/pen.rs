@@ -0,0 +1,498 @@
+use super::line::LineStyle;
+use super::{InputSample, Modifiers, Tool, ToolKey};
+use crate::document::Stroke;
+use crate::engine::{BlendMode, StrokeOp, Vertex};
+use crate::tessellation::{self, LineCap, LineJoin};
+use glam::Vec2;
+
+/// Distance, in canvas units, within which a press grabs an anchor or handle.
+const GRAB_DISTANCE: f32 = 0.02;
+
+/// Half the size of the square marking an anchor in the preview.
+const ANCHOR_MARKER_SIZE: f32 = 0.008;
+
+/// Radius of the dot marking a handle in the preview.
+const HANDLE_MARKER_RADIUS: f32 = 0.006;
+
+/// Half the width of the line from an anchor to its handles.
+const HANDLE_LINE_WIDTH: f32 = 0.0015;
+
+/// Color of anchors and handles in the preview.
+const MARKER_COLOR: [f32; 4] = [0.1, 0.45, 0.95, 1.0];
+
+/// A point on a Bezier path with its two control handles, in canvas
+/// coordinates. A handle on top of its anchor gives a sharp corner.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Anchor {
+    pub point: Vec2,
+    /// Shapes the curve arriving at `point`.
+    pub handle_in: Vec2,
+    /// Shapes the curve leaving `point`.
+    pub handle_out: Vec2,
+}
+
+impl Anchor {
+    pub fn corner(point: Vec2) -> Self {
+        Self {
+            point,
+            handle_in: point,
+            handle_out: point,
+        }
+    }
+
+    fn translate(&mut self, offset: Vec2) {
+        self.point += offset;
+        self.handle_in += offset;
+        self.handle_out += offset;
+    }
+}
+
+/// A committed path of cubic Bezier curves through `anchors`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BezierStroke {
+    pub anchors: Vec<Anchor>,
+    /// Closed paths curve from the last anchor back to the first.
+    pub closed: bool,
+    /// Half the width of the line.
+    pub thickness: f32,
+    /// Arrowheads are left off closed paths, which have no ends.
+    pub style: LineStyle,
+    pub color: [f32; 4],
+    pub blend_mode: BlendMode,
+}
+
+impl BezierStroke {
+    /// The path as a polyline, ending back at the first anchor when closed.
+    pub fn flatten(&self) -> Vec<Vec2> {
+        flatten(&self.anchors, self.closed)
+    }
+
+    pub fn tessellate(&self) -> Vec<Vertex> {
+        let style = if self.closed {
+            LineStyle {
+                start_arrow: false,
+                end_arrow: false,
+                ..self.style
+            }
+        } else {
+            self.style
+        };
+        style.tessellate(&self.flatten(), self.thickness, self.color)
+    }
+
+    pub fn hit_test(&self, point: Vec2, tolerance: f32) -> bool {
+        tessellation::polyline_distance(&self.flatten(), point) <= self.thickness + tolerance
+    }
+
+    pub fn translate(&mut self, offset: Vec2) {
+        self.anchors.iter_mut().for_each(|anchor| anchor.translate(offset));
+    }
+}
+
+fn flatten(anchors: &[Anchor], closed: bool) -> Vec<Vec2> {
+    let Some(first) = anchors.first() else {
+        return Vec::new();
+    };
+    let mut points = vec![first.point];
+    for pair in anchors.windows(2) {
+        tessellation::flatten_cubic(&mut points, curve(&pair[0], &pair[1]));
+    }
+    if closed && anchors.len() > 1 {
+        tessellation::flatten_cubic(&mut points, curve(&anchors[anchors.len() - 1], first));
+    }
+    points
+}
+
+/// Control points of the curve from anchor `a` to anchor `b`.
+fn curve(a: &Anchor, b: &Anchor) -> [Vec2; 4] {
+    [a.point, a.handle_out, b.handle_in, b.point]
+}
+
+/// What the current press is moving.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Drag {
+    /// An anchor just placed; dragging pulls out symmetric handles.
+    NewAnchor(usize),
+    Anchor(usize),
+    HandleIn(usize),
+    HandleOut(usize),
+    /// Nothing; the press missed the path while editing and ends the edit.
+    Finish,
+}
+
+/// Builds Bezier paths. Clicking places a corner, dragging pulls out curve
+/// handles. Existing anchors and handles can be dragged while the path is
+/// open for editing; Alt breaks a handle's symmetry with its partner.
+/// Clicking the first anchor closes the path, double-click or Enter leaves
+/// it open, Backspace removes the last anchor and Escape drops the path.
+#[derive(Debug)]
+pub struct Pen {
+    anchors: Vec<Anchor>,
+    /// Set when editing a committed path that was already closed.
+    closed: bool,
+    /// Style of the committed path being edited, which the edit keeps.
+    editing: Option<BezierStroke>,
+    drag: Option<Drag>,
+    last_position: Vec2,
+    cursor: Option<Vec2>,
+    modifiers: Modifiers,
+    thickness: f32,
+    style: LineStyle,
+    color: [f32; 4],
+    blend_mode: BlendMode,
+}
+
+impl Pen {
+    pub fn new(thickness: f32, color: [f32; 4], style: LineStyle) -> Self {
+        Self {
+            anchors: Vec::new(),
+            closed: false,
+            editing: None,
+            drag: None,
+            last_position: Vec2::ZERO,
+            cursor: None,
+            modifiers: Modifiers::default(),
+            thickness,
+            style,
+            color,
+            blend_mode: BlendMode::Normal,
+        }
+    }
+
+    pub fn set_style(&mut self, style: LineStyle) {
+        self.style = style;
+    }
+
+    /// Opens a committed path for editing. Finishing returns the edited
+    /// path with the original's style.
+    pub fn edit(&mut self, stroke: BezierStroke) {
+        self.anchors = stroke.anchors.clone();
+        self.closed = stroke.closed;
+        self.editing = Some(stroke);
+        self.drag = None;
+        self.cursor = None;
+    }
+
+    fn stroke(&self, anchors: Vec<Anchor>, closed: bool) -> BezierStroke {
+        match &self.editing {
+            Some(original) => BezierStroke {
+                anchors,
+                closed,
+                ..original.clone()
+            },
+            None => BezierStroke {
+                anchors,
+                closed,
+                thickness: self.thickness,
+                style: self.style,
+                color: self.color,
+                blend_mode: self.blend_mode,
+            },
+        }
+    }
+
+    /// Ends the path, returning it if it has at least one curve.
+    fn finish(&mut self, close: bool) -> Option<Stroke> {
+        let anchors = std::mem::take(&mut self.anchors);
+        let closed = close || self.closed;
+        let stroke = (anchors.len() >= 2).then(|| Stroke::Bezier(self.stroke(anchors, closed)));
+        self.closed = false;
+        self.editing = None;
+        self.drag = None;
+        stroke
+    }
+
+    /// The anchor or handle under `position`, preferring handles, which
+    /// sit on top of their anchors for corners.
+    fn grab(&self, position: Vec2) -> Option<Drag> {
+        let near = |point: Vec2| point.distance(position) <= GRAB_DISTANCE.max(self.thickness);
+        let handles = self.anchors.iter().enumerate().find_map(|(i, anchor)| {
+            if anchor.handle_out != anchor.point && near(anchor.handle_out) {
+                Some(Drag::HandleOut(i))
+            } else if anchor.handle_in != anchor.point && near(anchor.handle_in) {
+                Some(Drag::HandleIn(i))
+            } else {
+                None
+            }
+        });
+        handles.or_else(|| self.anchors.iter().position(|a| near(a.point)).map(Drag::Anchor))
+    }
+
+    /// Anchors and handles, drawn over the path while it's being built.
+    fn markers(&self) -> Vec<Vertex> {
+        let mut vertices = Vec::new();
+        for anchor in &self.anchors {
+            for handle in [anchor.handle_in, anchor.handle_out] {
+                if handle != anchor.point {
+                    vertices.extend(tessellation::stroke_polyline(
+                        &[anchor.point, handle],
+                        HANDLE_LINE_WIDTH,
+                        LineCap::Butt,
+                        LineJoin::Miter,
+                        MARKER_COLOR,
+                    ));
+                    vertices.extend(tessellation::ellipse(handle, Vec2::splat(HANDLE_MARKER_RADIUS), MARKER_COLOR));
+                }
+            }
+            let size = Vec2::splat(ANCHOR_MARKER_SIZE);
+            vertices.extend(tessellation::rounded_rect(anchor.point - size, anchor.point + size, 0.0, MARKER_COLOR));
+        }
+        vertices
+    }
+}
+
+impl Tool for Pen {
+    fn on_mouse_down(&mut self, sample: InputSample) {
+        self.modifiers = sample.modifiers;
+        self.last_position = sample.position;
+        self.drag = Some(match self.grab(sample.position) {
+            Some(drag) => drag,
+            None if self.editing.is_some() => Drag::Finish,
+            None => {
+                self.anchors.push(Anchor::corner(sample.position));
+                Drag::NewAnchor(self.anchors.len() - 1)
+            }
+        });
+    }
+
+    fn on_mouse_move(&mut self, sample: InputSample) -> Vec<Vertex> {
+        self.modifiers = sample.modifiers;
+        self.cursor = Some(sample.position);
+        let position = sample.position;
+        let offset = position - self.last_position;
+        self.last_position = position;
+        // Alt moves one handle on its own, for a sharp turn.
+        let mirror = !self.modifiers.alt;
+
+        match self.drag {
+            Some(Drag::NewAnchor(i)) => {
+                let anchor = &mut self.anchors[i];
+                anchor.handle_out = position;
+                anchor.handle_in = anchor.point * 2.0 - position;
+            }
+            Some(Drag::Anchor(i)) => self.anchors[i].translate(offset),
+            Some(Drag::HandleOut(i)) => {
+                let anchor = &mut self.anchors[i];
+                anchor.handle_out = position;
+                if mirror && anchor.handle_in != anchor.point {
+                    anchor.handle_in = anchor.point * 2.0 - position;
+                }
+            }
+            Some(Drag::HandleIn(i)) => {
+                let anchor = &mut self.anchors[i];
+                anchor.handle_in = position;
+                if mirror && anchor.handle_out != anchor.point {
+                    anchor.handle_out = anchor.point * 2.0 - position;
+                }
+            }
+            Some(Drag::Finish) | None => {}
+        }
+        self.get_vertices()
+    }
+
+    fn on_mouse_up(&mut self, sample: InputSample) -> Option<Stroke> {
+        self.on_mouse_move(sample);
+        match self.drag.take() {
+            Some(Drag::Finish) => self.finish(false),
+            _ => None,
+        }
+    }
+
+    fn get_vertices(&self) -> Vec<Vertex> {
+        let mut anchors = self.anchors.clone();
+        // Show where the next curve would go while hovering.
+        if let (None, false, Some(cursor)) = (self.drag, self.editing.is_some(), self.cursor) {
+            if !anchors.is_empty() {
+                anchors.push(Anchor::corner(cursor));
+            }
+        }
+        let mut vertices = self.stroke(anchors, self.closed).tessellate();
+        vertices.extend(self.markers());
+        vertices
+    }
+
+    fn set_color(&mut self, color: [f32; 4]) {
+        self.color = color;
+    }
+
+    fn set_size(&mut self, size: f32) {
+        self.thickness = size;
+    }
+
+    fn stroke_op(&self) -> StrokeOp {
+        let blend_mode = self.editing.as_ref().map_or(self.blend_mode, |s| s.blend_mode);
+        StrokeOp::Paint(blend_mode)
+    }
+
+    fn set_blend_mode(&mut self, blend_mode: BlendMode) {
+        self.blend_mode = blend_mode;
+    }
+
+    fn on_click(&mut self, sample: InputSample) -> Option<Stroke> {
+        let closes = !self.closed && self.anchors.len() >= 2 && self.grab(sample.position) == Some(Drag::Anchor(0));
+        closes.then(|| self.finish(true)).flatten()
+    }
+
+    fn on_double_click(&mut self, _sample: InputSample) -> Option<Stroke> {
+        // The second press landed on the anchor the first one placed, so
+        // there's nothing to remove.
+        self.finish(false)
+    }
+
+    fn on_key(&mut self, key: ToolKey) -> Option<Stroke> {
+        match key {
+            ToolKey::Enter => self.finish(false),
+            ToolKey::Backspace => {
+                if self.editing.is_none() {
+                    self.anchors.pop();
+                }
+                None
+            }
+            ToolKey::Escape => {
+                self.anchors.clear();
+                self.closed = false;
+                self.editing = None;
+                self.drag = None;
+                None
+            }
+        }
+    }
+
+    fn in_progress(&self) -> bool {
+        !self.anchors.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COLOR: [f32; 4] = [0.0, 0.0, 0.0, 1.0];
+
+    fn sample(x: f32, y: f32) -> InputSample {
+        InputSample::new(Vec2::new(x, y))
+    }
+
+    /// A press and release in place, as the canvas passes on a click.
+    fn click(pen: &mut Pen, x: f32, y: f32) -> Option<Stroke> {
+        pen.on_mouse_down(sample(x, y));
+        assert_eq!(pen.on_mouse_up(sample(x, y)), None);
+        pen.on_click(sample(x, y))
+    }
+
+    fn drag(pen: &mut Pen, from: InputSample, to: InputSample) -> Option<Stroke> {
+        pen.on_mouse_down(from);
+        pen.on_mouse_move(to);
+        pen.on_mouse_up(to)
+    }
+
+    fn bezier(stroke: Option<Stroke>) -> BezierStroke {
+        match stroke {
+            Some(Stroke::Bezier(stroke)) => stroke,
+            other => panic!("expected a Bezier path, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn clicks_place_corners_and_drags_pull_out_handles() {
+        let mut pen = Pen::new(0.01, COLOR, LineStyle::default());
+        assert_eq!(click(&mut pen, 0.0, 0.0), None);
+        assert_eq!(drag(&mut pen, sample(0.5, 0.0), sample(0.75, 0.25)), None);
+        assert!(pen.in_progress());
+
+        let path = bezier(pen.on_key(ToolKey::Enter));
+        assert!(!path.closed);
+        assert_eq!(path.thickness, 0.01);
+        assert_eq!(path.anchors[0], Anchor::corner(Vec2::ZERO));
+        assert_eq!(
+            path.anchors[1],
+            Anchor {
+                point: Vec2::new(0.5, 0.0),
+                handle_in: Vec2::new(0.25, -0.25),
+                handle_out: Vec2::new(0.75, 0.25),
+            }
+        );
+        assert!(!pen.in_progress());
+    }
+
+    #[test]
+    fn clicking_the_first_anchor_closes_the_path() {
+        let mut pen = Pen::new(0.01, COLOR, LineStyle::default());
+        click(&mut pen, 0.0, 0.0);
+        // One anchor isn't a path to close.
+        assert_eq!(click(&mut pen, 0.005, 0.0), None);
+        click(&mut pen, 0.5, 0.0);
+        click(&mut pen, 0.5, 0.5);
+        let path = bezier(click(&mut pen, 0.005, 0.0));
+        assert!(path.closed);
+        assert_eq!(path.anchors.len(), 3);
+        assert!(!pen.in_progress());
+    }
+
+    #[test]
+    fn double_click_and_keys_end_the_path() {
+        let mut pen = Pen::new(0.01, COLOR, LineStyle::default());
+        click(&mut pen, 0.0, 0.0);
+        click(&mut pen, 0.5, 0.0);
+        click(&mut pen, 0.5, 0.5);
+        let path = bezier(pen.on_double_click(sample(0.5, 0.5)));
+        assert!(!path.closed);
+        assert_eq!(path.anchors.len(), 3);
+
+        click(&mut pen, 0.0, 0.0);
+        click(&mut pen, 0.5, 0.0);
+        pen.on_key(ToolKey::Backspace);
+        // A single anchor makes no path.
+        assert_eq!(pen.on_key(ToolKey::Enter), None);
+        assert!(!pen.in_progress());
+
+        click(&mut pen, 0.0, 0.0);
+        click(&mut pen, 0.5, 0.0);
+        assert_eq!(pen.on_key(ToolKey::Escape), None);
+        assert!(!pen.in_progress());
+    }
+
+    #[test]
+    fn edits_anchors_and_handles_of_a_committed_path() {
+        let original = BezierStroke {
+            anchors: vec![
+                Anchor::corner(Vec2::ZERO),
+                Anchor {
+                    point: Vec2::new(0.5, 0.0),
+                    handle_in: Vec2::new(0.4, 0.0),
+                    handle_out: Vec2::new(0.6, 0.0),
+                },
+            ],
+            closed: false,
+            thickness: 0.03,
+            style: LineStyle::default(),
+            color: [1.0, 0.0, 0.0, 1.0],
+            blend_mode: BlendMode::Multiply,
+        };
+        let mut pen = Pen::new(0.01, COLOR, LineStyle::default());
+        pen.edit(original.clone());
+
+        // Moving an anchor takes its handles along.
+        drag(&mut pen, sample(0.0, 0.0), sample(0.0, 0.1));
+        // Moving a handle mirrors its partner, unless Alt is held.
+        drag(&mut pen, sample(0.6, 0.0), sample(0.6, 0.1));
+        let mut alt = sample(0.4, -0.1);
+        alt.modifiers.alt = true;
+        let to = InputSample { position: Vec2::new(0.4, 0.0), ..alt };
+        drag(&mut pen, alt, to);
+
+        // Pressing off the path ends the edit with the original's style.
+        let path = bezier(drag(&mut pen, sample(2.0, 2.0), sample(2.0, 2.0)));
+        assert_eq!(path.anchors[0], Anchor::corner(Vec2::new(0.0, 0.1)));
+        assert_eq!(
+            path.anchors[1],
+            Anchor {
+                point: Vec2::new(0.5, 0.0),
+                handle_in: Vec2::new(0.4, 0.0),
+                handle_out: Vec2::new(0.6, 0.1),
+            }
+        );
+        assert_eq!(BezierStroke { anchors: original.anchors.clone(), ..path }, original);
+        assert!(!pen.in_progress());
+    }
+}
@@ -0,0 +1,282 @@
+use super::{InputSample, Tool, ToolKey};
+use crate::document::Stroke;
+use crate::engine::{BlendMode, StrokeOp, Vertex};
+use crate::tessellation;
+use glam::Vec2;
+
+/// Smallest distance, in canvas units, at which a click counts as landing
+/// on an existing vertex.
+const SNAP_DISTANCE: f32 = 0.015;
+
+/// A committed polygon or polyline through `points`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PolygonStroke {
+    pub points: Vec<Vec2>,
+    /// Closed strokes join the last point back to the first.
+    pub closed: bool,
+    /// Only closed strokes can be filled. Filled strokes ignore `thickness`.
+    pub filled: bool,
+    /// Half the width of the outline.
+    pub thickness: f32,
+    pub color: [f32; 4],
+    pub blend_mode: BlendMode,
+}
+
+impl PolygonStroke {
+    pub fn is_filled(&self) -> bool {
+        self.closed && self.filled
+    }
+
+    /// The points in drawing order, back to the first one when closed.
+    pub fn outline(&self) -> Vec<Vec2> {
+        let mut outline = self.points.clone();
+        if self.closed && outline.len() > 2 {
+            outline.push(outline[0]);
+        }
+        outline
+    }
+
+    pub fn tessellate(&self) -> Vec<Vertex> {
+        if self.is_filled() {
+            tessellation::polygon(&self.points, self.color)
+        } else {
+            tessellation::round_polyline(&self.outline(), self.thickness, self.color)
+        }
+    }
+
+    pub fn hit_test(&self, point: Vec2, tolerance: f32) -> bool {
+        let distance = tessellation::polyline_distance(&self.outline(), point);
+        if self.is_filled() {
+            distance <= tolerance || contains(&self.points, point)
+        } else {
+            distance <= self.thickness + tolerance
+        }
+    }
+}
+
+/// Even-odd test for `point` inside the polygon through `points`.
+fn contains(points: &[Vec2], point: Vec2) -> bool {
+    let mut inside = false;
+    for (i, &a) in points.iter().enumerate() {
+        let b = points[(i + 1) % points.len()];
+        if (a.y > point.y) != (b.y > point.y) {
+            let x = a.x + (point.y - a.y) / (b.y - a.y) * (b.x - a.x);
+            if point.x < x {
+                inside = !inside;
+            }
+        }
+    }
+    inside
+}
+
+/// Builds a polygon one click per vertex. Double-click or Enter finishes
+/// it, clicking the first vertex closes it, Backspace removes the last
+/// vertex and Escape drops the whole shape.
+#[derive(Debug)]
+pub struct Polygon {
+    points: Vec<Vec2>,
+    cursor: Option<Vec2>,
+    closed: bool,
+    filled: bool,
+    thickness: f32,
+    color: [f32; 4],
+    blend_mode: BlendMode,
+}
+
+impl Polygon {
+    pub fn new(thickness: f32, color: [f32; 4], closed: bool, filled: bool) -> Self {
+        Self {
+            points: Vec::new(),
+            cursor: None,
+            closed,
+            filled,
+            thickness,
+            color,
+            blend_mode: BlendMode::Normal,
+        }
+    }
+
+    pub fn set_closed(&mut self, closed: bool) {
+        self.closed = closed;
+    }
+
+    pub fn set_filled(&mut self, filled: bool) {
+        self.filled = filled;
+    }
+
+    fn stroke(&self, points: Vec<Vec2>, closed: bool) -> PolygonStroke {
+        PolygonStroke {
+            points,
+            closed,
+            filled: self.filled,
+            thickness: self.thickness,
+            color: self.color,
+            blend_mode: self.blend_mode,
+        }
+    }
+
+    /// Ends the shape, returning it if it has enough vertices.
+    fn finish(&mut self, closed: bool) -> Option<Stroke> {
+        let points = std::mem::take(&mut self.points);
+        let needed = if closed { 3 } else { 2 };
+        (points.len() >= needed).then(|| Stroke::Polygon(self.stroke(points, closed)))
+    }
+}
+
+impl Tool for Polygon {
+    fn on_mouse_down(&mut self, sample: InputSample) {
+        self.cursor = Some(sample.position);
+    }
+
+    fn on_mouse_move(&mut self, sample: InputSample) -> Vec<Vertex> {
+        self.cursor = Some(sample.position);
+        self.get_vertices()
+    }
+
+    fn on_mouse_up(&mut self, sample: InputSample) -> Option<Stroke> {
+        self.cursor = Some(sample.position);
+        None
+    }
+
+    fn get_vertices(&self) -> Vec<Vertex> {
+        if self.points.is_empty() {
+            return Vec::new();
+        }
+        let mut points = self.points.clone();
+        points.extend(self.cursor);
+        self.stroke(points, self.closed).tessellate()
+    }
+
+    fn set_color(&mut self, color: [f32; 4]) {
+        self.color = color;
+    }
+
+    fn set_size(&mut self, size: f32) {
+        self.thickness = size;
+    }
+
+    fn stroke_op(&self) -> StrokeOp {
+        StrokeOp::Paint(self.blend_mode)
+    }
+
+    fn set_blend_mode(&mut self, blend_mode: BlendMode) {
+        self.blend_mode = blend_mode;
+    }
+
+    fn on_click(&mut self, sample: InputSample) -> Option<Stroke> {
+        let snap = SNAP_DISTANCE.max(self.thickness);
+        if self.points.len() >= 3 && self.points[0].distance(sample.position) <= snap {
+            return self.finish(true);
+        }
+        self.points.push(sample.position);
+        None
+    }
+
+    fn on_double_click(&mut self, sample: InputSample) -> Option<Stroke> {
+        // The second click added a vertex on top of the first one's.
+        let snap = SNAP_DISTANCE.max(self.thickness);
+        let len = self.points.len();
+        if len >= 2 && self.points[len - 2].distance(sample.position) <= snap {
+            self.points.pop();
+        }
+        self.finish(self.closed)
+    }
+
+    fn on_key(&mut self, key: ToolKey) -> Option<Stroke> {
+        match key {
+            ToolKey::Enter => self.finish(self.closed),
+            ToolKey::Backspace => {
+                self.points.pop();
+                None
+            }
+            ToolKey::Escape => {
+                self.points.clear();
+                None
+            }
+        }
+    }
+
+    fn in_progress(&self) -> bool {
+        !self.points.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(x: f32, y: f32) -> InputSample {
+        InputSample::new(Vec2::new(x, y))
+    }
+
+    /// A tool with three open vertices in an L shape.
+    fn l_shape(closed: bool) -> Polygon {
+        let mut polygon = Polygon::new(0.01, [0.0, 0.0, 0.0, 1.0], closed, true);
+        for (x, y) in [(0.0, 0.0), (0.5, 0.0), (0.5, 0.5)] {
+            assert_eq!(polygon.on_click(sample(x, y)), None);
+        }
+        polygon
+    }
+
+    fn points(stroke: Option<Stroke>) -> (Vec<Vec2>, bool) {
+        match stroke {
+            Some(Stroke::Polygon(stroke)) => (stroke.points, stroke.closed),
+            other => panic!("expected a polygon, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn clicking_the_first_vertex_closes_the_polygon() {
+        let mut polygon = l_shape(false);
+        let (points, closed) = points(polygon.on_click(sample(0.005, 0.0)));
+        assert!(closed);
+        assert_eq!(points, vec![Vec2::ZERO, Vec2::new(0.5, 0.0), Vec2::new(0.5, 0.5)]);
+        assert!(!polygon.in_progress());
+    }
+
+    #[test]
+    fn double_click_drops_the_duplicated_vertex() {
+        let mut polygon = l_shape(true);
+        // The first click of the pair added a vertex, the second finishes.
+        polygon.on_click(sample(0.0, 0.5));
+        let (points, closed) = points(polygon.on_double_click(sample(0.0, 0.5)));
+        assert!(closed);
+        assert_eq!(points.len(), 4);
+        assert_eq!(points[3], Vec2::new(0.0, 0.5));
+    }
+
+    #[test]
+    fn keys_edit_and_end_the_polygon() {
+        let mut polygon = l_shape(false);
+        assert_eq!(polygon.on_key(ToolKey::Backspace), None);
+        let (points, closed) = points(polygon.on_key(ToolKey::Enter));
+        assert!(!closed);
+        assert_eq!(points, vec![Vec2::ZERO, Vec2::new(0.5, 0.0)]);
+
+        let mut polygon = l_shape(false);
+        assert_eq!(polygon.on_key(ToolKey::Escape), None);
+        assert!(!polygon.in_progress());
+        assert_eq!(polygon.on_key(ToolKey::Enter), None);
+    }
+
+    #[test]
+    fn needs_enough_vertices() {
+        // Two vertices make a line but not a closed shape.
+        let mut polygon = l_shape(true);
+        polygon.on_key(ToolKey::Backspace);
+        assert_eq!(polygon.on_key(ToolKey::Enter), None);
+        assert!(!polygon.in_progress());
+
+        let mut polygon = l_shape(false);
+        polygon.on_key(ToolKey::Backspace);
+        polygon.on_key(ToolKey::Backspace);
+        assert_eq!(polygon.on_key(ToolKey::Enter), None);
+
+        // Clicking the first vertex of a two point shape adds a vertex.
+        let mut polygon = Polygon::new(0.01, [0.0, 0.0, 0.0, 1.0], true, false);
+        polygon.on_click(sample(0.0, 0.0));
+        polygon.on_click(sample(0.5, 0.0));
+        assert_eq!(polygon.on_click(sample(0.0, 0.0)), None);
+        assert!(polygon.in_progress());
+    }
+}
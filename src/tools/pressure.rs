@@ -0,0 +1,205 @@
+use glam::Vec2;
+
+/// Cursor speed, in canvas units per second, at which simulated pressure
+/// bottoms out.
+const LIGHTEST_SPEED: f32 = 4.0;
+
+/// Lowest simulated pressure, reached at `LIGHTEST_SPEED` and above.
+const MIN_SIMULATED_PRESSURE: f32 = 0.2;
+
+/// Time constant, in seconds, for easing simulated pressure towards its
+/// target so it doesn't flicker with uneven event timing.
+const SIMULATED_SMOOTHING: f32 = 0.05;
+
+/// Maps pen pressure (0 to 1) to a factor (0 to 1) through a piecewise
+/// linear curve. Control points are sorted by pressure and the first and
+/// last are pinned to pressure 0 and 1.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PressureCurve {
+    points: Vec<Vec2>,
+}
+
+impl Default for PressureCurve {
+    /// A flat curve, so pressure has no effect.
+    fn default() -> Self {
+        Self::flat(1.0)
+    }
+}
+
+impl PressureCurve {
+    pub fn flat(value: f32) -> Self {
+        Self::linear(value, value)
+    }
+
+    pub fn linear(from: f32, to: f32) -> Self {
+        Self {
+            points: vec![Vec2::new(0.0, from.clamp(0.0, 1.0)), Vec2::new(1.0, to.clamp(0.0, 1.0))],
+        }
+    }
+
+    /// Builds a curve from control points, or `None` unless there are at
+    /// least two, sorted by pressure, within 0 to 1 and spanning pressure 0
+    /// to 1.
+    pub fn from_points(points: Vec<Vec2>) -> Option<Self> {
+        let in_range = |p: &Vec2| (0.0..=1.0).contains(&p.x) && (0.0..=1.0).contains(&p.y);
+        let valid = points.len() >= 2
+            && points.iter().all(in_range)
+            && points.windows(2).all(|w| w[0].x <= w[1].x)
+            && points[0].x == 0.0
+            && points[points.len() - 1].x == 1.0;
+        valid.then_some(Self { points })
+    }
+
+    pub fn points(&self) -> &[Vec2] {
+        &self.points
+    }
+
+    pub fn evaluate(&self, pressure: f32) -> f32 {
+        let pressure = pressure.clamp(0.0, 1.0);
+        let next = self.points.partition_point(|p| p.x < pressure).clamp(1, self.points.len() - 1);
+        let (a, b) = (self.points[next - 1], self.points[next]);
+        if b.x <= a.x {
+            return b.y;
+        }
+        a.y + (b.y - a.y) * (pressure - a.x) / (b.x - a.x)
+    }
+
+    pub fn is_constant(&self) -> bool {
+        self.points.iter().all(|p| p.y == self.points[0].y)
+    }
+
+    /// Adds a control point, returning its index.
+    pub fn insert(&mut self, point: Vec2) -> usize {
+        let point = point.clamp(Vec2::ZERO, Vec2::ONE);
+        let index = self.points.partition_point(|p| p.x <= point.x).clamp(1, self.points.len() - 1);
+        self.points.insert(index, point);
+        index
+    }
+
+    /// Removes a control point. The end points stay.
+    pub fn remove(&mut self, index: usize) {
+        if index > 0 && index < self.points.len() - 1 {
+            self.points.remove(index);
+        }
+    }
+
+    /// Moves a control point, keeping it between its neighbours. The end
+    /// points only move up and down.
+    pub fn move_point(&mut self, index: usize, to: Vec2) {
+        let last = self.points.len() - 1;
+        let x = match index {
+            0 => 0.0,
+            i if i == last => 1.0,
+            i => to.x.clamp(self.points[i - 1].x, self.points[i + 1].x),
+        };
+        self.points[index] = Vec2::new(x, to.y.clamp(0.0, 1.0));
+    }
+}
+
+/// Simulates pen pressure for devices that don't report it: fast movement
+/// gives light pressure, like a pen skimming over paper.
+#[derive(Debug, Clone, Default)]
+pub struct VelocityPressure {
+    last: Option<(Vec2, f64)>,
+    pressure: f32,
+}
+
+impl VelocityPressure {
+    /// Starts a new stroke at full pressure.
+    pub fn reset(&mut self) {
+        self.last = None;
+    }
+
+    /// Pressure for the cursor at `position` at `time` seconds.
+    pub fn sample(&mut self, position: Vec2, time: f64) -> f32 {
+        match self.last {
+            None => self.pressure = 1.0,
+            Some((last_position, last_time)) => {
+                let elapsed = (time - last_time) as f32;
+                if elapsed > 0.0 {
+                    let speed = last_position.distance(position) / elapsed;
+                    let target = (1.0 - speed / LIGHTEST_SPEED).max(MIN_SIMULATED_PRESSURE);
+                    let ease = 1.0 - (-elapsed / SIMULATED_SMOOTHING).exp();
+                    self.pressure += (target - self.pressure) * ease;
+                }
+            }
+        }
+        self.last = Some((position, time));
+        self.pressure
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn curve() -> PressureCurve {
+        PressureCurve::from_points(vec![Vec2::new(0.0, 0.2), Vec2::new(0.5, 1.0), Vec2::new(1.0, 0.6)]).unwrap()
+    }
+
+    #[test]
+    fn evaluates_between_control_points() {
+        let curve = curve();
+        assert_eq!(curve.evaluate(0.0), 0.2);
+        assert_eq!(curve.evaluate(0.5), 1.0);
+        assert_eq!(curve.evaluate(1.0), 0.6);
+        assert!((curve.evaluate(0.25) - 0.6).abs() < 1e-6);
+        assert!((curve.evaluate(0.75) - 0.8).abs() < 1e-6);
+        assert_eq!(PressureCurve::linear(0.0, 1.0).evaluate(0.3), 0.3);
+        assert!(PressureCurve::default().is_constant());
+        assert!(!curve.is_constant());
+    }
+
+    #[test]
+    fn clamps_pressure_and_points() {
+        let curve = curve();
+        assert_eq!(curve.evaluate(-1.0), 0.2);
+        assert_eq!(curve.evaluate(2.0), 0.6);
+        assert_eq!(PressureCurve::linear(-1.0, 2.0).points(), &[Vec2::new(0.0, 0.0), Vec2::new(1.0, 1.0)]);
+
+        let mut curve = curve;
+        assert_eq!(curve.insert(Vec2::new(0.75, 1.5)), 2);
+        assert_eq!(curve.points()[2], Vec2::new(0.75, 1.0));
+        curve.move_point(2, Vec2::new(0.1, -1.0));
+        assert_eq!(curve.points()[2], Vec2::new(0.5, 0.0));
+        curve.move_point(0, Vec2::new(0.5, 0.5));
+        assert_eq!(curve.points()[0], Vec2::new(0.0, 0.5));
+        curve.remove(0);
+        curve.remove(3);
+        assert_eq!(curve.points().len(), 4);
+    }
+
+    #[test]
+    fn rejects_invalid_points() {
+        assert!(PressureCurve::from_points(vec![Vec2::ZERO]).is_none());
+        assert!(PressureCurve::from_points(vec![Vec2::new(0.0, 0.0), Vec2::new(0.9, 1.0)]).is_none());
+        assert!(PressureCurve::from_points(vec![Vec2::new(0.0, 0.0), Vec2::new(1.0, 1.5)]).is_none());
+        let unsorted = vec![Vec2::new(0.0, 0.0), Vec2::new(0.6, 0.5), Vec2::new(0.4, 0.5), Vec2::new(1.0, 1.0)];
+        assert!(PressureCurve::from_points(unsorted).is_none());
+    }
+
+    #[test]
+    fn simulated_pressure_falls_with_speed() {
+        let mut slow = VelocityPressure::default();
+        let mut fast = VelocityPressure::default();
+        assert_eq!(slow.sample(Vec2::ZERO, 0.0), 1.0);
+        assert_eq!(fast.sample(Vec2::ZERO, 0.0), 1.0);
+        let (mut slow_pressure, mut fast_pressure) = (1.0, 1.0);
+        for i in 1..=20 {
+            let time = i as f64 * 0.02;
+            slow_pressure = slow.sample(Vec2::new(0.2 * time as f32, 0.0), time);
+            fast_pressure = fast.sample(Vec2::new(10.0 * time as f32, 0.0), time);
+        }
+        assert!(slow_pressure > 0.9);
+        assert!((fast_pressure - MIN_SIMULATED_PRESSURE).abs() < 0.01);
+
+        // Standing still eases back towards full pressure, not at once.
+        let eased = fast.sample(Vec2::new(4.0, 0.0), 0.41);
+        assert!(eased > fast_pressure && eased < 1.0);
+        // Samples without time passing leave the pressure as it was.
+        assert_eq!(fast.sample(Vec2::new(5.0, 0.0), 0.41), eased);
+
+        fast.reset();
+        assert_eq!(fast.sample(Vec2::new(9.0, 0.0), 1.0), 1.0);
+    }
+}
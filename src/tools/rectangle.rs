@@ -1,93 +1,305 @@
-use super::Tool;
-use crate::engine::Vertex;
+use super::{drag_box, InputSample, Modifiers, Tool};
+use crate::document::Stroke;
+use crate::engine::{BlendMode, StrokeOp, Vertex};
+use crate::tessellation;
 use glam::Vec2;
 
+/// Which parts of a rectangle are painted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RectangleStyle {
+    #[default]
+    Fill,
+    Outline,
+    /// The fill stops where the outline starts, so translucent colors don't
+    /// mix.
+    FillAndOutline,
+}
+
+impl RectangleStyle {
+    pub const ALL: [RectangleStyle; 3] = [
+        RectangleStyle::Fill,
+        RectangleStyle::Outline,
+        RectangleStyle::FillAndOutline,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            RectangleStyle::Fill => "Fill",
+            RectangleStyle::Outline => "Outline",
+            RectangleStyle::FillAndOutline => "Both",
+        }
+    }
+
+    pub fn from_index(index: u32) -> Option<Self> {
+        Self::ALL.get(index as usize).copied()
+    }
+
+    pub fn has_fill(self) -> bool {
+        self != RectangleStyle::Outline
+    }
+
+    pub fn has_outline(self) -> bool {
+        self != RectangleStyle::Fill
+    }
+}
+
+/// A committed axis-aligned rectangle, stored by two opposite corners.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RectangleStroke {
+    pub start: Vec2,
+    pub end: Vec2,
+    pub style: RectangleStyle,
+    /// Half the width of the outline.
+    pub thickness: f32,
+    pub corner_radius: f32,
+    /// Color of the outline.
+    pub color: [f32; 4],
+    pub fill_color: [f32; 4],
+    pub blend_mode: BlendMode,
+}
+
+impl RectangleStroke {
+    pub fn min(&self) -> Vec2 {
+        self.start.min(self.end)
+    }
+
+    pub fn max(&self) -> Vec2 {
+        self.start.max(self.end)
+    }
+
+    /// The corner radius, limited to half the shorter side.
+    pub fn radius(&self) -> f32 {
+        self.corner_radius.clamp(0.0, (self.max() - self.min()).min_element() / 2.0)
+    }
+
+    pub fn tessellate(&self) -> Vec<Vertex> {
+        let (min, max, radius) = (self.min(), self.max(), self.radius());
+        match self.style {
+            RectangleStyle::Fill => tessellation::rounded_rect(min, max, radius, self.fill_color),
+            RectangleStyle::Outline => {
+                tessellation::rounded_rect_ring(min, max, radius, self.thickness, self.color)
+            }
+            RectangleStyle::FillAndOutline => {
+                let (inner_min, inner_max) = (min + self.thickness, max - self.thickness);
+                let mut vertices = if inner_max.cmpgt(inner_min).all() {
+                    let inner_radius = (radius - self.thickness).max(0.0);
+                    tessellation::rounded_rect(inner_min, inner_max, inner_radius, self.fill_color)
+                } else {
+                    Vec::new()
+                };
+                vertices.extend(tessellation::rounded_rect_ring(min, max, radius, self.thickness, self.color));
+                vertices
+            }
+        }
+    }
+
+    pub fn hit_test(&self, point: Vec2, tolerance: f32) -> bool {
+        let distance = self.signed_distance(point);
+        if self.style.has_fill() {
+            let outline = if self.style.has_outline() { self.thickness } else { 0.0 };
+            distance <= outline + tolerance
+        } else {
+            distance.abs() <= self.thickness + tolerance
+        }
+    }
+
+    /// Distance from `point` to the edge, negative inside.
+    fn signed_distance(&self, point: Vec2) -> f32 {
+        let radius = self.radius();
+        let center = (self.min() + self.max()) / 2.0;
+        let half_size = (self.max() - self.min()) / 2.0;
+        let q = (point - center).abs() - half_size + radius;
+        q.max(Vec2::ZERO).length() + q.max_element().min(0.0) - radius
+    }
+}
+
 #[derive(Debug)]
 pub struct Rectangle {
     start: Option<Vec2>,
     end: Option<Vec2>,
+    modifiers: Modifiers,
+    style: RectangleStyle,
+    thickness: f32,
+    corner_radius: f32,
     color: [f32; 4],
+    fill_color: [f32; 4],
+    blend_mode: BlendMode,
 }
 
 impl Rectangle {
-    pub fn new(color: [f32; 4]) -> Self {
+    pub fn new(thickness: f32, color: [f32; 4], fill_color: [f32; 4], style: RectangleStyle) -> Self {
         Self {
             start: None,
             end: None,
+            modifiers: Modifiers::default(),
+            style,
+            thickness,
+            corner_radius: 0.0,
             color,
+            fill_color,
+            blend_mode: BlendMode::Normal,
         }
     }
 
     pub fn set_color(&mut self, color: [f32; 4]) {
         self.color = color;
     }
+
+    pub fn set_fill_color(&mut self, color: [f32; 4]) {
+        self.fill_color = color;
+    }
+
+    pub fn set_style(&mut self, style: RectangleStyle) {
+        self.style = style;
+    }
+
+    pub fn set_corner_radius(&mut self, radius: f32) {
+        self.corner_radius = radius;
+    }
+
+    fn current_stroke(&self) -> Option<RectangleStroke> {
+        let (start, end) = self.start.zip(self.end)?;
+        let (center, half_size) = drag_box(start, end, self.modifiers);
+        Some(RectangleStroke {
+            start: center - half_size,
+            end: center + half_size,
+            style: self.style,
+            thickness: self.thickness,
+            corner_radius: self.corner_radius,
+            color: self.color,
+            fill_color: self.fill_color,
+            blend_mode: self.blend_mode,
+        })
+    }
 }
 
 impl Tool for Rectangle {
-    fn on_mouse_down(&mut self, position: Vec2) {
-        self.start = Some(position);
-        self.end = Some(position);
+    fn on_mouse_down(&mut self, sample: InputSample) {
+        self.start = Some(sample.position);
+        self.end = Some(sample.position);
+        self.modifiers = sample.modifiers;
     }
 
-    fn on_mouse_move(&mut self, position: Vec2) -> Vec<Vertex> {
-        self.end = Some(position);
+    fn on_mouse_move(&mut self, sample: InputSample) -> Vec<Vertex> {
+        self.end = Some(sample.position);
+        self.modifiers = sample.modifiers;
         self.get_vertices()
     }
 
-    fn on_mouse_up(&mut self, position: Vec2) -> Vec<Vertex> {
-        self.end = Some(position);
-        let vertices = self.get_vertices();
+    fn on_mouse_up(&mut self, sample: InputSample) -> Option<Stroke> {
+        self.end = Some(sample.position);
+        self.modifiers = sample.modifiers;
+        let stroke = self.current_stroke();
         self.start = None;
         self.end = None;
-        vertices
+        // A click without a drag has nothing to show.
+        stroke.filter(|s| s.start != s.end).map(Stroke::Rectangle)
     }
 
     fn get_vertices(&self) -> Vec<Vertex> {
-        match (self.start, self.end) {
-            (Some(start), Some(end)) => {
-                let min_x = start.x.min(end.x);
-                let max_x = start.x.max(end.x);
-                let min_y = start.y.min(end.y);
-                let max_y = start.y.max(end.y);
-
-                vec![
-                    // First triangle
-                    Vertex {
-                        position: [min_x, min_y],
-                        color: self.color,
-                    },
-                    Vertex {
-                        position: [max_x, min_y],
-                        color: self.color,
-                    },
-                    Vertex {
-                        position: [max_x, max_y],
-                        color: self.color,
-                    },
-                    // Second triangle
-                    Vertex {
-                        position: [min_x, min_y],
-                        color: self.color,
-                    },
-                    Vertex {
-                        position: [max_x, max_y],
-                        color: self.color,
-                    },
-                    Vertex {
-                        position: [min_x, max_y],
-                        color: self.color,
-                    },
-                ]
-            }
-            _ => Vec::new(),
-        }
+        self.current_stroke()
+            .map(|stroke| stroke.tessellate())
+            .unwrap_or_default()
     }
 
     fn set_color(&mut self, color: [f32; 4]) {
         self.color = color;
     }
 
-    fn set_size(&mut self, _size: f32) {
-        // Rectangle doesn't use size
+    fn set_size(&mut self, size: f32) {
+        self.thickness = size;
+    }
+
+    fn stroke_op(&self) -> StrokeOp {
+        StrokeOp::Paint(self.blend_mode)
+    }
+
+    fn set_blend_mode(&mut self, blend_mode: BlendMode) {
+        self.blend_mode = blend_mode;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OUTLINE: [f32; 4] = [0.0, 0.0, 0.0, 1.0];
+    const FILL: [f32; 4] = [1.0, 0.0, 0.0, 1.0];
+
+    fn drag(rectangle: &mut Rectangle, from: Vec2, to: Vec2, shift: bool, alt: bool) -> Option<RectangleStroke> {
+        let modifiers = Modifiers { shift, alt };
+        rectangle.on_mouse_down(InputSample { modifiers, ..InputSample::new(from) });
+        rectangle.on_mouse_move(InputSample { modifiers, ..InputSample::new((from + to) / 2.0) });
+        match rectangle.on_mouse_up(InputSample { modifiers, ..InputSample::new(to) })? {
+            Stroke::Rectangle(stroke) => Some(stroke),
+            other => panic!("expected a rectangle, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn drags_out_the_rectangle_between_corners() {
+        let mut rectangle = Rectangle::new(0.01, OUTLINE, FILL, RectangleStyle::Fill);
+        let stroke = drag(&mut rectangle, Vec2::new(0.5, 0.0), Vec2::new(0.0, 0.25), false, false).unwrap();
+        assert_eq!((stroke.min(), stroke.max()), (Vec2::ZERO, Vec2::new(0.5, 0.25)));
+        assert_eq!((stroke.color, stroke.fill_color), (OUTLINE, FILL));
+
+        // A click without a drag draws nothing.
+        assert_eq!(drag(&mut rectangle, Vec2::ZERO, Vec2::ZERO, false, false), None);
+    }
+
+    #[test]
+    fn shift_draws_squares_and_alt_draws_from_the_center() {
+        let mut rectangle = Rectangle::new(0.01, OUTLINE, FILL, RectangleStyle::Fill);
+        let start = Vec2::new(0.25, 0.25);
+        let end = Vec2::new(0.75, 0.5);
+
+        let square = drag(&mut rectangle, start, end, true, false).unwrap();
+        assert_eq!((square.min(), square.max()), (start, Vec2::splat(0.75)));
+
+        let centered = drag(&mut rectangle, start, end, false, true).unwrap();
+        assert_eq!((centered.min(), centered.max()), (Vec2::new(-0.25, 0.0), Vec2::new(0.75, 0.5)));
+
+        let both = drag(&mut rectangle, start, end, true, true).unwrap();
+        assert_eq!((both.min(), both.max()), (Vec2::splat(-0.25), Vec2::splat(0.75)));
     }
-} 
\ No newline at end of file
+
+    #[test]
+    fn fills_outlines_or_both() {
+        let mut rectangle = Rectangle::new(0.01, OUTLINE, FILL, RectangleStyle::Fill);
+        let mut stroke = |style| {
+            rectangle.set_style(style);
+            drag(&mut rectangle, Vec2::splat(-0.5), Vec2::splat(0.5), false, false).unwrap()
+        };
+        let (fill, outline, both) = (
+            stroke(RectangleStyle::Fill),
+            stroke(RectangleStyle::Outline),
+            stroke(RectangleStyle::FillAndOutline),
+        );
+        assert!(fill.hit_test(Vec2::ZERO, 0.0) && both.hit_test(Vec2::ZERO, 0.0));
+        assert!(!outline.hit_test(Vec2::ZERO, 0.0));
+
+        // The outline is centered on the edge.
+        let outside = Vec2::new(0.505, 0.0);
+        assert!(outline.hit_test(outside, 0.0) && both.hit_test(outside, 0.0));
+        assert!(!fill.hit_test(outside, 0.0));
+    }
+
+    #[test]
+    fn rounds_corners_up_to_half_the_shorter_side() {
+        let mut rectangle = Rectangle::new(0.01, OUTLINE, FILL, RectangleStyle::Fill);
+        let corner = Vec2::new(0.49, 0.24);
+        let sharp = drag(&mut rectangle, Vec2::new(-0.5, -0.25), Vec2::new(0.5, 0.25), false, false).unwrap();
+        assert_eq!(sharp.radius(), 0.0);
+        assert!(sharp.hit_test(corner, 0.0));
+
+        rectangle.set_corner_radius(0.1);
+        let rounded = drag(&mut rectangle, Vec2::new(-0.5, -0.25), Vec2::new(0.5, 0.25), false, false).unwrap();
+        assert_eq!(rounded.radius(), 0.1);
+        assert!(!rounded.hit_test(corner, 0.0));
+        assert!(rounded.hit_test(Vec2::new(0.49, 0.0), 0.0));
+
+        rectangle.set_corner_radius(1.0);
+        let pill = drag(&mut rectangle, Vec2::new(-0.5, -0.25), Vec2::new(0.5, 0.25), false, false).unwrap();
+        assert_eq!(pill.radius(), 0.25);
+    }
+}
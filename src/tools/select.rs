@@ -0,0 +1,100 @@
+use super::{InputSample, Tool, ToolKey};
+use crate::document::Stroke;
+use crate::engine::{BlendMode, StrokeOp, Vertex};
+use crate::tessellation::{self, LineCap, LineJoin};
+use glam::Vec2;
+
+/// Half the width of the selection outline.
+const OUTLINE_WIDTH: f32 = 0.002;
+
+/// Length of the outline's dashes and of the gaps between them.
+const OUTLINE_DASH: f32 = 0.015;
+
+const OUTLINE_COLOR: [f32; 4] = [0.1, 0.45, 0.95, 1.0];
+
+/// Drags out a rectangular selection for commands like crop to act on. The
+/// selection stays until the next drag; a click or Escape clears it.
+#[derive(Debug, Default)]
+pub struct Select {
+    start: Option<Vec2>,
+    /// Minimum and maximum corner of the selected area.
+    selection: Option<(Vec2, Vec2)>,
+}
+
+impl Select {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn selection(&self) -> Option<(Vec2, Vec2)> {
+        self.selection
+    }
+
+    pub fn clear(&mut self) {
+        self.start = None;
+        self.selection = None;
+    }
+}
+
+impl Tool for Select {
+    fn on_mouse_down(&mut self, sample: InputSample) {
+        self.start = Some(sample.position);
+        self.selection = None;
+    }
+
+    fn on_mouse_move(&mut self, sample: InputSample) -> Vec<Vertex> {
+        if let Some(start) = self.start {
+            let end = sample.position;
+            self.selection = Some((start.min(end), start.max(end)));
+        }
+        self.get_vertices()
+    }
+
+    fn on_mouse_up(&mut self, sample: InputSample) -> Option<Stroke> {
+        self.on_mouse_move(sample);
+        self.start = None;
+        // A click selects nothing.
+        if self.selection.is_some_and(|(min, max)| (max - min).min_element() <= 0.0) {
+            self.selection = None;
+        }
+        None
+    }
+
+    fn get_vertices(&self) -> Vec<Vertex> {
+        let Some((min, max)) = self.selection else {
+            return Vec::new();
+        };
+        let outline = [min, Vec2::new(max.x, min.y), max, Vec2::new(min.x, max.y), min];
+        tessellation::dash_polyline(&outline, OUTLINE_DASH, OUTLINE_DASH)
+            .iter()
+            .flat_map(|dash| tessellation::stroke_polyline(dash, OUTLINE_WIDTH, LineCap::Butt, LineJoin::Miter, OUTLINE_COLOR))
+            .collect()
+    }
+
+    fn set_color(&mut self, _color: [f32; 4]) {
+        // Selections have no color
+    }
+
+    fn set_size(&mut self, _size: f32) {
+        // Selections have no size
+    }
+
+    fn stroke_op(&self) -> StrokeOp {
+        StrokeOp::Paint(BlendMode::Normal)
+    }
+
+    fn set_blend_mode(&mut self, _blend_mode: BlendMode) {
+        // Selections aren't drawn into the document
+    }
+
+    fn on_key(&mut self, key: ToolKey) -> Option<Stroke> {
+        if key == ToolKey::Escape {
+            self.clear();
+        }
+        None
+    }
+
+    fn in_progress(&self) -> bool {
+        self.selection.is_some()
+    }
+}
@@ -0,0 +1,288 @@
+use glam::Vec2;
+
+/// Largest moving average window, in samples on each side, at full strength.
+const MAX_AVERAGE_RADIUS: f32 = 12.0;
+
+/// Longest lazy mouse string, in canvas units, at full strength.
+const MAX_STRING_LENGTH: f32 = 0.1;
+
+/// Largest distance between Catmull-Rom control points, in canvas units, at
+/// full strength.
+const MAX_CONTROL_SPACING: f32 = 0.08;
+
+/// Strength a new stabilizer starts at.
+pub const DEFAULT_STRENGTH: f32 = 0.5;
+
+/// Points generated per Catmull-Rom segment.
+const CURVE_SUBDIVISIONS: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StabilizerKind {
+    /// Raw samples are used as they are.
+    #[default]
+    None,
+    /// Each point is averaged with its neighbours along the path.
+    MovingAverage,
+    /// The brush trails the cursor on a string and only moves when pulled.
+    LazyMouse,
+    /// The path is thinned out and a Catmull-Rom spline is fitted through
+    /// the remaining points.
+    CatmullRom,
+}
+
+impl StabilizerKind {
+    pub const ALL: [StabilizerKind; 4] = [
+        StabilizerKind::None,
+        StabilizerKind::MovingAverage,
+        StabilizerKind::LazyMouse,
+        StabilizerKind::CatmullRom,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            StabilizerKind::None => "None",
+            StabilizerKind::MovingAverage => "Moving average",
+            StabilizerKind::LazyMouse => "Lazy mouse",
+            StabilizerKind::CatmullRom => "Catmull-Rom",
+        }
+    }
+
+    pub fn from_index(index: u32) -> Option<Self> {
+        Self::ALL.get(index as usize).copied()
+    }
+}
+
+/// Smooths out hand jitter in freehand input. Works on the whole list of
+/// samples so the result is the same every time a stroke is tessellated.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Stabilizer {
+    pub kind: StabilizerKind,
+    /// From 0 (no smoothing) to 1 (strongest).
+    pub strength: f32,
+}
+
+impl Default for Stabilizer {
+    fn default() -> Self {
+        Self::new(StabilizerKind::None, DEFAULT_STRENGTH)
+    }
+}
+
+impl Stabilizer {
+    pub fn new(kind: StabilizerKind, strength: f32) -> Self {
+        Self { kind, strength }
+    }
+
+    /// Returns the smoothed path for the raw `points`.
+    pub fn apply(&self, points: &[Vec2]) -> Vec<Vec2> {
+        let strength = self.strength.clamp(0.0, 1.0);
+        if strength == 0.0 || points.len() < 2 {
+            return points.to_vec();
+        }
+
+        match self.kind {
+            StabilizerKind::None => points.to_vec(),
+            StabilizerKind::MovingAverage => {
+                moving_average(points, (strength * MAX_AVERAGE_RADIUS).round() as usize)
+            }
+            StabilizerKind::LazyMouse => lazy_mouse(points, strength * MAX_STRING_LENGTH),
+            StabilizerKind::CatmullRom => catmull_rom(points, strength * MAX_CONTROL_SPACING),
+        }
+    }
+}
+
+/// Averages each point with up to `radius` neighbours on either side. The
+/// window shrinks near the ends so it stays centred, which keeps the first
+/// and last points in place.
+fn moving_average(points: &[Vec2], radius: usize) -> Vec<Vec2> {
+    let last = points.len() - 1;
+    (0..points.len())
+        .map(|i| {
+            let reach = radius.min(i).min(last - i);
+            let window = &points[i - reach..=i + reach];
+            window.iter().sum::<Vec2>() / window.len() as f32
+        })
+        .collect()
+}
+
+/// Drags the brush behind the cursor on a string `length` long. Cursor
+/// movement within the string's reach leaves the brush where it is.
+fn lazy_mouse(points: &[Vec2], length: f32) -> Vec<Vec2> {
+    let mut brush = points[0];
+    let mut path = vec![brush];
+    for &cursor in &points[1..] {
+        let distance = brush.distance(cursor);
+        if distance > length {
+            brush = cursor + (brush - cursor) * (length / distance);
+            path.push(brush);
+        }
+    }
+    path
+}
+
+/// Averages the samples into control points roughly `spacing` apart (the
+/// ends are kept as they are) and interpolates a centripetal Catmull-Rom
+/// spline through them.
+fn catmull_rom(points: &[Vec2], spacing: f32) -> Vec<Vec2> {
+    let last = points[points.len() - 1];
+    let mut controls = vec![points[0]];
+    let mut sum = Vec2::ZERO;
+    let mut count = 0;
+    for &point in &points[1..points.len() - 1] {
+        sum += point;
+        count += 1;
+        if controls[controls.len() - 1].distance(point) >= spacing {
+            controls.push(sum / count as f32);
+            sum = Vec2::ZERO;
+            count = 0;
+        }
+    }
+    // Replace a too-close final control point rather than adding a kink.
+    if controls.len() > 1 && controls[controls.len() - 1].distance(last) < spacing / 2.0 {
+        controls.pop();
+    }
+    controls.push(last);
+
+    if controls.len() < 3 {
+        return controls;
+    }
+
+    let mut path = vec![controls[0]];
+    for i in 0..controls.len() - 1 {
+        // Mirror the ends so the curve starts and ends on the path.
+        let p0 = if i == 0 { controls[0] * 2.0 - controls[1] } else { controls[i - 1] };
+        let p1 = controls[i];
+        let p2 = controls[i + 1];
+        let p3 = controls.get(i + 2).copied().unwrap_or(p2 * 2.0 - p1);
+        for step in 1..=CURVE_SUBDIVISIONS {
+            path.push(centripetal_point(p0, p1, p2, p3, step as f32 / CURVE_SUBDIVISIONS as f32));
+        }
+    }
+    path
+}
+
+/// Point at `t` (0 to 1) between `p1` and `p2` on the centripetal
+/// Catmull-Rom curve, which unlike the uniform variant never loops or
+/// overshoots on uneven spacing.
+fn centripetal_point(p0: Vec2, p1: Vec2, p2: Vec2, p3: Vec2, t: f32) -> Vec2 {
+    let knot = |a: Vec2, b: Vec2| a.distance(b).sqrt().max(1e-6);
+    let t0 = 0.0;
+    let t1 = t0 + knot(p0, p1);
+    let t2 = t1 + knot(p1, p2);
+    let t3 = t2 + knot(p2, p3);
+    let t = t1 + (t2 - t1) * t;
+
+    let lerp = |a: Vec2, b: Vec2, ta: f32, tb: f32| a + (b - a) * ((t - ta) / (tb - ta));
+    let a1 = lerp(p0, p1, t0, t1);
+    let a2 = lerp(p1, p2, t1, t2);
+    let a3 = lerp(p2, p3, t2, t3);
+    let b1 = lerp(a1, a2, t0, t2);
+    let b2 = lerp(a2, a3, t1, t3);
+    lerp(b1, b2, t1, t2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A horizontal line from x = 0 to 1 with samples alternating `amplitude`
+    /// above and below it.
+    fn jittery_line(samples: usize, amplitude: f32) -> Vec<Vec2> {
+        (0..samples)
+            .map(|i| {
+                let x = i as f32 / (samples - 1) as f32;
+                let y = if i % 2 == 0 { amplitude } else { -amplitude };
+                Vec2::new(x, y)
+            })
+            .collect()
+    }
+
+    fn max_abs_y(points: &[Vec2]) -> f32 {
+        points.iter().map(|p| p.y.abs()).fold(0.0, f32::max)
+    }
+
+    #[test]
+    fn zero_strength_and_none_leave_points_unchanged() {
+        let points = jittery_line(20, 0.01);
+        for kind in StabilizerKind::ALL {
+            assert_eq!(Stabilizer::new(kind, 0.0).apply(&points), points);
+        }
+        assert_eq!(Stabilizer::new(StabilizerKind::None, 1.0).apply(&points), points);
+    }
+
+    #[test]
+    fn short_inputs_pass_through() {
+        for kind in StabilizerKind::ALL {
+            let stabilizer = Stabilizer::new(kind, 1.0);
+            assert!(stabilizer.apply(&[]).is_empty());
+            assert_eq!(stabilizer.apply(&[Vec2::ONE]), vec![Vec2::ONE]);
+        }
+    }
+
+    #[test]
+    fn moving_average_reduces_jitter_and_keeps_ends() {
+        let points = jittery_line(50, 0.01);
+        let smoothed = Stabilizer::new(StabilizerKind::MovingAverage, 0.5).apply(&points);
+
+        assert_eq!(smoothed.len(), points.len());
+        assert_eq!(smoothed[0], points[0]);
+        assert_eq!(smoothed[49], points[49]);
+        // Away from the ends the alternating offsets mostly cancel out.
+        assert!(max_abs_y(&smoothed[10..40]) < 0.002);
+    }
+
+    #[test]
+    fn moving_average_keeps_straight_lines_straight() {
+        let points: Vec<Vec2> = (0..10).map(|i| Vec2::new(i as f32 * 0.1, i as f32 * 0.05)).collect();
+        let smoothed = Stabilizer::new(StabilizerKind::MovingAverage, 1.0).apply(&points);
+        for (a, b) in smoothed.iter().zip(&points) {
+            assert!(a.distance(*b) < 1e-5, "{a} vs {b}");
+        }
+    }
+
+    #[test]
+    fn lazy_mouse_trails_by_string_length() {
+        let points: Vec<Vec2> = (0..=100).map(|i| Vec2::new(i as f32 * 0.01, 0.0)).collect();
+        let stabilizer = Stabilizer::new(StabilizerKind::LazyMouse, 0.5);
+        let path = stabilizer.apply(&points);
+
+        let length = 0.5 * MAX_STRING_LENGTH;
+        let end = *path.last().unwrap();
+        assert!((end.distance(Vec2::new(1.0, 0.0)) - length).abs() < 1e-4);
+        assert!(path.iter().all(|p| p.y == 0.0));
+        assert!(path.windows(2).all(|w| w[1].x > w[0].x));
+    }
+
+    #[test]
+    fn lazy_mouse_ignores_movement_within_reach() {
+        let points = jittery_line(30, 0.01);
+        // Squash the line so the cursor never leaves the string's reach.
+        let points: Vec<Vec2> = points.iter().map(|p| Vec2::new(p.x * 0.02, p.y)).collect();
+        let path = Stabilizer::new(StabilizerKind::LazyMouse, 1.0).apply(&points);
+        assert_eq!(path, vec![points[0]]);
+    }
+
+    #[test]
+    fn catmull_rom_keeps_ends_and_smooths_jitter() {
+        let points = jittery_line(200, 0.005);
+        let path = Stabilizer::new(StabilizerKind::CatmullRom, 0.5).apply(&points);
+
+        assert_eq!(path[0], points[0]);
+        assert!(path.last().unwrap().distance(points[199]) < 1e-5);
+        // Away from the ends the control points average the jitter out.
+        let middle: Vec<Vec2> = path.iter().copied().filter(|p| p.x > 0.1 && p.x < 0.9).collect();
+        assert!(max_abs_y(&middle) < 0.001, "{}", max_abs_y(&middle));
+        assert!(path.windows(2).all(|w| w[1].x >= w[0].x - 1e-5));
+    }
+
+    #[test]
+    fn catmull_rom_follows_a_circle() {
+        let points: Vec<Vec2> = (0..=90)
+            .map(|i| Vec2::from_angle((i as f32).to_radians()) * 0.5)
+            .collect();
+        let path = Stabilizer::new(StabilizerKind::CatmullRom, 1.0).apply(&points);
+        assert!(path.len() > 3);
+        for p in &path {
+            assert!((p.length() - 0.5).abs() < 0.002, "{p} is off the circle");
+        }
+    }
+}